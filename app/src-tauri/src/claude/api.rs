@@ -0,0 +1,1172 @@
+//! Direct Anthropic Messages API backend
+//!
+//! For machines without the Claude CLI. Each turn is POSTed to
+//! `/v1/messages` with `stream: true`, and the server-sent events are
+//! translated into the same `ParsedOutput` stream the CLI produces, so
+//! sessions and the frontend can't tell the backends apart. The API runs
+//! no tools: MCP servers, permission modes and tool rules only apply to
+//! the CLI.
+//!
+//! Each finished turn is appended to the conversation's transcript in the
+//! CLI's format, so API conversations are listed, searched, exported and
+//! resumed (on either backend) like the CLI's own. The history sent with a
+//! turn is the text of that transcript.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::time::Interval;
+
+use super::backend::{AssistantBackend, BackendFactory, BoxFuture};
+use super::error::{ClaudeError, Result};
+use super::options::{api_model_id, is_model_id};
+use super::pty::{
+    KillHandle, ParsedOutput, ProcessConfig, ProcessExit, SessionArg, TurnUsage, Usage, Watchdog,
+    WATCHDOG_TICK,
+};
+use super::sessions::SessionManager;
+use super::store::ClaudeStore;
+use super::transcript::{
+    append_entries, copy_transcript, last_uuid, load_messages, HistoryContent, HistoryMessage,
+};
+
+/// Production endpoint
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
+/// Model used for the `default`/`sonnet` aliases unless configured otherwise
+const DEFAULT_MODEL: &str = "claude-sonnet-4-5";
+
+/// `anthropic-version` header sent with every request
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+const DEFAULT_MAX_TOKENS: u32 = 8192;
+
+/// Upper bound for `max_tokens`
+const MAX_TOKENS_LIMIT: u32 = 64_000;
+
+/// Time allowed to establish the HTTP connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Key of the settings row in `claude_settings`
+const SETTINGS_KEY: &str = "api";
+
+/// USD per million input and output tokens, by model id prefix (more
+/// specific prefixes first). Cache writes cost 1.25 times the input price
+/// and cache reads a tenth of it.
+const MODEL_PRICES: &[(&str, f64, f64)] = &[
+    ("claude-opus-4-5", 5.0, 25.0),
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-haiku-4-5", 1.0, 5.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-haiku", 0.25, 1.25),
+];
+
+/// Which backend new turns run on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendPreference {
+    /// The CLI when it is installed, otherwise the API if a key is saved
+    #[default]
+    Auto,
+    Cli,
+    Api,
+}
+
+/// User-editable API settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiSettings {
+    pub backend: BackendPreference,
+    /// Endpoint root, e.g. a proxy or a local mock server
+    pub base_url: String,
+    /// Model id used for the `default` and `sonnet` aliases
+    pub model: String,
+    pub max_tokens: u32,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            backend: BackendPreference::default(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+}
+
+impl ApiSettings {
+    /// Check every setting, returning the first problem found
+    pub fn validate(&self) -> Result<()> {
+        let url = reqwest::Url::parse(&self.base_url)
+            .map_err(|e| ClaudeError::InvalidOption(format!("Invalid base URL: {}", e)))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ClaudeError::InvalidOption(
+                "Base URL must use http or https".to_string(),
+            ));
+        }
+        if !is_model_id(&self.model) {
+            return Err(ClaudeError::InvalidOption(format!("Unknown model: {}", self.model)));
+        }
+        if self.max_tokens == 0 || self.max_tokens > MAX_TOKENS_LIMIT {
+            return Err(ClaudeError::InvalidOption(format!(
+                "max_tokens must be between 1 and {}",
+                MAX_TOKENS_LIMIT
+            )));
+        }
+        Ok(())
+    }
+
+    fn messages_url(&self) -> String {
+        format!("{}/v1/messages", self.base_url.trim_end_matches('/'))
+    }
+
+    /// Model id for a session's `--model` option
+    fn resolve_model(&self, model: Option<&str>) -> String {
+        api_model_id(model, &self.model)
+    }
+}
+
+/// Settings as shown in the UI; the key itself is never sent back
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiStatus {
+    #[serde(flatten)]
+    pub settings: ApiSettings,
+    pub has_key: bool,
+}
+
+/// API key kept in a file next to the app database, readable only by the
+/// current user
+pub struct ApiKeyStore {
+    path: PathBuf,
+}
+
+impl ApiKeyStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The saved key, if any
+    pub fn load(&self) -> Option<String> {
+        let key = std::fs::read_to_string(&self.path).ok()?;
+        let key = key.trim();
+        (!key.is_empty()).then(|| key.to_string())
+    }
+
+    pub fn save(&self, key: &str) -> Result<()> {
+        let key = key.trim();
+        if key.is_empty() || key.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(ClaudeError::InvalidOption("API key is malformed".to_string()));
+        }
+        write_private(&self.path, key)?;
+        Ok(())
+    }
+
+    /// Forget the saved key
+    pub fn clear(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // `mode` only applies when the file is created
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+/// Creates Messages API turns
+pub struct ApiBackendFactory {
+    store: Arc<ClaudeStore>,
+    keys: ApiKeyStore,
+    client: reqwest::Client,
+    /// Where conversation transcripts are read and written
+    transcripts: SessionManager,
+}
+
+impl ApiBackendFactory {
+    pub fn new(store: Arc<ClaudeStore>, key_path: impl Into<PathBuf>) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            store,
+            keys: ApiKeyStore::new(key_path),
+            client,
+            transcripts: SessionManager::new(),
+        }
+    }
+
+    /// Saved settings, or the defaults if none were saved
+    pub fn settings(&self) -> ApiSettings {
+        self.store.get_setting(SETTINGS_KEY).unwrap_or_else(|e| {
+            eprintln!("[Claude] Failed to load API settings: {}", e);
+            None
+        })
+        .unwrap_or_default()
+    }
+
+    pub fn save_settings(&self, settings: &ApiSettings) -> Result<()> {
+        settings.validate()?;
+        self.store.set_setting(SETTINGS_KEY, settings)
+    }
+
+    pub fn keys(&self) -> &ApiKeyStore {
+        &self.keys
+    }
+
+    pub fn status(&self) -> ApiStatus {
+        ApiStatus {
+            settings: self.settings(),
+            has_key: self.is_available(),
+        }
+    }
+}
+
+impl BackendFactory for ApiBackendFactory {
+    fn is_available(&self) -> bool {
+        self.keys.load().is_some()
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.is_available() {
+            Ok(())
+        } else {
+            Err(ClaudeError::ApiKeyMissing)
+        }
+    }
+
+    fn create(&self, config: &ProcessConfig) -> Box<dyn AssistantBackend> {
+        Box::new(ApiBackend {
+            config: config.clone(),
+            settings: self.settings(),
+            key: self.keys.load(),
+            client: self.client.clone(),
+            transcripts: self.transcripts.clone(),
+            kill_handle: KillHandle::default(),
+        })
+    }
+}
+
+/// One streamed Messages API turn
+pub struct ApiBackend {
+    config: ProcessConfig,
+    settings: ApiSettings,
+    key: Option<String>,
+    client: reqwest::Client,
+    transcripts: SessionManager,
+    kill_handle: KillHandle,
+}
+
+impl ApiBackend {
+    /// Transcript file of a conversation in the session's project
+    fn transcript_path(&self, id: &str) -> Result<PathBuf> {
+        let mut transcripts = self.transcripts.clone();
+        transcripts.set_project_dir(&self.config.working_dir);
+        transcripts
+            .transcript_path(id)
+            .ok_or_else(|| ClaudeError::InvalidOption(format!("Invalid conversation id: {}", id)))
+    }
+
+    async fn run(
+        &mut self,
+        message: &str,
+        session: &SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<Option<String>> {
+        self.config.check_prompt_size(message)?;
+        let key = self.key.clone().ok_or(ClaudeError::ApiKeyMissing)?;
+        let (conversation_id, parent) = match session {
+            SessionArg::New(id) | SessionArg::Resume(id) => (id.clone(), None),
+            // A fork continues from a copy of its parent's history
+            SessionArg::Fork(parent) => (
+                uuid::Uuid::new_v4().to_string(),
+                Some(self.transcript_path(parent)?),
+            ),
+        };
+        let path = self.transcript_path(&conversation_id)?;
+
+        let started = Instant::now();
+        let history_path = parent.clone().unwrap_or_else(|| path.clone());
+        let mut messages = tokio::task::spawn_blocking(move || load_history(&history_path))
+            .await
+            .map_err(std::io::Error::other)??;
+        messages.push(json!({ "role": "user", "content": message }));
+
+        let model = self.settings.resolve_model(self.config.options.model.as_deref());
+        let mut body = json!({
+            "model": model,
+            "max_tokens": self.settings.max_tokens,
+            "stream": true,
+            "messages": messages,
+        });
+        let system: Vec<&str> = [
+            self.config.system_prompt.as_deref(),
+            self.config.options.append_system_prompt.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !system.is_empty() {
+            body["system"] = json!(system.join("\n\n"));
+        }
+
+        let _ = output_tx.send(ParsedOutput::SessionId(conversation_id.clone())).await;
+
+        let request = self
+            .client
+            .post(self.settings.messages_url())
+            .header("x-api-key", key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&body);
+        let mut guard = TurnGuard::new(&self.kill_handle, &self.config);
+        let mut turn = TurnState::default();
+        let api_started = Instant::now();
+        let finished = stream_response(request, &mut guard, &mut turn, &output_tx).await?;
+        let duration_api_ms = api_started.elapsed().as_millis() as u64;
+        // The response names the exact model that answered
+        let model = turn.model.clone().unwrap_or(model);
+        if finished {
+            let total_cost_usd = turn_cost(&model, &turn.usage).unwrap_or_else(|| {
+                eprintln!("[Claude] No price known for {}; recording no cost", model);
+                0.0
+            });
+            let _ = output_tx
+                .send(ParsedOutput::Usage(TurnUsage {
+                    usage: turn.usage.clone(),
+                    total_cost_usd,
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    duration_api_ms,
+                    num_turns: 1,
+                    ..TurnUsage::default()
+                }))
+                .await;
+        } else {
+            // Like the CLI, keep the prompt and the text received so far
+            eprintln!("[Claude] Message cancelled");
+        }
+
+        let record = TurnRecord {
+            path,
+            parent,
+            conversation_id: conversation_id.clone(),
+            working_dir: self.config.working_dir.clone(),
+            prompt: message.to_string(),
+            model,
+            turn,
+        };
+        tokio::task::spawn_blocking(move || record.write())
+            .await
+            .map_err(std::io::Error::other)??;
+        Ok(Some(conversation_id))
+    }
+}
+
+impl AssistantBackend for ApiBackend {
+    fn send_message<'a>(
+        &'a mut self,
+        message: &'a str,
+        session: &'a SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(self.run(message, session, output_tx))
+    }
+
+    fn kill_handle(&self) -> KillHandle {
+        self.kill_handle.clone()
+    }
+
+    fn last_exit(&self) -> Option<&ProcessExit> {
+        None
+    }
+}
+
+/// Earlier turns of a conversation, from its transcript
+fn load_history(path: &Path) -> Result<Vec<Value>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(transcript_history(&load_messages(path)?))
+}
+
+/// A finished or cancelled turn, to be appended to its conversation's
+/// transcript
+struct TurnRecord {
+    path: PathBuf,
+    /// Transcript a fork continues from
+    parent: Option<PathBuf>,
+    conversation_id: String,
+    working_dir: String,
+    prompt: String,
+    model: String,
+    turn: TurnState,
+}
+
+impl TurnRecord {
+    /// Append the turn in the CLI's format, starting a fork's transcript as
+    /// a copy of its parent's
+    fn write(self) -> Result<()> {
+        let path = &self.path;
+        if let Some(parent) = self.parent.as_ref().filter(|p| p.exists() && !path.exists()) {
+            copy_transcript(parent, path, &self.conversation_id)?;
+        }
+        let parent_uuid = if path.exists() { last_uuid(path)? } else { None };
+        let entry = |kind: &str, uuid: &str, parent_uuid: Option<&str>, message: Value| {
+            json!({
+                "type": kind,
+                "uuid": uuid,
+                "parentUuid": parent_uuid,
+                "sessionId": self.conversation_id,
+                "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                "cwd": self.working_dir,
+                "isSidechain": false,
+                "userType": "external",
+                "message": message,
+            })
+        };
+
+        let prompt_uuid = uuid::Uuid::new_v4().to_string();
+        let mut entries = vec![entry(
+            "user",
+            &prompt_uuid,
+            parent_uuid.as_deref(),
+            json!({ "role": "user", "content": self.prompt }),
+        )];
+        if !self.turn.reply.is_empty() {
+            entries.push(entry(
+                "assistant",
+                &uuid::Uuid::new_v4().to_string(),
+                Some(&prompt_uuid),
+                json!({
+                    "id": self.turn.message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.model,
+                    "content": [{ "type": "text", "text": self.turn.reply }],
+                    "usage": self.turn.usage,
+                }),
+            ));
+        }
+        append_entries(path, &entries)
+    }
+}
+
+/// The text of a transcript's messages as API turns; tool calls and
+/// thinking are left out since the API runs no tools
+fn transcript_history(messages: &[HistoryMessage]) -> Vec<Value> {
+    let mut history = Vec::new();
+    for message in messages {
+        let (role, content) = match &message.content {
+            HistoryContent::User { content } => ("user", content),
+            HistoryContent::Assistant { content } => ("assistant", content),
+            _ => continue,
+        };
+        // The first turn must be the user's
+        if history.is_empty() && role == "assistant" {
+            continue;
+        }
+        history.push(json!({ "role": role, "content": content }));
+    }
+    history
+}
+
+/// Cost in USD of a response from `model`, if its price is known
+fn turn_cost(model: &str, usage: &Usage) -> Option<f64> {
+    let (_, input, output) = MODEL_PRICES
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))?;
+    let input_tokens = usage.input_tokens as f64
+        + usage.cache_creation_input_tokens as f64 * 1.25
+        + usage.cache_read_input_tokens as f64 * 0.1;
+    Some((input_tokens * input + usage.output_tokens as f64 * output) / 1_000_000.0)
+}
+
+/// Applies cancellation and the session's time limits to each await of a
+/// turn
+struct TurnGuard<'a> {
+    kill_handle: &'a KillHandle,
+    watchdog: Watchdog,
+    tick: Interval,
+}
+
+impl<'a> TurnGuard<'a> {
+    fn new(kill_handle: &'a KillHandle, config: &ProcessConfig) -> Self {
+        let mut watchdog = Watchdog::new(config.timeouts());
+        watchdog.start();
+        Self {
+            kill_handle,
+            watchdog,
+            tick: tokio::time::interval(WATCHDOG_TICK),
+        }
+    }
+
+    /// Await `future`, or `None` if the turn is cancelled first
+    async fn run<T>(&mut self, future: impl Future<Output = T>) -> Result<Option<T>> {
+        tokio::pin!(future);
+        loop {
+            tokio::select! {
+                value = &mut future => {
+                    self.watchdog.touch();
+                    return Ok(Some(value));
+                }
+                _ = self.kill_handle.cancelled() => return Ok(None),
+                _ = self.tick.tick() => {
+                    if let Some(e) = self.watchdog.expired() {
+                        eprintln!("[Claude] {}", e);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Send the request and forward the streamed response into `turn`
+///
+/// Returns whether the response finished; `false` if the turn was
+/// cancelled, leaving what arrived until then in `turn`.
+async fn stream_response(
+    request: reqwest::RequestBuilder,
+    guard: &mut TurnGuard<'_>,
+    turn: &mut TurnState,
+    output_tx: &mpsc::Sender<ParsedOutput>,
+) -> Result<bool> {
+    let Some(response) = guard.run(request.send()).await? else {
+        return Ok(false);
+    };
+    let mut response = response.map_err(|e| ClaudeError::ApiError(e.to_string()))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let Some(body) = guard.run(response.text()).await? else {
+            return Ok(false);
+        };
+        return Err(classify_api_error(Some(status.as_u16()), &body.unwrap_or_default()));
+    }
+
+    let mut parser = SseParser::default();
+    loop {
+        let Some(chunk) = guard.run(response.chunk()).await? else {
+            return Ok(false);
+        };
+        let chunk = chunk
+            .map_err(|e| ClaudeError::ApiError(format!("Connection lost: {}", e)))?
+            .ok_or_else(|| ClaudeError::ApiError("Response ended unexpectedly".to_string()))?;
+
+        for data in parser.push(&chunk) {
+            if turn.handle(&data, output_tx).await? {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// A Messages API stream event (the `data` of one SSE event)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MessageEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    ContentBlockDelta {
+        delta: ContentDelta,
+    },
+    MessageDelta {
+        #[serde(default)]
+        usage: Option<Usage>,
+    },
+    MessageStop,
+    Error {
+        error: ApiErrorBody,
+    },
+    /// `ping`, block start/stop and anything added later
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    usage: Usage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentDelta {
+    TextDelta {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ApiErrorBody {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorEnvelope {
+    error: ApiErrorBody,
+}
+
+/// Reply text and usage accumulated over one response
+#[derive(Debug, Default)]
+struct TurnState {
+    reply: String,
+    usage: Usage,
+    /// Messages API id of the response
+    message_id: Option<String>,
+    /// Model that produced the response
+    model: Option<String>,
+}
+
+impl TurnState {
+    /// Handle one event; returns `true` at `message_stop`
+    async fn handle(&mut self, data: &str, output_tx: &mpsc::Sender<ParsedOutput>) -> Result<bool> {
+        let event: MessageEvent = match serde_json::from_str(data) {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("[Claude] Unexpected API event ({}): {}", e, data);
+                return Ok(false);
+            }
+        };
+        match event {
+            MessageEvent::MessageStart { message } => {
+                self.message_id = message.id;
+                self.model = message.model;
+                self.usage = message.usage;
+            }
+            MessageEvent::ContentBlockDelta {
+                delta: ContentDelta::TextDelta { text },
+            } => {
+                self.reply.push_str(&text);
+                let _ = output_tx
+                    .send(ParsedOutput::Text {
+                        text,
+                        parent_tool_use_id: None,
+                    })
+                    .await;
+            }
+            MessageEvent::MessageDelta { usage: Some(usage) } => {
+                // Counts here are cumulative for the message
+                self.usage.output_tokens = usage.output_tokens;
+            }
+            MessageEvent::MessageStop => return Ok(true),
+            MessageEvent::Error { error } => return Err(error.into_claude_error(None)),
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+impl ApiErrorBody {
+    fn into_claude_error(self, status: Option<u16>) -> ClaudeError {
+        match (status, self.kind.as_str()) {
+            (Some(401), _) | (_, "authentication_error") => ClaudeError::ApiKeyRejected,
+            (Some(429), _) | (_, "rate_limit_error") => ClaudeError::RateLimited(self.message),
+            (Some(529), _) | (_, "overloaded_error") => ClaudeError::Overloaded,
+            _ if !self.message.is_empty() => ClaudeError::ApiError(self.message),
+            (Some(status), _) => ClaudeError::ApiError(format!("HTTP {}", status)),
+            (None, kind) => ClaudeError::ApiError(kind.to_string()),
+        }
+    }
+}
+
+/// Map an error response to a `ClaudeError`
+fn classify_api_error(status: Option<u16>, body: &str) -> ClaudeError {
+    serde_json::from_str::<ApiErrorEnvelope>(body)
+        .map(|envelope| envelope.error)
+        .unwrap_or_default()
+        .into_claude_error(status)
+}
+
+/// Incremental `text/event-stream` parser
+///
+/// Chunks may split lines (and UTF-8 sequences) anywhere; complete events
+/// are returned as their joined `data` payload.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(std::mem::take(&mut self.data).join("\n"));
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data.push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+            // `event:`, `id:`, `retry:` and `:` comments carry nothing we use
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use crate::claude::transcript::token_usage;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use super::super::testutil::TempDir;
+
+    #[test]
+    fn test_sse_parser_handles_split_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: ping\r\ndata: {\"a\"").is_empty());
+        assert_eq!(parser.push(b":1}\r\n\r\n: comment\n"), vec![r#"{"a":1}"#]);
+        let euro = "data: \u{20ac}\ndata: x\n\n".as_bytes();
+        assert!(parser.push(&euro[..7]).is_empty());
+        assert_eq!(parser.push(&euro[7..]), vec!["\u{20ac}\nx"]);
+    }
+
+    #[test]
+    fn test_turn_cost() {
+        let usage = Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            cache_creation_input_tokens: 1_000_000,
+            cache_read_input_tokens: 1_000_000,
+        };
+        let cost = turn_cost("claude-opus-4-1-20250805", &usage).unwrap();
+        assert!((cost - (15.0 + 75.0 + 18.75 + 1.5)).abs() < 1e-9);
+        assert_eq!(turn_cost("claude-opus-4-5", &Usage::default()), Some(0.0));
+        assert!(turn_cost("claude-future-1", &usage).is_none());
+    }
+
+    #[test]
+    fn test_settings_validation_and_models() {
+        let settings = ApiSettings::default();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.messages_url(), "https://api.anthropic.com/v1/messages");
+        assert_eq!(settings.resolve_model(None), DEFAULT_MODEL);
+        assert_eq!(settings.resolve_model(Some("haiku")), "claude-haiku-4-5");
+        assert_eq!(
+            settings.resolve_model(Some("claude-sonnet-4-5[1m]")),
+            "claude-sonnet-4-5"
+        );
+
+        let invalid = [
+            ApiSettings { base_url: "file:///etc".to_string(), ..ApiSettings::default() },
+            ApiSettings { model: "gpt-4".to_string(), ..ApiSettings::default() },
+            ApiSettings { max_tokens: 0, ..ApiSettings::default() },
+        ];
+        for settings in invalid {
+            assert!(settings.validate().is_err(), "{:?}", settings);
+        }
+    }
+
+    /// Read one request, checking its headers, and return its JSON body
+    async fn read_request(socket: &mut TcpStream) -> Value {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        let (head_len, content_length) = loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&request[..pos]).to_lowercase();
+                assert!(head.contains("x-api-key: sk-test"));
+                assert!(head.contains("anthropic-version: 2023-06-01"));
+                let length = head
+                    .lines()
+                    .find_map(|l| l.strip_prefix("content-length:"))
+                    .map_or(0, |v| v.trim().parse().unwrap());
+                break (pos + 4, length);
+            }
+        };
+        while request.len() < head_len + content_length {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        serde_json::from_slice(&request[head_len..]).unwrap()
+    }
+
+    /// Serve one canned HTTP response per connection, recording request bodies
+    async fn mock_server(responses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                recorded.lock().push(request);
+
+                let content_type = if status == 200 { "text/event-stream" } else { "application/json" };
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    content_type,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base_url, requests)
+    }
+
+    fn sse(events: &[Value]) -> String {
+        events
+            .iter()
+            .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+            .collect()
+    }
+
+    fn reply(text: &[&str]) -> String {
+        let mut events = vec![
+            json!({"type": "message_start", "message": {"id": "msg_1", "model": "claude-sonnet-4-5-20250929", "usage": {"input_tokens": 12, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "ping"}),
+        ];
+        for part in text {
+            events.push(json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": part}}));
+        }
+        events.extend([
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 7}}),
+            json!({"type": "message_stop"}),
+        ]);
+        sse(&events)
+    }
+
+    /// A factory keeping its key and transcripts in `dir`
+    fn factory(dir: &Path, base_url: &str, key: Option<&str>) -> ApiBackendFactory {
+        let store = Arc::new(ClaudeStore::open_in_memory().unwrap());
+        let mut factory = ApiBackendFactory::new(store, dir.join("api-key"));
+        factory.transcripts = SessionManager::with_claude_dir(dir.join("claude"));
+        if let Some(key) = key {
+            factory.keys().save(key).unwrap();
+        }
+        factory
+            .save_settings(&ApiSettings {
+                base_url: base_url.to_string(),
+                ..ApiSettings::default()
+            })
+            .unwrap();
+        factory
+    }
+
+    fn config() -> ProcessConfig {
+        ProcessConfig {
+            working_dir: ".".to_string(),
+            mcp_config_path: None,
+            system_prompt: Some("Be brief.".to_string()),
+            options: Default::default(),
+        }
+    }
+
+    async fn send(factory: &ApiBackendFactory, message: &str) -> (Result<Option<String>>, Vec<ParsedOutput>) {
+        send_to(factory, &SessionArg::New("conv-1".to_string()), message).await
+    }
+
+    async fn send_to(
+        factory: &ApiBackendFactory,
+        session: &SessionArg,
+        message: &str,
+    ) -> (Result<Option<String>>, Vec<ParsedOutput>) {
+        let (tx, mut rx) = mpsc::channel(64);
+        let mut backend = factory.create(&config());
+        let result = backend.send_message(message, session, tx).await;
+        let mut outputs = Vec::new();
+        while let Some(output) = rx.recv().await {
+            outputs.push(output);
+        }
+        (result, outputs)
+    }
+
+    #[tokio::test]
+    async fn test_streams_reply_and_keeps_history() {
+        let (base_url, requests) =
+            mock_server(vec![(200, reply(&["Hel", "lo"])), (200, reply(&["Again"]))]).await;
+        let dir = TempDir::new("api");
+        let factory = factory(&dir, &base_url, Some("sk-test"));
+
+        let (result, outputs) = send(&factory, "Hi").await;
+        assert_eq!(result.unwrap().as_deref(), Some("conv-1"));
+        assert_eq!(
+            outputs[..3],
+            [
+                ParsedOutput::SessionId("conv-1".to_string()),
+                ParsedOutput::Text {
+                    text: "Hel".to_string(),
+                    parent_tool_use_id: None,
+                },
+                ParsedOutput::Text {
+                    text: "lo".to_string(),
+                    parent_tool_use_id: None,
+                },
+            ]
+        );
+        match &outputs[3..] {
+            [ParsedOutput::Usage(turn)] => {
+                assert_eq!((turn.usage.input_tokens, turn.usage.output_tokens), (12, 7));
+                assert_eq!(turn.num_turns, 1);
+                // 12 input tokens at $3/M and 7 output tokens at $15/M
+                assert!((turn.total_cost_usd - 0.000141).abs() < 1e-12);
+                assert!(turn.duration_api_ms <= turn.duration_ms);
+            }
+            other => panic!("expected usage, got {:?}", other),
+        }
+
+        send(&factory, "And again?").await.0.unwrap();
+        {
+            let requests = requests.lock();
+            assert_eq!(requests[0]["system"], "Be brief.");
+            assert_eq!(requests[0]["stream"], true);
+            assert_eq!(requests[0]["model"], DEFAULT_MODEL);
+            assert_eq!(
+                requests[1]["messages"],
+                json!([
+                    {"role": "user", "content": "Hi"},
+                    {"role": "assistant", "content": "Hello"},
+                    {"role": "user", "content": "And again?"},
+                ])
+            );
+        }
+
+        // The turns are in a transcript the rest of the app can read
+        let mut transcripts = factory.transcripts.clone();
+        transcripts.set_project_dir(&config().working_dir);
+        let conversations = transcripts.list_conversations().unwrap();
+        assert_eq!(conversations.len(), 1);
+        assert_eq!(conversations[0].id, "conv-1");
+        let path = transcripts.transcript_path("conv-1").unwrap();
+        assert_eq!(load_messages(&path).unwrap().len(), 4);
+        // The mock reuses its message id, so only one reply's usage counts
+        assert_eq!(token_usage(&path).unwrap().output_tokens, 7);
+    }
+
+    #[tokio::test]
+    async fn test_keeps_cancelled_turn() {
+        // The first reply stalls after its first words; the second is whole
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        tokio::spawn(async move {
+            let (mut stalled, _) = listener.accept().await.unwrap();
+            read_request(&mut stalled).await;
+            let start = sse(&[
+                json!({"type": "message_start", "message": {"id": "msg_1", "usage": {"input_tokens": 12}}}),
+                json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Let me"}}),
+            ]);
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\n";
+            stalled.write_all(format!("{}{}", head, start).as_bytes()).await.unwrap();
+
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await;
+            recorded.lock().push(request);
+            let body = reply(&["Done"]);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            drop(stalled);
+        });
+        let dir = TempDir::new("api");
+        let factory = factory(&dir, &base_url, Some("sk-test"));
+
+        let (tx, mut rx) = mpsc::channel(64);
+        let mut backend = factory.create(&config());
+        let kill_handle = backend.kill_handle();
+        let session = SessionArg::New("conv-1".to_string());
+        let cancel = async {
+            while let Some(output) = rx.recv().await {
+                if matches!(output, ParsedOutput::Text { .. }) {
+                    kill_handle.kill().unwrap();
+                }
+            }
+        };
+        let (result, ()) = tokio::join!(backend.send_message("Hi", &session, tx), cancel);
+        assert_eq!(result.unwrap().as_deref(), Some("conv-1"));
+
+        // The prompt and the partial reply stay in the history
+        send_to(&factory, &SessionArg::Resume("conv-1".to_string()), "Go on")
+            .await
+            .0
+            .unwrap();
+        assert_eq!(
+            requests.lock()[0]["messages"],
+            json!([
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Let me"},
+                {"role": "user", "content": "Go on"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resumes_after_restart() {
+        let (base_url, requests) =
+            mock_server(vec![(200, reply(&["Hello"])), (200, reply(&["Welcome back"]))]).await;
+        let dir = TempDir::new("api");
+        let first = factory(&dir, &base_url, Some("sk-test"));
+        send(&first, "Hi").await.0.unwrap();
+
+        let second = factory(&dir, &base_url, Some("sk-test"));
+        send_to(&second, &SessionArg::Resume("conv-1".to_string()), "Still there?")
+            .await
+            .0
+            .unwrap();
+        assert_eq!(
+            requests.lock()[1]["messages"],
+            json!([
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello"},
+                {"role": "user", "content": "Still there?"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fork_keeps_parent_history() {
+        let (base_url, requests) = mock_server(vec![
+            (200, reply(&["Hello"])),
+            (200, reply(&["Forked"])),
+            (200, reply(&["Still forked"])),
+        ])
+        .await;
+        let dir = TempDir::new("api");
+        let factory = factory(&dir, &base_url, Some("sk-test"));
+
+        send(&factory, "Hi").await.0.unwrap();
+        let fork = send_to(&factory, &SessionArg::Fork("conv-1".to_string()), "Fork here")
+            .await
+            .0
+            .unwrap()
+            .unwrap();
+        assert_ne!(fork, "conv-1");
+        send_to(&factory, &SessionArg::Resume(fork), "And then?").await.0.unwrap();
+
+        let requests = requests.lock();
+        assert_eq!(
+            requests[2]["messages"],
+            json!([
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello"},
+                {"role": "user", "content": "Fork here"},
+                {"role": "assistant", "content": "Forked"},
+                {"role": "user", "content": "And then?"},
+            ])
+        );
+        // The parent is unchanged
+        let mut transcripts = factory.transcripts.clone();
+        transcripts.set_project_dir(&config().working_dir);
+        let parent = transcripts.transcript_path("conv-1").unwrap();
+        assert_eq!(load_messages(&parent).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resumes_from_transcript() {
+        let (base_url, requests) = mock_server(vec![(200, reply(&["Sure"]))]).await;
+        let dir = TempDir::new("api");
+        let factory = factory(&dir, &base_url, Some("sk-test"));
+        let mut transcripts = factory.transcripts.clone();
+        transcripts.set_project_dir(&config().working_dir);
+        let path = transcripts.transcript_path("fork-1").unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let lines = [
+            r#"{"type":"user","uuid":"u1","message":{"role":"user","content":"List my tasks"}}"#,
+            r#"{"type":"assistant","uuid":"a1","message":{"id":"m1","content":[{"type":"text","text":"Checking."},{"type":"tool_use","id":"t1","name":"list_tasks","input":{}}]}}"#,
+            r#"{"type":"user","uuid":"r1","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"none"}]}}"#,
+            r#"{"type":"assistant","uuid":"a2","message":{"id":"m2","content":[{"type":"text","text":"No tasks."}]}}"#,
+        ];
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        send_to(&factory, &SessionArg::Resume("fork-1".to_string()), "Add one").await.0.unwrap();
+        assert_eq!(
+            requests.lock()[0]["messages"],
+            json!([
+                {"role": "user", "content": "List my tasks"},
+                {"role": "assistant", "content": "Checking."},
+                {"role": "assistant", "content": "No tasks."},
+                {"role": "user", "content": "Add one"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_classifies_api_errors() {
+        let overloaded = sse(&[
+            json!({"type": "message_start", "message": {"usage": {"input_tokens": 3}}}),
+            json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+        ]);
+        let (base_url, _) = mock_server(vec![
+            (401, r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#.to_string()),
+            (429, r#"{"type":"error","error":{"type":"rate_limit_error","message":"Slow down"}}"#.to_string()),
+            (200, overloaded),
+            (500, "Internal Server Error".to_string()),
+        ])
+        .await;
+        let dir = TempDir::new("api");
+        let factory = factory(&dir, &base_url, Some("sk-test"));
+
+        assert!(matches!(send(&factory, "1").await.0, Err(ClaudeError::ApiKeyRejected)));
+        assert!(matches!(send(&factory, "2").await.0, Err(ClaudeError::RateLimited(m)) if m == "Slow down"));
+        assert!(matches!(send(&factory, "3").await.0, Err(ClaudeError::Overloaded)));
+        assert!(matches!(send(&factory, "4").await.0, Err(ClaudeError::ApiError(m)) if m == "HTTP 500"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_key() {
+        let dir = TempDir::new("api");
+        let factory = factory(&dir, DEFAULT_BASE_URL, None);
+        assert!(!factory.is_available());
+        assert!(matches!(send(&factory, "Hi").await.0, Err(ClaudeError::ApiKeyMissing)));
+
+        factory.keys().save(" sk-test\n").unwrap();
+        assert_eq!(factory.keys().load().as_deref(), Some("sk-test"));
+        assert!(factory.keys().save("sk test").is_err());
+        factory.keys().clear().unwrap();
+        factory.keys().clear().unwrap();
+        assert!(!factory.is_available());
+    }
+}
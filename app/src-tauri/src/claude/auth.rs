@@ -0,0 +1,274 @@
+//! Claude CLI login state
+//!
+//! Reads the same credential sources the CLI uses, in its order of
+//! precedence: cloud-provider and API-key environment variables, an
+//! `apiKeyHelper` in `settings.json`, the OAuth credentials of a
+//! subscription login (`CLAUDE_CODE_OAUTH_TOKEN`, `.credentials.json`, or
+//! the login keychain on macOS), and finally a Console API key saved in
+//! `.claude.json`. The account behind a stored subscription login comes
+//! from `oauthAccount` in `.claude.json`; a token from the environment may
+//! belong to another account, so none is reported for it. Nothing is sent
+//! over the network.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Keychain item holding the CLI's OAuth credentials on macOS
+#[cfg(target_os = "macos")]
+const KEYCHAIN_SERVICE: &str = "Claude Code-credentials";
+
+/// How the CLI authenticates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// Claude Pro/Max/Team login (`/login` with a Claude account)
+    Subscription,
+    /// Anthropic Console API key
+    ApiKey,
+    Bedrock,
+    Vertex,
+}
+
+/// Result of `auth_status`
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AuthStatus {
+    pub authenticated: bool,
+    pub method: Option<AuthMethod>,
+    pub email: Option<String>,
+    pub organization: Option<String>,
+    /// `pro`, `max`, ... for subscription logins
+    pub subscription_type: Option<String>,
+    /// Where the credentials were found, or why none were
+    pub detail: String,
+}
+
+impl AuthStatus {
+    fn authenticated(method: AuthMethod, detail: impl Into<String>) -> Self {
+        Self {
+            authenticated: true,
+            method: Some(method),
+            detail: detail.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// `.claude.json`, the CLI's global state file
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct GlobalConfig {
+    oauth_account: Option<OAuthAccount>,
+    primary_api_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct OAuthAccount {
+    email_address: Option<String>,
+    organization_name: Option<String>,
+}
+
+/// `.credentials.json`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Credentials {
+    claude_ai_oauth: Option<OAuthCredentials>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct OAuthCredentials {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    subscription_type: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Settings {
+    api_key_helper: Option<String>,
+}
+
+/// The CLI's config directory and its `.claude.json`
+pub fn config_paths() -> (PathBuf, PathBuf) {
+    let home = dirs::home_dir().unwrap_or_default();
+    // `CLAUDE_CONFIG_DIR` moves both the config directory and `.claude.json`
+    match std::env::var_os("CLAUDE_CONFIG_DIR") {
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            (dir.clone(), dir.join(".claude.json"))
+        }
+        None => (home.join(".claude"), home.join(".claude.json")),
+    }
+}
+
+/// Login state of the CLI for the current user
+pub fn auth_status() -> AuthStatus {
+    let (config_dir, global_config) = config_paths();
+    detect(
+        &config_dir,
+        &global_config,
+        &|name| std::env::var(name).ok(),
+        &keychain_has_credentials,
+    )
+}
+
+fn detect(
+    config_dir: &Path,
+    global_config: &Path,
+    var: &dyn Fn(&str) -> Option<String>,
+    keychain: &dyn Fn() -> bool,
+) -> AuthStatus {
+    let is_set = |name: &str| var(name).is_some_and(|v| !v.trim().is_empty());
+    let enabled = |name: &str| var(name).is_some_and(|v| matches!(v.trim(), "1" | "true"));
+
+    if enabled("CLAUDE_CODE_USE_BEDROCK") {
+        return AuthStatus::authenticated(AuthMethod::Bedrock, "Amazon Bedrock (CLAUDE_CODE_USE_BEDROCK)");
+    }
+    if enabled("CLAUDE_CODE_USE_VERTEX") {
+        return AuthStatus::authenticated(AuthMethod::Vertex, "Google Vertex AI (CLAUDE_CODE_USE_VERTEX)");
+    }
+    for name in ["ANTHROPIC_API_KEY", "ANTHROPIC_AUTH_TOKEN"] {
+        if is_set(name) {
+            return AuthStatus::authenticated(AuthMethod::ApiKey, format!("{} environment variable", name));
+        }
+    }
+    let settings: Settings = read_json(&config_dir.join("settings.json"));
+    if settings.api_key_helper.is_some_and(|h| !h.trim().is_empty()) {
+        return AuthStatus::authenticated(AuthMethod::ApiKey, "apiKeyHelper in settings.json");
+    }
+
+    let global: GlobalConfig = read_json(global_config);
+    let credentials: Credentials = read_json(&config_dir.join(".credentials.json"));
+    let oauth = credentials.claude_ai_oauth.filter(|o| {
+        // An expired access token is fine as long as it can be refreshed
+        [&o.access_token, &o.refresh_token]
+            .iter()
+            .any(|t| t.as_deref().is_some_and(|t| !t.is_empty()))
+    });
+    // A token from `claude setup-token` takes precedence over the stored
+    // login, whose account it needn't share
+    if is_set("CLAUDE_CODE_OAUTH_TOKEN") {
+        return AuthStatus::authenticated(
+            AuthMethod::Subscription,
+            "CLAUDE_CODE_OAUTH_TOKEN environment variable",
+        );
+    }
+    let subscription_detail = if oauth.is_some() {
+        Some(".credentials.json")
+    } else if global.oauth_account.is_some() && keychain() {
+        Some("macOS keychain")
+    } else {
+        None
+    };
+    if let Some(detail) = subscription_detail {
+        let account = global.oauth_account.unwrap_or_default();
+        return AuthStatus {
+            email: account.email_address,
+            organization: account.organization_name,
+            subscription_type: oauth.and_then(|o| o.subscription_type),
+            ..AuthStatus::authenticated(AuthMethod::Subscription, detail)
+        };
+    }
+
+    if global.primary_api_key.is_some_and(|k| !k.is_empty()) {
+        return AuthStatus::authenticated(AuthMethod::ApiKey, "Console API key in .claude.json");
+    }
+
+    AuthStatus {
+        detail: "Not logged in. Run `claude` in a terminal and use /login.".to_string(),
+        ..AuthStatus::default()
+    }
+}
+
+/// Parse a JSON file, treating a missing or unreadable file as empty
+fn read_json<T: Default + serde::de::DeserializeOwned>(path: &Path) -> T {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Whether the login keychain has the CLI's credentials (without reading them)
+#[cfg(target_os = "macos")]
+fn keychain_has_credentials() -> bool {
+    std::process::Command::new("/usr/bin/security")
+        .args(["find-generic-password", "-s", KEYCHAIN_SERVICE])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_has_credentials() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testutil::TempDir;
+    use std::collections::HashMap;
+
+    fn detect_in(dir: &Path, env: &[(&str, &str)], keychain: bool) -> AuthStatus {
+        let env: HashMap<String, String> =
+            env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        detect(
+            &dir.join(".claude"),
+            &dir.join(".claude.json"),
+            &|name| env.get(name).cloned(),
+            &|| keychain,
+        )
+    }
+
+    #[test]
+    fn test_detect_auth_sources() {
+        let home = TempDir::new("auth");
+        std::fs::create_dir_all(home.join(".claude")).unwrap();
+
+        // A configured, used, but logged-out CLI
+        std::fs::write(home.join(".claude/settings.json"), r#"{"model":"opus"}"#).unwrap();
+        std::fs::write(home.join(".claude/history.jsonl"), "").unwrap();
+        std::fs::write(home.join(".claude.json"), r#"{"numStartups":3}"#).unwrap();
+        let status = detect_in(&home, &[], true);
+        assert!(!status.authenticated);
+        assert_eq!(status.method, None);
+
+        let status = detect_in(&home, &[("ANTHROPIC_API_KEY", "sk-ant-x")], false);
+        assert_eq!((status.authenticated, status.method), (true, Some(AuthMethod::ApiKey)));
+
+        // Subscription login: account from .claude.json, token from either store
+        std::fs::write(
+            home.join(".claude.json"),
+            r#"{"oauthAccount":{"emailAddress":"ada@example.com","organizationName":"Acme"}}"#,
+        )
+        .unwrap();
+        let status = detect_in(&home, &[], true);
+        assert_eq!(status.method, Some(AuthMethod::Subscription));
+        assert_eq!(status.email.as_deref(), Some("ada@example.com"));
+        assert!(!detect_in(&home, &[], false).authenticated);
+
+        std::fs::write(
+            home.join(".claude/.credentials.json"),
+            r#"{"claudeAiOauth":{"accessToken":"a","refreshToken":"r","expiresAt":1,"subscriptionType":"max"}}"#,
+        )
+        .unwrap();
+        let status = detect_in(&home, &[], false);
+        assert_eq!(status.subscription_type.as_deref(), Some("max"));
+        assert_eq!(status.organization.as_deref(), Some("Acme"));
+        assert_eq!(status.detail, ".credentials.json");
+
+        let status = detect_in(&home, &[("CLAUDE_CODE_OAUTH_TOKEN", "sk-ant-oat")], false);
+        assert_eq!(status.method, Some(AuthMethod::Subscription));
+        assert_eq!(status.detail, "CLAUDE_CODE_OAUTH_TOKEN environment variable");
+        assert_eq!((status.email, status.organization, status.subscription_type), (None, None, None));
+
+        let status = detect_in(&home, &[("CLAUDE_CODE_USE_BEDROCK", "1")], false);
+        assert_eq!(status.method, Some(AuthMethod::Bedrock));
+
+        std::fs::remove_file(home.join(".claude/.credentials.json")).unwrap();
+        std::fs::write(home.join(".claude.json"), r#"{"primaryApiKey":"sk-ant-y"}"#).unwrap();
+        assert_eq!(detect_in(&home, &[], false).method, Some(AuthMethod::ApiKey));
+    }
+}
@@ -0,0 +1,182 @@
+//! Pluggable assistant backends
+//!
+//! The manager runs every turn through an `AssistantBackend` created by a
+//! `BackendFactory`. `CliBackend` drives the real Claude CLI,
+//! `ApiBackendFactory` talks to the Messages API directly, and
+//! `SelectingBackend` picks between the two per the saved preference.
+//! Tests use a scripted fake that replays recorded stream-json.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::api::{ApiBackendFactory, BackendPreference};
+use super::discovery::CliLocator;
+use super::error::{ClaudeError, Result};
+use super::pty::{
+    ClaudeProcess, KillHandle, ParsedOutput, PersistentProcess, ProcessConfig,
+    ProcessExit, SessionArg,
+};
+
+/// Boxed future returned by backend methods (keeps the traits object-safe)
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Runs one assistant turn, streaming its output
+pub trait AssistantBackend: Send {
+    /// Send `message` and forward the parsed response to `output_tx`
+    ///
+    /// Returns the conversation id reported by the backend, or the error
+    /// that ended the turn.
+    fn send_message<'a>(
+        &'a mut self,
+        message: &'a str,
+        session: &'a SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> BoxFuture<'a, Result<Option<String>>>;
+
+    /// Handle for cancelling the turn from another task
+    fn kill_handle(&self) -> KillHandle;
+
+    /// How the last turn's process ended, if the backend runs one
+    fn last_exit(&self) -> Option<&ProcessExit>;
+}
+
+impl AssistantBackend for ClaudeProcess {
+    fn send_message<'a>(
+        &'a mut self,
+        message: &'a str,
+        session: &'a SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(ClaudeProcess::send_message(self, message, session, output_tx))
+    }
+
+    fn kill_handle(&self) -> KillHandle {
+        ClaudeProcess::kill_handle(self)
+    }
+
+    fn last_exit(&self) -> Option<&ProcessExit> {
+        ClaudeProcess::last_exit(self)
+    }
+}
+
+/// Creates backends for a session's turns
+pub trait BackendFactory: Send + Sync {
+    /// Whether the backend can be used at all (e.g. the CLI is installed)
+    fn is_available(&self) -> bool;
+
+    /// Like `is_available`, but says what is missing
+    fn check(&self) -> Result<()> {
+        if self.is_available() {
+            Ok(())
+        } else {
+            Err(ClaudeError::CliNotFound)
+        }
+    }
+
+    /// A backend for a single per-message turn
+    fn create(&self, config: &ProcessConfig) -> Box<dyn AssistantBackend>;
+
+    /// Start a long-lived process for persistent mode
+    fn spawn_persistent(
+        &self,
+        _config: &ProcessConfig,
+        _session: &SessionArg,
+        _output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<PersistentProcess> {
+        Err(ClaudeError::InvalidOption(
+            "Persistent mode requires the Claude CLI backend".to_string(),
+        ))
+    }
+}
+
+/// The Claude CLI (`claude -p --output-format stream-json`)
+pub struct CliBackend {
+    locator: Arc<CliLocator>,
+}
+
+impl CliBackend {
+    pub fn new(locator: Arc<CliLocator>) -> Self {
+        Self { locator }
+    }
+}
+
+impl BackendFactory for CliBackend {
+    fn is_available(&self) -> bool {
+        self.check().is_ok()
+    }
+
+    fn check(&self) -> Result<()> {
+        self.locator.resolve().map(|_| ())
+    }
+
+    fn create(&self, config: &ProcessConfig) -> Box<dyn AssistantBackend> {
+        // The manager checks the CLI before each turn; if it vanished since,
+        // spawning the bare name fails with a clear error
+        let cli_path = self
+            .locator
+            .resolve()
+            .map_or_else(|_| "claude".to_string(), |cli| cli.path);
+        Box::new(ClaudeProcess::new(cli_path, config.clone()))
+    }
+
+    fn spawn_persistent(
+        &self,
+        config: &ProcessConfig,
+        session: &SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<PersistentProcess> {
+        let cli = self.locator.resolve()?;
+        PersistentProcess::spawn(&cli.path, config, session, output_tx)
+    }
+}
+
+/// Routes turns to the CLI or the Messages API
+///
+/// With the `auto` preference the CLI is used whenever it is installed,
+/// and the API only when it isn't and a key has been saved.
+pub struct SelectingBackend {
+    cli: Arc<dyn BackendFactory>,
+    api: Arc<ApiBackendFactory>,
+}
+
+impl SelectingBackend {
+    pub fn new(cli: Arc<dyn BackendFactory>, api: Arc<ApiBackendFactory>) -> Self {
+        Self { cli, api }
+    }
+
+    fn current(&self) -> &dyn BackendFactory {
+        match self.api.settings().backend {
+            BackendPreference::Cli => self.cli.as_ref(),
+            BackendPreference::Api => self.api.as_ref(),
+            BackendPreference::Auto if !self.cli.is_available() && self.api.is_available() => {
+                self.api.as_ref()
+            }
+            BackendPreference::Auto => self.cli.as_ref(),
+        }
+    }
+}
+
+impl BackendFactory for SelectingBackend {
+    fn is_available(&self) -> bool {
+        self.current().is_available()
+    }
+
+    fn check(&self) -> Result<()> {
+        self.current().check()
+    }
+
+    fn create(&self, config: &ProcessConfig) -> Box<dyn AssistantBackend> {
+        self.current().create(config)
+    }
+
+    fn spawn_persistent(
+        &self,
+        config: &ProcessConfig,
+        session: &SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<PersistentProcess> {
+        self.current().spawn_persistent(config, session, output_tx)
+    }
+}
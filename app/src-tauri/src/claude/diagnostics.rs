@@ -0,0 +1,540 @@
+//! Health report for the Claude integration
+//!
+//! `claude_diagnostics` gathers everything needed to tell why chat is
+//! broken: the resolved CLI and its version, login state, working
+//! directory, MCP servers (each probed with an `initialize` handshake),
+//! and the recent stderr output and errors kept by `ActivityLog`. The
+//! report leaves out secrets (API keys, MCP server arguments and
+//! environment) so it can be attached to bug reports.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::task::JoinSet;
+
+use super::api::{ApiStatus, SseParser};
+use super::auth::AuthStatus;
+use super::discovery::{path_env_for, CliStatus};
+use super::error::{ClaudeError, ErrorKind};
+use super::manager::SessionState;
+use super::pty::ProcessExit;
+
+/// Stderr lines kept across all sessions
+const MAX_STDERR_LINES: usize = 500;
+
+/// Errors kept across all sessions
+const MAX_ERRORS: usize = 50;
+
+/// Time an MCP server gets to answer `initialize` (`npx` may download first)
+const MCP_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// MCP protocol revision offered in the handshake
+const MCP_PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// A stderr line from a finished CLI process
+#[derive(Debug, Clone, Serialize)]
+pub struct StderrLine {
+    pub at: String,
+    pub session_id: String,
+    pub line: String,
+}
+
+/// An error reported to the frontend
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentError {
+    pub at: String,
+    pub session_id: String,
+    pub kind: ErrorKind,
+    pub message: String,
+    pub exit_code: Option<i32>,
+}
+
+/// Recent stderr output and errors of all sessions, newest last
+#[derive(Debug, Default)]
+pub struct ActivityLog {
+    stderr: Mutex<VecDeque<StderrLine>>,
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+impl ActivityLog {
+    pub fn record_stderr(&self, session_id: &str, lines: &[String]) {
+        let at = chrono::Utc::now().to_rfc3339();
+        let mut stderr = self.stderr.lock();
+        for line in lines {
+            if stderr.len() == MAX_STDERR_LINES {
+                stderr.pop_front();
+            }
+            stderr.push_back(StderrLine {
+                at: at.clone(),
+                session_id: session_id.to_string(),
+                line: line.clone(),
+            });
+        }
+    }
+
+    /// Record an error together with the stderr of the process that failed
+    pub fn record_error(&self, session_id: &str, error: &ClaudeError, exit: Option<&ProcessExit>) {
+        if let Some(exit) = exit {
+            self.record_stderr(session_id, &exit.stderr);
+        }
+        let mut errors = self.errors.lock();
+        if errors.len() == MAX_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(RecentError {
+            at: chrono::Utc::now().to_rfc3339(),
+            session_id: session_id.to_string(),
+            kind: error.kind(),
+            message: error.to_string(),
+            exit_code: exit.and_then(|e| e.code),
+        });
+    }
+
+    /// The last `limit` stderr lines
+    pub fn recent_stderr(&self, limit: usize) -> Vec<StderrLine> {
+        let stderr = self.stderr.lock();
+        stderr.iter().skip(stderr.len().saturating_sub(limit)).cloned().collect()
+    }
+
+    pub fn recent_errors(&self) -> Vec<RecentError> {
+        self.errors.lock().iter().cloned().collect()
+    }
+}
+
+/// Everything `claude_diagnostics` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsReport {
+    pub generated_at: String,
+    pub app_version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub cli: CliStatus,
+    pub auth: AuthStatus,
+    pub api: ApiStatus,
+    pub working_dir: String,
+    pub working_dir_exists: bool,
+    pub mcp_config_path: Option<String>,
+    pub mcp_servers: Vec<McpServerCheck>,
+    pub sessions: Vec<SessionState>,
+    pub recent_stderr: Vec<StderrLine>,
+    pub recent_errors: Vec<RecentError>,
+}
+
+/// Outcome of one MCP server's handshake
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum McpCheckStatus {
+    Ok,
+    Failed,
+    /// Transport the check doesn't speak
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct McpServerCheck {
+    pub name: String,
+    /// Config file the server came from
+    pub source: String,
+    pub transport: String,
+    /// Command or URL (without arguments or query)
+    pub target: String,
+    pub status: McpCheckStatus,
+    pub server_name: Option<String>,
+    pub server_version: Option<String>,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// A server entry of an MCP config's `mcpServers`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct McpServerConfig {
+    #[serde(rename = "type")]
+    transport: Option<String>,
+    command: Option<String>,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    url: Option<String>,
+    headers: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct McpConfigFile {
+    mcp_servers: HashMap<String, McpServerConfig>,
+}
+
+/// The MCP servers of `.claude.json`: user-scoped at the top level,
+/// local-scoped under the project's directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct GlobalMcpConfig {
+    mcp_servers: HashMap<String, McpServerConfig>,
+    projects: HashMap<String, McpConfigFile>,
+}
+
+/// Read an MCP config file; a missing one has no servers
+fn read_mcp_config<T: serde::de::DeserializeOwned + Default>(path: &Path) -> T {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return T::default();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        eprintln!("[Claude] Invalid MCP config {}: {}", path.display(), e);
+        T::default()
+    })
+}
+
+/// MCP servers the CLI would load for `working_dir`: the `--mcp-config`
+/// file, then the local, project (`.mcp.json`) and user scopes
+///
+/// A name defined in several scopes is listed once, from the scope the CLI
+/// prefers.
+fn configured_mcp_servers(
+    mcp_config_path: Option<&str>,
+    working_dir: &Path,
+    global_config: &Path,
+) -> Vec<(String, String, McpServerConfig)> {
+    let global_source = global_config.to_string_lossy();
+    let mut global: GlobalMcpConfig = read_mcp_config(global_config);
+    let local = global
+        .projects
+        .remove(working_dir.to_string_lossy().as_ref())
+        .unwrap_or_default();
+
+    let mut sources = Vec::new();
+    if let Some(path) = mcp_config_path {
+        sources.push((path.to_string(), read_mcp_config::<McpConfigFile>(Path::new(path)).mcp_servers));
+    }
+    sources.push((format!("{} (local)", global_source), local.mcp_servers));
+    let project = working_dir.join(".mcp.json");
+    sources.push((
+        project.to_string_lossy().to_string(),
+        read_mcp_config::<McpConfigFile>(&project).mcp_servers,
+    ));
+    sources.push((format!("{} (user)", global_source), global.mcp_servers));
+
+    let mut seen = HashSet::new();
+    let mut servers = Vec::new();
+    for (source, entries) in sources {
+        let mut entries: Vec<_> = entries.into_iter().filter(|(name, _)| seen.insert(name.clone())).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        servers.extend(entries.into_iter().map(|(name, server)| (name, source.clone(), server)));
+    }
+    servers
+}
+
+/// Probe every configured MCP server concurrently
+///
+/// Stdio servers run with the `PATH` the CLI gets, so `npx`-style commands
+/// resolve to the same `node`.
+pub async fn check_mcp_servers(
+    mcp_config_path: Option<&str>,
+    working_dir: &Path,
+    global_config: &Path,
+    cli_path: Option<&Path>,
+) -> Vec<McpServerCheck> {
+    let path_env = cli_path.and_then(path_env_for);
+    let mut tasks = JoinSet::new();
+    for (index, (name, source, server)) in configured_mcp_servers(mcp_config_path, working_dir, global_config)
+        .into_iter()
+        .enumerate()
+    {
+        let working_dir = working_dir.to_path_buf();
+        let path_env = path_env.clone();
+        tasks.spawn(async move {
+            (index, check_mcp_server(name, source, server, &working_dir, path_env).await)
+        });
+    }
+
+    let mut checks = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        if let Ok(check) = result {
+            checks.push(check);
+        }
+    }
+    checks.sort_by_key(|(index, _)| *index);
+    checks.into_iter().map(|(_, check)| check).collect()
+}
+
+async fn check_mcp_server(
+    name: String,
+    source: String,
+    server: McpServerConfig,
+    working_dir: &Path,
+    path_env: Option<OsString>,
+) -> McpServerCheck {
+    let transport = server
+        .transport
+        .clone()
+        .unwrap_or_else(|| if server.url.is_some() { "http" } else { "stdio" }.to_string());
+    let target = match (&server.command, &server.url) {
+        (Some(command), _) => command.clone(),
+        (None, Some(url)) => url.split(['?', '#']).next().unwrap_or_default().to_string(),
+        (None, None) => String::new(),
+    };
+    let mut check = McpServerCheck {
+        name,
+        source,
+        transport: transport.clone(),
+        target,
+        status: McpCheckStatus::Skipped,
+        server_name: None,
+        server_version: None,
+        error: None,
+        elapsed_ms: 0,
+    };
+
+    let started = Instant::now();
+    let result = match transport.as_str() {
+        "stdio" => with_timeout(stdio_handshake(&server, working_dir, path_env)).await,
+        "http" => with_timeout(http_handshake(&server)).await,
+        _ => {
+            check.error = Some(format!("{} transport is not checked", transport));
+            return check;
+        }
+    };
+    check.elapsed_ms = started.elapsed().as_millis() as u64;
+    match result.and_then(|response| initialize_result(&response)) {
+        Ok(info) => {
+            check.status = McpCheckStatus::Ok;
+            check.server_name = info["name"].as_str().map(str::to_string);
+            check.server_version = info["version"].as_str().map(str::to_string);
+        }
+        Err(e) => {
+            check.status = McpCheckStatus::Failed;
+            check.error = Some(e);
+        }
+    }
+    check
+}
+
+async fn with_timeout(
+    handshake: impl std::future::Future<Output = std::result::Result<Value, String>>,
+) -> std::result::Result<Value, String> {
+    tokio::time::timeout(MCP_HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .unwrap_or_else(|_| {
+            Err(format!(
+                "No answer to initialize within {} seconds",
+                MCP_HANDSHAKE_TIMEOUT.as_secs()
+            ))
+        })
+}
+
+fn initialize_request() -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": MCP_PROTOCOL_VERSION,
+            "capabilities": {},
+            "clientInfo": { "name": "officeos-diagnostics", "version": env!("CARGO_PKG_VERSION") },
+        },
+    })
+}
+
+/// `serverInfo` from an `initialize` response, or the JSON-RPC error
+fn initialize_result(response: &Value) -> std::result::Result<Value, String> {
+    if let Some(error) = response.get("error") {
+        return Err(format!(
+            "Server returned an error: {}",
+            error["message"].as_str().unwrap_or("unknown error")
+        ));
+    }
+    response
+        .get("result")
+        .map(|result| result.get("serverInfo").cloned().unwrap_or(Value::Null))
+        .ok_or_else(|| "Response has neither result nor error".to_string())
+}
+
+/// Start the server, send `initialize` and wait for the matching response
+async fn stdio_handshake(
+    server: &McpServerConfig,
+    working_dir: &Path,
+    path_env: Option<OsString>,
+) -> std::result::Result<Value, String> {
+    let command = server.command.as_deref().ok_or("No command configured")?;
+    let mut cmd = tokio::process::Command::new(command);
+    if let Some(path_env) = path_env {
+        cmd.env("PATH", path_env);
+    }
+    let mut child = cmd
+        .args(&server.args)
+        .envs(&server.env)
+        .current_dir(working_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", command, e))?;
+
+    let mut stdin = child.stdin.take().ok_or("Failed to capture stdin")?;
+    let stdout = child.stdout.take().ok_or("Failed to capture stdout")?;
+    if let Err(e) = stdin
+        .write_all(format!("{}\n", initialize_request()).as_bytes())
+        .await
+    {
+        // Most likely the server already exited; its status says more
+        return Err(match child.wait().await.ok().and_then(|s| s.code()) {
+            Some(code) => format!("Server exited with code {} before answering", code),
+            None => format!("Failed to write to server: {}", e),
+        });
+    }
+
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                // Servers may log or send notifications first
+                if let Ok(message) = serde_json::from_str::<Value>(&line) {
+                    if message["id"] == 1 {
+                        return Ok(message);
+                    }
+                }
+            }
+            Ok(None) => {
+                let status = child.wait().await.ok().and_then(|s| s.code());
+                return Err(match status {
+                    Some(code) => format!("Server exited with code {} before answering", code),
+                    None => "Server exited before answering".to_string(),
+                });
+            }
+            Err(e) => return Err(format!("Failed to read from server: {}", e)),
+        }
+    }
+}
+
+/// POST `initialize` to a Streamable HTTP server
+async fn http_handshake(server: &McpServerConfig) -> std::result::Result<Value, String> {
+    let url = server.url.as_deref().ok_or("No URL configured")?;
+    let mut request = reqwest::Client::new()
+        .post(url)
+        .header("accept", "application/json, text/event-stream")
+        .json(&initialize_request());
+    for (name, value) in &server.headers {
+        request = request.header(name, value);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+
+    let is_sse = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    let payloads = if is_sse {
+        let mut parser = SseParser::default();
+        let mut events = parser.push(&body);
+        events.extend(parser.push(b"\n\n"));
+        events
+    } else {
+        vec![String::from_utf8_lossy(&body).to_string()]
+    };
+    payloads
+        .iter()
+        .filter_map(|payload| serde_json::from_str::<Value>(payload).ok())
+        .find(|message| message["id"] == 1)
+        .ok_or_else(|| "No initialize response in reply".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testutil::TempDir;
+
+    #[test]
+    fn test_activity_log_keeps_recent_entries() {
+        let log = ActivityLog::default();
+        let lines: Vec<String> = (0..MAX_STDERR_LINES + 10).map(|i| i.to_string()).collect();
+        log.record_stderr("s1", &lines);
+        let exit = ProcessExit {
+            code: Some(1),
+            stderr: vec!["Invalid API key".to_string()],
+        };
+        log.record_error("s1", &ClaudeError::NotAuthenticated, Some(&exit));
+
+        let recent = log.recent_stderr(3);
+        let recent: Vec<&str> = recent.iter().map(|l| l.line.as_str()).collect();
+        assert_eq!(recent, ["508", "509", "Invalid API key"]);
+        assert_eq!(log.recent_stderr(usize::MAX).len(), MAX_STDERR_LINES);
+
+        let errors = log.recent_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].kind, errors[0].exit_code), (ErrorKind::NotAuthenticated, Some(1)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_mcp_handshakes() {
+        let dir = TempDir::new("mcp");
+        // A notification first, then the response
+        let reply = r#"{"jsonrpc":"2.0","method":"notifications/message"}
+{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-06-18","serverInfo":{"name":"tasks","version":"0.3.0"}}}"#;
+        std::fs::write(dir.join("reply.txt"), reply).unwrap();
+        std::fs::write(
+            dir.join(".mcp.json"),
+            json!({"mcpServers": {
+                "tasks": {"command": "sh", "args": ["-c", "read line && cat reply.txt"]},
+                "broken": {"command": "sh", "args": ["-c", "exit 3"]},
+                "missing": {"command": "/nonexistent/mcp-server"},
+                "legacy": {"type": "sse", "url": "http://127.0.0.1:1/sse?token=secret"},
+            }})
+            .to_string(),
+        )
+        .unwrap();
+
+        // User and local scopes live in .claude.json; the local `tasks`
+        // entry wins over the project's, and `helper` is only found on the
+        // CLI's PATH
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        let helper = bin.join("helper-mcp");
+        std::fs::write(&helper, format!("#!/bin/sh\nread line\ncat {}\n", dir.join("reply.txt").display())).unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let global_config = dir.join(".claude.json");
+        std::fs::write(
+            &global_config,
+            json!({
+                "mcpServers": {"helper": {"command": "helper-mcp"}},
+                "projects": {dir.to_string_lossy(): {"mcpServers": {
+                    "tasks": {"command": "sh", "args": ["-c", "read line && cat reply.txt"]},
+                }}},
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let checks = check_mcp_servers(None, &dir, &global_config, Some(&bin.join("claude"))).await;
+        let by_name: HashMap<&str, &McpServerCheck> =
+            checks.iter().map(|c| (c.name.as_str(), c)).collect();
+        assert_eq!(checks.len(), 5);
+        assert!(by_name["tasks"].source.ends_with("(local)"));
+        assert!(by_name["helper"].source.ends_with("(user)"));
+        assert_eq!(by_name["helper"].status, McpCheckStatus::Ok, "{:?}", by_name["helper"].error);
+
+        let tasks = by_name["tasks"];
+        assert_eq!(tasks.status, McpCheckStatus::Ok, "{:?}", tasks.error);
+        assert_eq!(
+            (tasks.server_name.as_deref(), tasks.server_version.as_deref()),
+            (Some("tasks"), Some("0.3.0"))
+        );
+        assert_eq!(by_name["broken"].status, McpCheckStatus::Failed);
+        assert!(by_name["broken"].error.as_deref().unwrap().contains("code 3"));
+        assert_eq!(by_name["missing"].status, McpCheckStatus::Failed);
+        assert_eq!(by_name["legacy"].status, McpCheckStatus::Skipped);
+        assert_eq!(by_name["legacy"].target, "http://127.0.0.1:1/sse");
+    }
+}
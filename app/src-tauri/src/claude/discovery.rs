@@ -0,0 +1,501 @@
+//! Locating and version-checking the Claude CLI
+//!
+//! The CLI is taken from the path configured in settings, else searched
+//! for on `PATH`, else in the install locations of the common installers
+//! (the native installer, npm global prefixes, nvm, volta and bun). Apps
+//! launched from the dock don't inherit the shell's `PATH`, which is why
+//! the fixed locations matter. The result, including the parsed
+//! `claude --version`, is cached until the configured path changes or the
+//! binary is replaced (e.g. by `claude update`); a failed search is
+//! remembered for `NOT_FOUND_TTL`. The cache isn't locked while a binary is
+//! probed, so a slow `--version` doesn't hold up other callers.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+use super::error::{ClaudeError, Result};
+use super::store::ClaudeStore;
+
+/// Oldest CLI whose flags (`--session-id`, `--append-system-prompt`,
+/// `--input-format stream-json`) match what the app passes
+pub const MIN_CLI_VERSION: CliVersion = CliVersion {
+    major: 1,
+    minor: 0,
+    patch: 0,
+};
+
+/// How long `claude --version` may take before the binary is given up on
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a search that found no CLI is trusted before searching again
+const NOT_FOUND_TTL: Duration = Duration::from_secs(30);
+
+/// Key of the configured path in `claude_settings`
+const SETTINGS_KEY: &str = "cli_path";
+
+#[cfg(windows)]
+const EXECUTABLE_NAMES: &[&str] = &["claude.exe", "claude.cmd"];
+#[cfg(not(windows))]
+const EXECUTABLE_NAMES: &[&str] = &["claude"];
+
+/// A `major.minor.patch` CLI version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CliVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CliVersion {
+    /// Parse `claude --version` output, e.g. `1.0.93 (Claude Code)`
+    pub fn parse(output: &str) -> Option<Self> {
+        let token = output.split_whitespace().next()?;
+        let token = token.strip_prefix('v').unwrap_or(token);
+        // Drop pre-release and build suffixes (`2.0.0-beta.1`, `1.0.0+abc`)
+        let core = token.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u32>());
+        let version = Self {
+            major: parts.next()?.ok()?,
+            minor: parts.next()?.ok()?,
+            patch: parts.next().unwrap_or(Ok(0)).ok()?,
+        };
+        parts.next().is_none().then_some(version)
+    }
+}
+
+impl std::fmt::Display for CliVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Serialize for CliVersion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A CLI binary and its version
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CliInfo {
+    pub path: String,
+    pub version: CliVersion,
+}
+
+impl CliInfo {
+    /// Reject versions older than `MIN_CLI_VERSION`
+    fn check_supported(&self) -> Result<()> {
+        if self.version < MIN_CLI_VERSION {
+            return Err(ClaudeError::CliOutdated {
+                found: self.version.to_string(),
+                required: MIN_CLI_VERSION.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Discovery result for the settings UI
+#[derive(Debug, Clone, Serialize)]
+pub struct CliStatus {
+    pub configured_path: Option<String>,
+    pub cli: Option<CliInfo>,
+    pub min_version: CliVersion,
+    pub error: Option<String>,
+}
+
+/// A probed binary and its modification time when probed
+struct CachedCli {
+    info: CliInfo,
+    modified: Option<SystemTime>,
+}
+
+impl CachedCli {
+    fn new(info: CliInfo) -> Self {
+        let modified = modified(Path::new(&info.path));
+        Self { info, modified }
+    }
+
+    /// Still the same binary as when it was probed
+    fn is_current(&self) -> bool {
+        self.modified.is_some() && modified(Path::new(&self.info.path)) == self.modified
+    }
+}
+
+/// Finds the CLI and caches what it found
+pub struct CliLocator {
+    store: Arc<ClaudeStore>,
+    cache: Mutex<Option<CachedCli>>,
+    /// When a search last came up empty
+    not_found: Mutex<Option<Instant>>,
+}
+
+impl CliLocator {
+    pub fn new(store: Arc<ClaudeStore>) -> Self {
+        Self {
+            store,
+            cache: Mutex::new(None),
+            not_found: Mutex::new(None),
+        }
+    }
+
+    /// Path set by the user, if any
+    pub fn configured_path(&self) -> Option<String> {
+        self.store
+            .get_setting::<Option<String>>(SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("[Claude] Failed to load CLI path: {}", e);
+                None
+            })
+            .flatten()
+    }
+
+    /// Use the CLI at `path` from now on, or go back to discovery with
+    /// `None`; a new path is only saved if it runs and is supported
+    pub fn set_configured_path(&self, path: Option<&str>) -> Result<()> {
+        let info = match path {
+            Some(path) => {
+                let info = probe(Path::new(path))?;
+                info.check_supported()?;
+                Some(info)
+            }
+            None => None,
+        };
+        self.store.set_setting(SETTINGS_KEY, &path)?;
+        *self.cache.lock() = info.map(CachedCli::new);
+        *self.not_found.lock() = None;
+        Ok(())
+    }
+
+    /// The CLI to run, checked against the minimum version
+    pub fn resolve(&self) -> Result<CliInfo> {
+        let cached = {
+            let mut cache = self.cache.lock();
+            if cache.as_ref().is_some_and(|cached| !cached.is_current()) {
+                *cache = None;
+            }
+            cache.as_ref().map(|cached| cached.info.clone())
+        };
+        let info = match cached {
+            Some(info) => info,
+            None => {
+                let info = self.locate()?;
+                *self.cache.lock() = Some(CachedCli::new(info.clone()));
+                info
+            }
+        };
+        info.check_supported()?;
+        Ok(info)
+    }
+
+    /// Find and probe the CLI, without holding the cache
+    fn locate(&self) -> Result<CliInfo> {
+        let path = match self.configured_path() {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let mut not_found = self.not_found.lock();
+                if not_found.is_some_and(|at| at.elapsed() < NOT_FOUND_TTL) {
+                    return Err(ClaudeError::CliNotFound);
+                }
+                let found = discover(std::env::var_os("PATH"), dirs::home_dir(), &|name| {
+                    std::env::var_os(name)
+                });
+                *not_found = found.is_none().then(Instant::now);
+                found.ok_or(ClaudeError::CliNotFound)?
+            }
+        };
+        let info = probe(&path)?;
+        eprintln!("[Claude] Found claude {} at: {}", info.version, info.path);
+        Ok(info)
+    }
+
+    pub fn status(&self) -> CliStatus {
+        let (cli, error) = match self.resolve() {
+            Ok(info) => (Some(info), None),
+            // An outdated CLI is still reported
+            Err(e) => (self.cache.lock().as_ref().map(|c| c.info.clone()), Some(e.to_string())),
+        };
+        CliStatus {
+            configured_path: self.configured_path(),
+            cli,
+            min_version: MIN_CLI_VERSION,
+            error,
+        }
+    }
+}
+
+/// Search `PATH`, then the well-known install locations
+fn discover(
+    path_var: Option<OsString>,
+    home: Option<PathBuf>,
+    var: &dyn Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    let mut dirs: Vec<PathBuf> = path_var
+        .map(|p| std::env::split_paths(&p).collect())
+        .unwrap_or_default();
+    dirs.extend(install_dirs(home.as_deref(), var));
+    dirs.iter()
+        .flat_map(|dir| EXECUTABLE_NAMES.iter().map(move |name| dir.join(name)))
+        .find(|path| is_executable(path))
+}
+
+/// Directories the native installer, npm, nvm, volta and bun put the CLI in
+fn install_dirs(home: Option<&Path>, var: &dyn Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+    let from_var = |name: &str, default: &str| {
+        var(name)
+            .map(PathBuf::from)
+            .or_else(|| home.map(|h| h.join(default)))
+    };
+
+    let mut dirs = Vec::new();
+    if let Some(home) = home {
+        dirs.push(home.join(".claude/local"));
+        dirs.push(home.join(".local/bin"));
+    }
+    if let Some(prefix) = var("NPM_CONFIG_PREFIX") {
+        dirs.push(PathBuf::from(prefix).join("bin"));
+    }
+    if let Some(home) = home {
+        dirs.push(home.join(".npm-global/bin"));
+    }
+    if let Some(appdata) = var("APPDATA") {
+        dirs.push(PathBuf::from(appdata).join("npm"));
+    }
+    if let Some(nvm) = from_var("NVM_DIR", ".nvm") {
+        dirs.extend(nvm_bin_dirs(&nvm.join("versions/node")));
+    }
+    dirs.extend(from_var("VOLTA_HOME", ".volta").map(|d| d.join("bin")));
+    dirs.extend(from_var("BUN_INSTALL", ".bun").map(|d| d.join("bin")));
+    dirs.push(PathBuf::from("/opt/homebrew/bin"));
+    dirs.push(PathBuf::from("/usr/local/bin"));
+    dirs
+}
+
+/// `bin` directories of the installed nvm Node versions, newest first
+fn nvm_bin_dirs(versions_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(versions_dir) else {
+        return Vec::new();
+    };
+    let mut versions: Vec<(Option<CliVersion>, PathBuf)> = entries
+        .flatten()
+        .map(|e| (CliVersion::parse(&e.file_name().to_string_lossy()), e.path()))
+        .collect();
+    versions.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+    versions.into_iter().map(|(_, dir)| dir.join("bin")).collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// `PATH` for running the CLI, with its own directory first
+///
+/// npm-installed CLIs are `#!/usr/bin/env node` scripts; under nvm or
+/// volta, `node` sits next to them but isn't on the app's `PATH`.
+pub fn path_env_for(cli_path: &Path) -> Option<OsString> {
+    let dir = cli_path.parent()?.to_path_buf();
+    let inherited = std::env::var_os("PATH").unwrap_or_default();
+    std::env::join_paths(std::iter::once(dir).chain(std::env::split_paths(&inherited))).ok()
+}
+
+/// Run `claude --version` and parse the result
+///
+/// Callers are synchronous but often on a runtime worker: on the app's
+/// multi-threaded runtime the worker hands its other tasks off while the
+/// probe runs; elsewhere (no runtime, or a single-threaded one in tests)
+/// the probe gets a runtime of its own on a scoped thread.
+fn probe(path: &Path) -> Result<CliInfo> {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| handle.block_on(probe_version(path)))
+        }
+        _ => std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?
+                        .block_on(probe_version(path))
+                })
+                .join()
+                .unwrap_or_else(|_| Err(ClaudeError::CliError("CLI probe panicked".to_string())))
+        }),
+    }
+}
+
+async fn probe_version(path: &Path) -> Result<CliInfo> {
+    if !is_executable(path) {
+        return Err(ClaudeError::InvalidOption(format!(
+            "Not an executable file: {}",
+            path.display()
+        )));
+    }
+
+    let mut command = tokio::process::Command::new(path);
+    command
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // Dropping the child on timeout kills it
+        .kill_on_drop(true);
+    if let Some(path_env) = path_env_for(path) {
+        command.env("PATH", path_env);
+    }
+    let child = command
+        .spawn()
+        .map_err(|e| ClaudeError::SpawnFailed(format!("{}: {}", path.display(), e)))?;
+
+    let output = tokio::time::timeout(VERSION_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| {
+            ClaudeError::CliError(format!(
+                "`{} --version` did not finish within {} seconds",
+                path.display(),
+                VERSION_TIMEOUT.as_secs()
+            ))
+        })??;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let version = CliVersion::parse(&stdout).ok_or_else(|| {
+        ClaudeError::CliError(format!(
+            "Unrecognised `claude --version` output: {}",
+            stdout.trim()
+        ))
+    })?;
+    Ok(CliInfo {
+        path: path.to_string_lossy().to_string(),
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testutil::TempDir;
+    use std::collections::HashMap;
+
+    fn version(major: u32, minor: u32, patch: u32) -> CliVersion {
+        CliVersion { major, minor, patch }
+    }
+
+    /// Write an executable script printing `version_output`
+    #[cfg(unix)]
+    fn fake_cli(dir: &Path, version_output: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join("claude");
+        std::fs::write(&path, format!("#!/bin/sh\necho '{}'\n", version_output)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(CliVersion::parse("1.0.93 (Claude Code)\n"), Some(version(1, 0, 93)));
+        assert_eq!(CliVersion::parse("v2.0.1"), Some(version(2, 0, 1)));
+        assert_eq!(CliVersion::parse("2.1.0-beta.3 (Claude Code)"), Some(version(2, 1, 0)));
+        assert_eq!(CliVersion::parse("v18.20"), Some(version(18, 20, 0)));
+        assert_eq!(CliVersion::parse("command not found"), None);
+        assert_eq!(CliVersion::parse("1.2.3.4"), None);
+        assert!(version(0, 2, 125) < MIN_CLI_VERSION);
+        assert_eq!(version(1, 0, 93).to_string(), "1.0.93");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discovery_order() {
+        let home = TempDir::new("discovery");
+        let nvm = home.join(".nvm/versions/node");
+        let old_node = fake_cli(&nvm.join("v18.20.0/bin"), "1.0.0");
+        let new_node = fake_cli(&nvm.join("v20.11.1/bin"), "1.0.0");
+        let no_env = |_: &str| None;
+
+        // Newest nvm Node wins over older ones
+        assert_eq!(discover(None, Some(home.to_path_buf()), &no_env), Some(new_node.clone()));
+
+        // volta (here via VOLTA_HOME) is searched after nvm
+        let volta = home.join("volta");
+        let volta_cli = fake_cli(&volta.join("bin"), "1.0.0");
+        let env = HashMap::from([("VOLTA_HOME", volta.into_os_string())]);
+        let lookup = |name: &str| env.get(name).cloned();
+        assert_eq!(discover(None, Some(home.to_path_buf()), &lookup), Some(new_node));
+        std::fs::remove_file(nvm.join("v20.11.1/bin/claude")).unwrap();
+        std::fs::remove_file(&old_node).unwrap();
+        assert_eq!(discover(None, Some(home.to_path_buf()), &lookup), Some(volta_cli));
+
+        // PATH comes first; non-executable files are skipped
+        let on_path = home.join("on-path");
+        std::fs::create_dir_all(&on_path).unwrap();
+        std::fs::write(on_path.join("claude"), "not executable").unwrap();
+        let path_cli = fake_cli(&home.join("also-on-path"), "1.0.0");
+        let path_var = std::env::join_paths([on_path, home.join("also-on-path")]).unwrap();
+        assert_eq!(discover(Some(path_var), Some(home.to_path_buf()), &lookup), Some(path_cli));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_configured_path_and_version_check() {
+        let dir = TempDir::new("discovery");
+        let locator = CliLocator::new(Arc::new(ClaudeStore::open_in_memory().unwrap()));
+
+        let old = fake_cli(&dir.join("old"), "0.2.125 (Claude Code)");
+        assert!(matches!(
+            locator.set_configured_path(Some(old.to_str().unwrap())),
+            Err(ClaudeError::CliOutdated { .. })
+        ));
+        assert_eq!(locator.configured_path(), None);
+
+        let current = fake_cli(&dir.join("current"), "1.0.93 (Claude Code)");
+        locator.set_configured_path(Some(current.to_str().unwrap())).unwrap();
+        let info = locator.resolve().unwrap();
+        assert_eq!((info.path.as_str(), info.version), (current.to_str().unwrap(), version(1, 0, 93)));
+
+        // Removing or replacing the binary invalidates the cache
+        std::fs::remove_file(&current).unwrap();
+        assert!(matches!(locator.resolve(), Err(ClaudeError::InvalidOption(_))));
+        fake_cli(&dir.join("current"), "0.9.0");
+        assert!(matches!(locator.resolve(), Err(ClaudeError::CliOutdated { .. })));
+        let status = locator.status();
+        assert_eq!(status.cli.map(|c| c.version), Some(version(0, 9, 0)));
+        assert!(status.error.is_some());
+
+        locator.set_configured_path(None).unwrap();
+        assert_eq!(locator.configured_path(), None);
+
+        // A search that came up empty isn't repeated right away
+        *locator.not_found.lock() = Some(Instant::now());
+        assert!(matches!(locator.resolve(), Err(ClaudeError::CliNotFound)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_probe_on_runtime_worker() {
+        let dir = TempDir::new("discovery");
+        let current = fake_cli(&dir.join("current"), "1.0.93 (Claude Code)");
+
+        let locator = CliLocator::new(Arc::new(ClaudeStore::open_in_memory().unwrap()));
+        locator.set_configured_path(Some(current.to_str().unwrap())).unwrap();
+        assert_eq!(locator.resolve().unwrap().version, version(1, 0, 93));
+    }
+}
@@ -1,13 +1,19 @@
 //! Error types for Claude CLI integration
 
+use serde::Serialize;
 use thiserror::Error;
 
+use super::manager::SessionStatus;
+
 #[derive(Error, Debug)]
 #[allow(dead_code)]
 pub enum ClaudeError {
     #[error("Claude CLI not found. Please install Claude Code CLI.")]
     CliNotFound,
 
+    #[error("Claude CLI {found} is too old; version {required} or newer is required. Please run 'claude update'.")]
+    CliOutdated { found: String, required: String },
+
     #[error("Claude CLI not authenticated. Please run 'claude login' first.")]
     NotAuthenticated,
 
@@ -29,14 +35,191 @@ pub enum ClaudeError {
     #[error("Session already exists: {0}")]
     SessionAlreadyExists(String),
 
+    #[error("Session is not running: {0}")]
+    SessionNotRunning(String),
+
+    #[error("Message queue is full ({0} messages)")]
+    QueueFull(usize),
+
+    #[error("Queued message not found: {0}")]
+    QueuedMessageNotFound(String),
+
+    #[error("Working directory does not exist: {0}")]
+    InvalidWorkingDir(String),
+
+    #[error("Invalid session option: {0}")]
+    InvalidOption(String),
+
+    #[error("Preset not found: {0}")]
+    PresetNotFound(String),
+
     #[error("Failed to send message: {0}")]
     SendFailed(String),
 
+    #[error("Message is too large ({size} bytes, limit is {limit} bytes)")]
+    PromptTooLarge { size: usize, limit: usize },
+
     #[error("Process terminated unexpectedly")]
     ProcessTerminated,
 
+    #[error("Rate limit reached: {0}")]
+    RateLimited(String),
+
+    #[error("Claude is overloaded right now. Please try again shortly.")]
+    Overloaded,
+
+    #[error("Claude CLI error: {0}")]
+    CliError(String),
+
+    #[error("No Anthropic API key configured. Add one in the Claude settings.")]
+    ApiKeyMissing,
+
+    #[error("The Anthropic API rejected the API key. Check the key in the Claude settings.")]
+    ApiKeyRejected,
+
+    #[error("Anthropic API error: {0}")]
+    ApiError(String),
+
+    #[error("Claude {kind} within {secs} seconds and was stopped")]
+    Timeout { kind: TimeoutKind, secs: u64 },
+
+    #[error("No response in progress")]
+    NoResponseInProgress,
+
+    #[error("Invalid session transition from {from:?} to {to:?}")]
+    InvalidTransition { from: SessionStatus, to: SessionStatus },
+
+    #[error("Conversation not found: {0}")]
+    ConversationNotFound(String),
+
+    #[error("Conversation is open in a session: {0}")]
+    ConversationInUse(String),
+
     #[error("Failed to parse conversation history: {0}")]
     HistoryParseError(String),
+
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+}
+
+/// Coarse error category sent to the frontend with `claude:error`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    CliNotFound,
+    CliOutdated,
+    NotAuthenticated,
+    RateLimited,
+    Overloaded,
+    ProcessTerminated,
+    Timeout,
+    Other,
+}
+
+/// Which time limit a message exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutKind {
+    /// The overall per-message deadline
+    Deadline,
+    /// No output for longer than the idle timeout
+    Idle,
+}
+
+impl std::fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TimeoutKind::Deadline => "did not finish",
+            TimeoutKind::Idle => "produced no output",
+        })
+    }
+}
+
+/// Substrings identifying known CLI failures (matched case-insensitively)
+const AUTH_PATTERNS: &[&str] = &[
+    "invalid api key",
+    "please run /login",
+    "not logged in",
+    "authentication_error",
+    "oauth token has expired",
+    "api error: 401",
+];
+const RATE_LIMIT_PATTERNS: &[&str] = &[
+    "rate_limit",
+    "rate limit",
+    "usage limit reached",
+    "api error: 429",
+];
+const OVERLOADED_PATTERNS: &[&str] = &["overloaded", "api error: 529"];
+
+impl ClaudeError {
+    /// Category of the error, for the frontend
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ClaudeError::CliNotFound => ErrorKind::CliNotFound,
+            ClaudeError::CliOutdated { .. } => ErrorKind::CliOutdated,
+            ClaudeError::NotAuthenticated
+            | ClaudeError::ApiKeyMissing
+            | ClaudeError::ApiKeyRejected => ErrorKind::NotAuthenticated,
+            ClaudeError::RateLimited(_) => ErrorKind::RateLimited,
+            ClaudeError::Overloaded => ErrorKind::Overloaded,
+            ClaudeError::ProcessTerminated => ErrorKind::ProcessTerminated,
+            ClaudeError::Timeout { .. } => ErrorKind::Timeout,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Recognise a known failure in CLI output (stderr lines or the text
+    /// of an error `result` event)
+    pub fn from_cli_output(output: &str) -> Option<ClaudeError> {
+        if matches_any(output, AUTH_PATTERNS) {
+            Some(ClaudeError::NotAuthenticated)
+        } else if matches_any(output, RATE_LIMIT_PATTERNS) {
+            let line = output
+                .lines()
+                .find(|l| matches_any(l, RATE_LIMIT_PATTERNS))
+                .unwrap_or(output);
+            Some(ClaudeError::RateLimited(describe_rate_limit(line)))
+        } else if matches_any(output, OVERLOADED_PATTERNS) {
+            Some(ClaudeError::Overloaded)
+        } else {
+            None
+        }
+    }
+
+    /// Classify an error message streamed by the CLI, keeping the raw text
+    /// when it isn't a known failure
+    pub fn from_cli_message(message: &str) -> ClaudeError {
+        Self::from_cli_output(message).unwrap_or_else(|| {
+            let message = message.trim();
+            ClaudeError::CliError(if message.is_empty() {
+                "Claude returned an error without details".to_string()
+            } else {
+                message.to_string()
+            })
+        })
+    }
+}
+
+fn matches_any(line: &str, patterns: &[&str]) -> bool {
+    let lower = line.to_lowercase();
+    patterns.iter().any(|p| lower.contains(p))
+}
+
+/// The CLI reports subscription limits as `Claude AI usage limit reached|<unix time>`
+fn describe_rate_limit(line: &str) -> String {
+    let line = line.trim();
+    if let Some((text, reset)) = line.rsplit_once('|') {
+        if let Some(reset) = reset
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+        {
+            return format!("{} (resets {})", text.trim(), reset.to_rfc3339());
+        }
+    }
+    line.to_string()
 }
 
 impl serde::Serialize for ClaudeError {
@@ -49,3 +232,28 @@ impl serde::Serialize for ClaudeError {
 }
 
 pub type Result<T> = std::result::Result<T, ClaudeError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_cli_output() {
+        assert!(matches!(
+            ClaudeError::from_cli_output("Invalid API key · Please run /login"),
+            Some(ClaudeError::NotAuthenticated)
+        ));
+        assert!(matches!(
+            ClaudeError::from_cli_output(r#"API Error: 529 {"type":"error","error":{"type":"overloaded_error"}}"#),
+            Some(ClaudeError::Overloaded)
+        ));
+        match ClaudeError::from_cli_output("Claude AI usage limit reached|1760000000") {
+            Some(ClaudeError::RateLimited(detail)) => {
+                assert!(detail.starts_with("Claude AI usage limit reached (resets 2025-"));
+            }
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert!(ClaudeError::from_cli_output("Done").is_none());
+        assert_eq!(ClaudeError::from_cli_message("").kind(), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,108 @@
+//! Delivery of `claude:*` events to the frontend
+//!
+//! Sessions emit through an `EventEmitter` rather than a Tauri `AppHandle`
+//! directly, so the chat flow can run (and be tested) without a window.
+
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+
+/// Destination for serialized events
+pub trait EventSink: Send + Sync {
+    fn emit_event(&self, event: &str, payload: serde_json::Value);
+}
+
+impl EventSink for AppHandle {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        if let Err(e) = Emitter::emit(self, event, payload) {
+            eprintln!("[Claude] Failed to emit {}: {}", event, e);
+        }
+    }
+}
+
+/// Cloneable handle to an event sink
+#[derive(Clone)]
+pub struct EventEmitter(Arc<dyn EventSink>);
+
+impl EventEmitter {
+    pub fn new(sink: impl EventSink + 'static) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    /// Serialize `payload` and send it as `event`
+    pub fn emit<T: Serialize>(&self, event: &str, payload: T) {
+        match serde_json::to_value(payload) {
+            Ok(value) => self.0.emit_event(event, value),
+            Err(e) => eprintln!("[Claude] Failed to serialize {}: {}", event, e),
+        }
+    }
+}
+
+impl From<AppHandle> for EventEmitter {
+    fn from(app: AppHandle) -> Self {
+        Self::new(app)
+    }
+}
+
+/// Sink that keeps every event, for tests
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    events: Arc<parking_lot::Mutex<Vec<(String, serde_json::Value)>>>,
+}
+
+#[cfg(test)]
+impl RecordingSink {
+    pub fn emitter(&self) -> EventEmitter {
+        EventEmitter::new(self.clone())
+    }
+
+    /// Payloads of every `event` received so far
+    pub fn payloads(&self, event: &str) -> Vec<serde_json::Value> {
+        self.events
+            .lock()
+            .iter()
+            .filter(|(name, _)| name == event)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+
+    /// Wait (up to 5s) for an `event` whose payload matches `predicate`
+    pub async fn wait_for(
+        &self,
+        event: &str,
+        predicate: impl Fn(&serde_json::Value) -> bool,
+    ) -> serde_json::Value {
+        self.wait_for_count(event, predicate, 1).await.remove(0)
+    }
+
+    /// Wait (up to 5s) until `count` `event`s match `predicate`
+    pub async fn wait_for_count(
+        &self,
+        event: &str,
+        predicate: impl Fn(&serde_json::Value) -> bool,
+        count: usize,
+    ) -> Vec<serde_json::Value> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let matching: Vec<_> = self.payloads(event).into_iter().filter(|p| predicate(p)).collect();
+            if matching.len() >= count {
+                return matching;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "timed out waiting for {}; got {:?}",
+                event,
+                self.events.lock()
+            );
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+impl EventSink for RecordingSink {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        self.events.lock().push((event.to_string(), payload));
+    }
+}
@@ -0,0 +1,411 @@
+//! Markdown export of conversations into the Obsidian vault
+//!
+//! The project directory doubles as an Obsidian vault. Each conversation
+//! becomes one note: YAML frontmatter (session id, project, dates, token
+//! totals), then the messages under `## You` and `## Claude` headings, with
+//! tool calls and thinking in collapsed callouts. Exports are recorded in
+//! `claude_exports`: exporting again overwrites the same note, and syncing
+//! only writes conversations that have never been exported.
+
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use super::error::{ClaudeError, Result};
+use super::pty::Usage;
+use super::sessions::ConversationInfo;
+use super::store::ClaudeStore;
+use super::transcript::{load_messages, token_usage, HistoryContent, HistoryMessage};
+
+const SETTINGS_KEY: &str = "export";
+
+/// Vault folder for notes unless configured otherwise
+const DEFAULT_FOLDER: &str = "Claude";
+
+/// Longest tool input or result kept in a note, in characters
+const MAX_TOOL_CHARS: usize = 4000;
+
+/// Characters Obsidian or the filesystem don't allow in note names
+const UNSAFE_NAME_CHARS: &str = "\\/:*?\"<>|#^[]";
+
+/// Where exported notes go
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportSettings {
+    /// Folder relative to the vault root, or an absolute path
+    pub folder: String,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            folder: DEFAULT_FOLDER.to_string(),
+        }
+    }
+}
+
+impl ExportSettings {
+    pub fn validate(&self) -> Result<()> {
+        let folder = Path::new(self.folder.trim());
+        if folder.as_os_str().is_empty() {
+            return Err(ClaudeError::InvalidOption("Export folder is empty".to_string()));
+        }
+        if folder.components().any(|c| c == Component::ParentDir) {
+            return Err(ClaudeError::InvalidOption(
+                "Export folder must not contain '..'".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The export folder for the vault at `vault_root`
+    pub fn resolve(&self, vault_root: &Path) -> PathBuf {
+        vault_root.join(self.folder.trim())
+    }
+}
+
+/// A note written by an export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportedNote {
+    pub conversation_id: String,
+    pub path: String,
+}
+
+impl ClaudeStore {
+    pub fn export_settings(&self) -> ExportSettings {
+        self.get_setting(SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("[Claude] Failed to load export settings: {}", e);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save_export_settings(&self, settings: &ExportSettings) -> Result<()> {
+        settings.validate()?;
+        self.set_setting(SETTINGS_KEY, settings)
+    }
+
+    /// Ids of every conversation exported so far
+    pub fn exported_conversations(&self) -> Result<HashSet<String>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT conversation_id FROM claude_exports")?;
+            let ids = stmt.query_map([], |row| row.get(0))?;
+            ids.collect()
+        })
+    }
+
+    fn export_path(&self, id: &str) -> Result<Option<String>> {
+        self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT path FROM claude_exports WHERE conversation_id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+    }
+
+    fn record_export(&self, id: &str, path: &str) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO claude_exports (conversation_id, path) VALUES (?1, ?2)
+                 ON CONFLICT(conversation_id) DO UPDATE SET path = excluded.path,
+                     exported_at = datetime('now')",
+                params![id, path],
+            )
+            .map(|_| ())
+        })
+    }
+}
+
+/// Write the note for a conversation into the vault at `vault_root`,
+/// replacing the note of an earlier export if it still exists
+pub fn export_conversation(
+    store: &ClaudeStore,
+    vault_root: &Path,
+    project: &str,
+    info: &ConversationInfo,
+    transcript: &Path,
+) -> Result<ExportedNote> {
+    let messages = load_messages(transcript)?;
+    let usage = token_usage(transcript)?;
+    let note = render_note(info, project, &usage, &messages);
+
+    let path = match store.export_path(&info.id)?.map(PathBuf::from) {
+        Some(previous) if previous.exists() => previous,
+        _ => {
+            let folder = store.export_settings().resolve(vault_root);
+            std::fs::create_dir_all(&folder)?;
+            folder.join(note_file_name(info))
+        }
+    };
+    std::fs::write(&path, note)?;
+
+    let path = path.to_string_lossy().to_string();
+    store.record_export(&info.id, &path)?;
+    Ok(ExportedNote {
+        conversation_id: info.id.clone(),
+        path,
+    })
+}
+
+/// `2025-06-01 Weekly planning (1a2b3c4d).md`; the id keeps names unique
+fn note_file_name(info: &ConversationInfo) -> String {
+    let title = info.title.as_deref().unwrap_or("Conversation");
+    let title: String = title
+        .chars()
+        .filter(|c| !UNSAFE_NAME_CHARS.contains(*c) && !c.is_control())
+        .take(60)
+        .collect();
+    // Titles cut short end in "..."
+    let title = title.trim().trim_end_matches('.').trim_end();
+    let short_id: String = info.id.chars().take(8).collect();
+    format!("{} {} ({}).md", info.created_at.format("%Y-%m-%d"), title, short_id)
+}
+
+/// Render a conversation as an Obsidian note
+pub fn render_note(
+    info: &ConversationInfo,
+    project: &str,
+    usage: &Usage,
+    messages: &[HistoryMessage],
+) -> String {
+    let title = info.title.as_deref().unwrap_or("Untitled conversation");
+    // JSON strings are valid YAML double-quoted scalars
+    let quote = |s: &str| serde_json::to_string(s).unwrap_or_default();
+
+    let mut note = String::new();
+    note.push_str("---\n");
+    note.push_str(&format!("title: {}\n", quote(title)));
+    note.push_str(&format!("session_id: {}\n", info.id));
+    note.push_str(&format!("project: {}\n", quote(project)));
+    note.push_str(&format!("created: {}\n", info.created_at.to_rfc3339()));
+    note.push_str(&format!("updated: {}\n", info.updated_at.to_rfc3339()));
+    note.push_str(&format!("messages: {}\n", info.message_count));
+    note.push_str(&format!("input_tokens: {}\n", usage.input_tokens));
+    note.push_str(&format!("output_tokens: {}\n", usage.output_tokens));
+    note.push_str(&format!(
+        "cache_creation_input_tokens: {}\n",
+        usage.cache_creation_input_tokens
+    ));
+    note.push_str(&format!("cache_read_input_tokens: {}\n", usage.cache_read_input_tokens));
+    note.push_str("tags:\n  - claude\n");
+    for tag in &info.tags {
+        // Obsidian tags can't contain spaces
+        let tag = tag.name.split_whitespace().collect::<Vec<_>>().join("-");
+        note.push_str(&format!("  - {}\n", quote(&tag)));
+    }
+    note.push_str("---\n\n");
+    note.push_str(&format!("# {}\n", title));
+
+    // Results are shown inside the callout of their tool call
+    let results: HashMap<&str, (&str, bool)> = messages
+        .iter()
+        .filter_map(|m| match &m.content {
+            HistoryContent::ToolResult {
+                tool_use_id,
+                content,
+                is_error,
+                ..
+            } => Some((tool_use_id.as_str(), (content.as_str(), *is_error))),
+            _ => None,
+        })
+        .collect();
+    let calls: HashSet<&str> = messages
+        .iter()
+        .filter_map(|m| match &m.content {
+            HistoryContent::ToolUse { id, .. } => Some(id.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut speaker = None;
+    for message in messages {
+        let (heading, block) = match &message.content {
+            HistoryContent::User { content } => ("You", content.clone()),
+            HistoryContent::Assistant { content } => ("Claude", content.clone()),
+            HistoryContent::Thinking { content } => ("Claude", callout("abstract", "Thinking", content)),
+            HistoryContent::ToolUse { id, name, input } => {
+                let input = serde_json::to_string_pretty(input).unwrap_or_default();
+                let mut body = code_block(&clip(&input), "json");
+                let mut kind = "example";
+                if let Some((content, is_error)) = results.get(id.as_str()) {
+                    body.push_str("\n\n**Result**\n\n");
+                    body.push_str(&code_block(&clip(content), ""));
+                    if *is_error {
+                        kind = "failure";
+                    }
+                }
+                ("Claude", callout(kind, &format!("Tool: {}", name), &body))
+            }
+            HistoryContent::ToolResult {
+                tool_use_id,
+                name,
+                content,
+                is_error,
+            } => {
+                if calls.contains(tool_use_id.as_str()) {
+                    continue;
+                }
+                let kind = if *is_error { "failure" } else { "example" };
+                let title = format!("Result: {}", name.as_deref().unwrap_or("tool"));
+                ("Claude", callout(kind, &title, &code_block(&clip(content), "")))
+            }
+        };
+        if speaker != Some(heading) {
+            note.push_str(&format!("\n## {}\n", heading));
+            speaker = Some(heading);
+        }
+        note.push('\n');
+        note.push_str(block.trim_end());
+        note.push('\n');
+    }
+
+    note
+}
+
+/// A collapsed callout (`> [!kind]- title`) holding `body`
+fn callout(kind: &str, title: &str, body: &str) -> String {
+    let mut out = format!("> [!{}]- {}\n", kind, title);
+    for line in body.lines() {
+        if line.is_empty() {
+            out.push_str(">\n");
+        } else {
+            out.push_str("> ");
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Fenced code block, with a fence longer than any backtick run in `content`
+fn code_block(content: &str, lang: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}{}\n{}\n{}", fence, lang, content.trim_end(), fence)
+}
+
+/// Keep long tool output from swamping the note
+fn clip(text: &str) -> String {
+    match text.char_indices().nth(MAX_TOOL_CHARS) {
+        Some((cut, _)) => format!("{}\n... (truncated)", &text[..cut]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::metadata::ConversationTag;
+    use super::super::testutil::TempDir;
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn info(title: Option<&str>) -> ConversationInfo {
+        ConversationInfo {
+            id: "1a2b3c4d-0000-4000-8000-000000000000".to_string(),
+            title: title.map(str::to_string),
+            preview: None,
+            created_at: Utc.with_ymd_and_hms(2025, 6, 1, 10, 0, 0).unwrap(),
+            updated_at: Utc.with_ymd_and_hms(2025, 6, 1, 10, 5, 0).unwrap(),
+            message_count: 2,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+            forked_from: None,
+        }
+    }
+
+    fn message(content: HistoryContent) -> HistoryMessage {
+        HistoryMessage {
+            uuid: None,
+            timestamp: None,
+            content,
+        }
+    }
+
+    #[test]
+    fn test_render_note() {
+        let messages = vec![
+            message(HistoryContent::User { content: "Fix the DATEV export".to_string() }),
+            message(HistoryContent::ToolUse {
+                id: "t1".to_string(),
+                name: "Read".to_string(),
+                input: serde_json::json!({"file_path": "export.ts"}),
+            }),
+            message(HistoryContent::ToolResult {
+                tool_use_id: "t1".to_string(),
+                name: Some("Read".to_string()),
+                content: "```ts\nexport {}\n```".to_string(),
+                is_error: false,
+            }),
+            message(HistoryContent::Assistant { content: "Done.".to_string() }),
+        ];
+        let usage = Usage {
+            input_tokens: 12,
+            output_tokens: 34,
+            ..Usage::default()
+        };
+        let mut info = info(Some("DATEV \"export\""));
+        info.tags.push(ConversationTag {
+            id: "t".to_string(),
+            name: "tax return".to_string(),
+            color: None,
+        });
+        let note = render_note(&info, "/work/app", &usage, &messages);
+
+        assert!(note.starts_with("---\ntitle: \"DATEV \\\"export\\\"\"\nsession_id: 1a2b3c4d-"));
+        assert!(note.contains("\nproject: \"/work/app\"\ncreated: 2025-06-01T10:00:00+00:00\n"));
+        assert!(note.contains("\ninput_tokens: 12\noutput_tokens: 34\n"));
+        assert!(note.contains("\ntags:\n  - claude\n  - \"tax-return\"\n---\n"));
+        let body = note.split("\n---\n").nth(1).unwrap();
+        assert_eq!(
+            body,
+            "\n# DATEV \"export\"\n\n## You\n\nFix the DATEV export\n\n## Claude\n\n\
+             > [!example]- Tool: Read\n> ```json\n> {\n>   \"file_path\": \"export.ts\"\n> }\n> ```\n>\n\
+             > **Result**\n>\n> ````\n> ```ts\n> export {}\n> ```\n> ````\n\nDone.\n"
+        );
+    }
+
+    #[test]
+    fn test_note_file_name_and_settings() {
+        assert_eq!(
+            note_file_name(&info(Some("What's in src/lib.rs? A rather long question..."))),
+            "2025-06-01 What's in srclib.rs A rather long question (1a2b3c4d).md"
+        );
+        assert_eq!(note_file_name(&info(None)), "2025-06-01 Conversation (1a2b3c4d).md");
+
+        let store = ClaudeStore::open_in_memory().unwrap();
+        assert_eq!(store.export_settings(), ExportSettings::default());
+        assert!(store.save_export_settings(&ExportSettings { folder: "../outside".to_string() }).is_err());
+        assert!(store.save_export_settings(&ExportSettings { folder: " ".to_string() }).is_err());
+        store.save_export_settings(&ExportSettings { folder: "Notes/Claude".to_string() }).unwrap();
+        assert_eq!(store.export_settings().resolve(Path::new("/vault")), Path::new("/vault/Notes/Claude"));
+    }
+
+    #[test]
+    fn test_export_conversation() {
+        let dir = TempDir::new("export");
+        let transcript = dir.join("conv.jsonl");
+        let lines = [
+            r#"{"type":"user","uuid":"u1","message":{"role":"user","content":"Hello"}}"#,
+            r#"{"type":"assistant","uuid":"a1","message":{"id":"msg_1","content":[{"type":"text","text":"Hi"}],"usage":{"input_tokens":5,"output_tokens":1}}}"#,
+            r#"{"type":"assistant","uuid":"a2","message":{"id":"msg_1","content":[{"type":"text","text":"there"}],"usage":{"input_tokens":5,"output_tokens":7}}}"#,
+        ];
+        std::fs::write(&transcript, lines.join("\n") + "\n").unwrap();
+
+        let store = ClaudeStore::open_in_memory().unwrap();
+        let note = export_conversation(&store, &dir, "/work/app", &info(Some("Greeting")), &transcript).unwrap();
+        assert!(note.path.ends_with("Claude/2025-06-01 Greeting (1a2b3c4d).md"));
+        let text = std::fs::read_to_string(&note.path).unwrap();
+        assert!(text.contains("\ninput_tokens: 5\noutput_tokens: 7\n"));
+        assert!(store.exported_conversations().unwrap().contains(&note.conversation_id));
+
+        // A re-export keeps the note where it is, even after a rename
+        let again = export_conversation(&store, &dir, "/work/app", &info(Some("Renamed")), &transcript).unwrap();
+        assert_eq!(again.path, note.path);
+        assert!(std::fs::read_to_string(&note.path).unwrap().contains("# Renamed"));
+    }
+}
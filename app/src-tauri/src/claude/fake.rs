@@ -0,0 +1,208 @@
+//! Scripted stand-in for the Claude CLI, for tests
+//!
+//! Replays recorded stream-json fixtures (see `fixtures/`) through the same
+//! reader, watchdog and cancellation code as the real process. Besides
+//! stream-json events, a fixture may contain directives:
+//!
+//! ```text
+//! # delay <ms>     pause before the next line
+//! # stderr <text>  add a line to the process's stderr
+//! # exit <code>    exit status once output ends (default 0)
+//! # hang           stop producing output until killed
+//! ```
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use super::backend::{AssistantBackend, BackendFactory, BoxFuture};
+use super::error::Result;
+use super::pty::{read_turn, KillHandle, ParsedOutput, ProcessConfig, ProcessExit, SessionArg, TurnOutput};
+
+/// Recorded CLI sessions, by fixture name
+pub const TEXT_AND_TOOL: &str = include_str!("fixtures/text_and_tool.jsonl");
+pub const API_ERROR: &str = include_str!("fixtures/api_error.jsonl");
+pub const AUTH_FAILURE: &str = include_str!("fixtures/auth_failure.jsonl");
+pub const SLOW_STREAM: &str = include_str!("fixtures/slow_stream.jsonl");
+pub const HANG: &str = include_str!("fixtures/hang.jsonl");
+
+#[derive(Debug, Clone)]
+enum Step {
+    Line(String),
+    Delay(Duration),
+    Hang,
+}
+
+/// A parsed fixture
+#[derive(Debug, Clone, Default)]
+struct Script {
+    steps: Vec<Step>,
+    stderr: Vec<String>,
+    exit_code: i32,
+}
+
+impl Script {
+    fn parse(fixture: &str) -> Self {
+        let mut script = Script::default();
+        for line in fixture.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let Some(directive) = line.strip_prefix('#') else {
+                script.steps.push(Step::Line(line.to_string()));
+                continue;
+            };
+            let (name, arg) = directive.trim().split_once(' ').unwrap_or((directive.trim(), ""));
+            match name {
+                "delay" => script.steps.push(Step::Delay(Duration::from_millis(
+                    arg.parse().expect("delay in ms"),
+                ))),
+                "stderr" => script.stderr.push(arg.to_string()),
+                "exit" => script.exit_code = arg.parse().expect("exit code"),
+                "hang" => script.steps.push(Step::Hang),
+                other => panic!("unknown fixture directive: {}", other),
+            }
+        }
+        script
+    }
+}
+
+/// Hands out one fixture per turn, in order, and records the prompts sent
+#[derive(Default)]
+pub struct FakeBackendFactory {
+    scripts: Mutex<VecDeque<Script>>,
+    prompts: Arc<Mutex<Vec<String>>>,
+    sessions: Arc<Mutex<Vec<SessionArg>>>,
+    /// Report the backend as missing, as if the CLI was uninstalled
+    unavailable: AtomicBool,
+}
+
+impl FakeBackendFactory {
+    pub fn new(fixtures: &[&str]) -> Self {
+        Self {
+            scripts: Mutex::new(fixtures.iter().map(|f| Script::parse(f)).collect()),
+            prompts: Arc::default(),
+            sessions: Arc::default(),
+            unavailable: AtomicBool::new(false),
+        }
+    }
+
+    /// Prompts received so far, in dispatch order
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().clone()
+    }
+
+    pub fn set_available(&self, available: bool) {
+        self.unavailable.store(!available, Ordering::SeqCst);
+    }
+
+    /// The conversation each prompt was sent to
+    pub fn session_args(&self) -> Vec<SessionArg> {
+        self.sessions.lock().clone()
+    }
+}
+
+impl BackendFactory for FakeBackendFactory {
+    fn is_available(&self) -> bool {
+        !self.unavailable.load(Ordering::SeqCst)
+    }
+
+    fn create(&self, config: &ProcessConfig) -> Box<dyn AssistantBackend> {
+        let script = self
+            .scripts
+            .lock()
+            .pop_front()
+            .expect("fake backend ran out of fixtures");
+        Box::new(FakeBackend {
+            config: config.clone(),
+            script,
+            prompts: Arc::clone(&self.prompts),
+            sessions: Arc::clone(&self.sessions),
+            kill_handle: KillHandle::default(),
+            last_exit: None,
+        })
+    }
+}
+
+/// One scripted turn
+pub struct FakeBackend {
+    config: ProcessConfig,
+    script: Script,
+    prompts: Arc<Mutex<Vec<String>>>,
+    sessions: Arc<Mutex<Vec<SessionArg>>>,
+    kill_handle: KillHandle,
+    last_exit: Option<ProcessExit>,
+}
+
+impl FakeBackend {
+    async fn run(
+        &mut self,
+        message: &str,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<Option<String>> {
+        self.config.check_prompt_size(message)?;
+        self.prompts.lock().push(message.to_string());
+
+        // The script plays the CLI's stdout into a pipe read by `read_turn`
+        let (reader, mut writer) = tokio::io::duplex(64 * 1024);
+        let steps = self.script.steps.clone();
+        let player = tokio::spawn(async move {
+            for step in steps {
+                match step {
+                    Step::Line(line) => {
+                        if writer.write_all(format!("{}\n", line).as_bytes()).await.is_err() {
+                            return;
+                        }
+                    }
+                    Step::Delay(delay) => tokio::time::sleep(delay).await,
+                    Step::Hang => std::future::pending::<()>().await,
+                }
+            }
+        });
+
+        let TurnOutput { session_id, timeout } =
+            read_turn(reader, &self.kill_handle, self.config.timeouts(), &output_tx).await;
+        player.abort();
+
+        if let Some(e) = timeout {
+            self.last_exit = Some(ProcessExit::default());
+            return Err(e);
+        }
+        if self.kill_handle.is_cancelled() {
+            self.last_exit = Some(ProcessExit::default());
+            return Ok(session_id);
+        }
+
+        let exit = ProcessExit {
+            code: Some(self.script.exit_code),
+            stderr: self.script.stderr.clone(),
+        };
+        let error = exit.error();
+        self.last_exit = Some(exit);
+        match error {
+            Some(e) => Err(e),
+            None => Ok(session_id),
+        }
+    }
+}
+
+impl AssistantBackend for FakeBackend {
+    fn send_message<'a>(
+        &'a mut self,
+        message: &'a str,
+        session: &'a SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> BoxFuture<'a, Result<Option<String>>> {
+        self.sessions.lock().push(session.clone());
+        Box::pin(self.run(message, output_tx))
+    }
+
+    fn kill_handle(&self) -> KillHandle {
+        self.kill_handle.clone()
+    }
+
+    fn last_exit(&self) -> Option<&ProcessExit> {
+        self.last_exit.as_ref()
+    }
+}
@@ -1,14 +1,42 @@
 //! Claude CLI process manager (non-interactive mode)
+//!
+//! Sessions live in a registry keyed by the app session id, so several
+//! conversations can run side by side, each with its own working directory,
+//! state machine and CLI process. Every event sent to the frontend carries
+//! the `session_id` it belongs to.
 
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
-use tauri::{AppHandle, Emitter};
+use tauri::AppHandle;
 use tokio::sync::mpsc;
 
-use super::error::{ClaudeError, Result};
-use super::pty::{check_claude_cli, ClaudeProcess, ProcessConfig};
-use super::sessions::{ConversationInfo, SessionManager};
+use super::api::{ApiBackendFactory, ApiSettings, ApiStatus};
+use super::auth::{auth_status, config_paths, AuthStatus};
+use super::backend::{BackendFactory, CliBackend, SelectingBackend};
+use super::diagnostics::{check_mcp_servers, ActivityLog, DiagnosticsReport};
+use super::discovery::{CliLocator, CliStatus};
+use super::error::{ClaudeError, ErrorKind, Result};
+use super::events::EventEmitter;
+use super::export::{self, ExportSettings, ExportedNote};
+use super::metadata::{self, ConversationFilter, ConversationTag, ForkPoint};
+use super::options::{SessionOptions, SessionPreset};
+use super::pty::{
+    ControlResponse, KillHandle, ParsedOutput, PersistentProcess, ProcessConfig, ProcessExit,
+    ProcessMode, SessionArg, SessionInit, TurnUsage, OUTPUT_CHANNEL_CAPACITY,
+};
+use super::queue::{MessageQueue, QueuedMessage};
+use super::retention::{
+    self, RetentionAction, RetentionFailure, RetentionReport, RetentionSettings,
+};
+use super::search::SearchHit;
+use super::sessions::{ConversationInfo, MessagePage, SessionManager, TrashedConversation};
+use super::store::ClaudeStore;
+use super::transcript;
+use super::usage::{UsageRecord, UsageReport};
+use super::watcher::ConversationWatcher;
 
 /// Message sent from Claude CLI output
 #[derive(Debug, Clone, Serialize)]
@@ -18,8 +46,128 @@ pub struct ClaudeOutput {
     pub session_id: String,
 }
 
+/// Session details reported by the CLI when it starts
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeInit {
+    #[serde(flatten)]
+    pub init: SessionInit,
+    pub session_id: String,
+}
+
+/// Tool invocation requested by the assistant
+///
+/// `parent_tool_use_id` is the `Task` call of the subagent that made it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeToolUse {
+    pub id: String,
+    pub name: String,
+    pub input: serde_json::Value,
+    pub parent_tool_use_id: Option<String>,
+    pub session_id: String,
+}
+
+/// Result of a tool invocation, as fed back to the assistant
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeToolResult {
+    pub tool_use_id: String,
+    pub content: String,
+    pub is_error: bool,
+    pub parent_tool_use_id: Option<String>,
+    pub session_id: String,
+}
+
+/// Incremental stream event of the message being generated
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudePartial {
+    pub event: serde_json::Value,
+    pub parent_tool_use_id: Option<String>,
+    pub session_id: String,
+}
+
+/// The CLI's answer to a control request such as an interrupt
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeControlResponse {
+    #[serde(flatten)]
+    pub response: ControlResponse,
+    pub session_id: String,
+}
+
+/// Text a subagent wrote while working on a `Task` call; not part of the
+/// reply
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeSubagentOutput {
+    pub content: String,
+    pub parent_tool_use_id: String,
+    pub session_id: String,
+}
+
+/// Extended thinking emitted by the assistant or a subagent
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeThinking {
+    pub content: String,
+    pub parent_tool_use_id: Option<String>,
+    pub session_id: String,
+}
+
+/// User prompt echoed back by the CLI
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeUserMessage {
+    pub content: String,
+    pub session_id: String,
+}
+
+/// A response was cancelled by the user
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeCancelled {
+    pub session_id: String,
+    pub partial_output: String,
+}
+
+/// An error reported for a session
+///
+/// `kind` lets the UI react to known failures (e.g. prompt for login on
+/// `not_authenticated`); `exit_code` and `stderr` are set when the CLI
+/// process exited unsuccessfully.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeErrorEvent {
+    pub session_id: String,
+    pub kind: ErrorKind,
+    pub message: String,
+    pub exit_code: Option<i32>,
+    pub stderr: Vec<String>,
+}
+
+impl ClaudeErrorEvent {
+    pub fn new(session_id: String, error: &ClaudeError, exit: Option<&ProcessExit>) -> Self {
+        Self {
+            session_id,
+            kind: error.kind(),
+            message: error.to_string(),
+            exit_code: exit.and_then(|e| e.code),
+            stderr: exit.map(|e| e.stderr.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Pending messages of a session, emitted as `claude:queue` on every change
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeQueue {
+    pub session_id: String,
+    pub messages: Vec<QueuedMessage>,
+}
+
+/// What happened to a message passed to `claude_send_message`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SendOutcome {
+    /// Dispatched to the CLI right away
+    Sent,
+    /// Waiting for the current turn to finish
+    Queued { message: QueuedMessage },
+}
+
 /// Status of the Claude session
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum SessionStatus {
     Inactive,
@@ -30,246 +178,1382 @@ pub enum SessionStatus {
     Error,
 }
 
+impl SessionStatus {
+    /// Whether the state machine allows moving from `self` to `next`
+    ///
+    /// ```text
+    /// Inactive -> Starting -> Active <-> Processing
+    /// Starting | Active | Processing | Error -> Stopping -> Inactive
+    /// Starting | Active | Processing -> Error -> Starting | Active
+    /// ```
+    pub fn can_transition_to(self, next: SessionStatus) -> bool {
+        use SessionStatus::*;
+        matches!(
+            (self, next),
+            (Inactive, Starting)
+                | (Starting, Active)
+                | (Starting, Error)
+                | (Starting, Stopping)
+                | (Active, Processing)
+                | (Active, Stopping)
+                | (Active, Error)
+                | (Processing, Active)
+                | (Processing, Error)
+                | (Processing, Stopping)
+                | (Stopping, Inactive)
+                | (Error, Starting)
+                | (Error, Active)
+                | (Error, Stopping)
+        )
+    }
+}
+
 /// Current session state
+///
+/// `session_id` is the app's id for the session and stays stable;
+/// `cli_session_id` is the id the Claude CLI reported for the conversation
+/// and is what `--resume` is given on the next turn.
 #[derive(Debug, Clone, Serialize)]
 pub struct SessionState {
     pub status: SessionStatus,
-    pub session_id: Option<String>,
+    pub session_id: String,
+    pub cli_session_id: Option<String>,
+    pub working_dir: String,
+    pub mode: ProcessMode,
+    pub options: SessionOptions,
     pub error: Option<String>,
 }
 
-/// Manager for Claude CLI integration
-pub struct ClaudeManager {
-    process: Mutex<Option<ClaudeProcess>>,
-    session_manager: Mutex<SessionManager>,
-    current_session_id: Mutex<Option<String>>,
+/// A single conversation with its own state machine and CLI process
+pub struct ClaudeSession {
+    id: String,
+    mode: ProcessMode,
+    config: ProcessConfig,
+    backend: Arc<dyn BackendFactory>,
+    store: Arc<ClaudeStore>,
+    activity: Arc<ActivityLog>,
+    /// Conversation id issued by the CLI (from the `system` init event)
+    cli_session_id: Mutex<Option<String>>,
+    /// Conversation the first turn forks from (`--fork-session`)
+    fork: Option<ForkPoint>,
     status: Mutex<SessionStatus>,
-    working_dir: String,
-    mcp_config_path: Option<String>,
-    system_prompt: Option<String>,
+    last_error: Mutex<Option<String>>,
+    /// Kill handle of the in-flight response, if any
+    running: Mutex<Option<KillHandle>>,
+    persistent: Mutex<Option<PersistentProcess>>,
+    current_message_id: Mutex<Option<String>>,
+    /// Text streamed so far in the current turn (reported on cancel)
+    partial_output: Mutex<String>,
+    /// Messages waiting for the current turn to finish
+    queue: Mutex<MessageQueue>,
 }
 
-impl ClaudeManager {
-    /// Create a new Claude manager
-    pub fn new(working_dir: String) -> Self {
-        let mut session_manager = SessionManager::new();
-        session_manager.set_project_dir(&working_dir);
-
+impl ClaudeSession {
+    fn new(
+        id: String,
+        mode: ProcessMode,
+        config: ProcessConfig,
+        backend: Arc<dyn BackendFactory>,
+        store: Arc<ClaudeStore>,
+        activity: Arc<ActivityLog>,
+        cli_session_id: Option<String>,
+    ) -> Self {
         Self {
-            process: Mutex::new(None),
-            session_manager: Mutex::new(session_manager),
-            current_session_id: Mutex::new(None),
+            id,
+            mode,
+            config,
+            backend,
+            store,
+            activity,
+            cli_session_id: Mutex::new(cli_session_id),
+            fork: None,
             status: Mutex::new(SessionStatus::Inactive),
-            working_dir,
-            mcp_config_path: None,
-            system_prompt: Some(
-                "You are an assistant for the Personal Assistant app. \
-                You have access to MCP tools to manage tasks, projects, and time entries. \
-                Use list_tasks, create_task, list_projects, and other tools to help the user. \
-                Be concise and helpful.".to_string()
-            ),
+            last_error: Mutex::new(None),
+            running: Mutex::new(None),
+            persistent: Mutex::new(None),
+            current_message_id: Mutex::new(None),
+            partial_output: Mutex::new(String::new()),
+            queue: Mutex::new(MessageQueue::default()),
         }
     }
 
-    /// Set MCP config path
-    #[allow(dead_code)]
-    pub fn set_mcp_config(&mut self, path: String) {
-        self.mcp_config_path = Some(path);
+    /// Fork `fork` on the first turn instead of starting a new conversation
+    fn forking(self, fork: Option<ForkPoint>) -> Self {
+        Self { fork, ..self }
     }
 
-    /// Set system prompt
-    #[allow(dead_code)]
-    pub fn set_system_prompt(&mut self, prompt: String) {
-        self.system_prompt = Some(prompt);
+    /// Get the session state
+    pub fn get_state(&self) -> SessionState {
+        SessionState {
+            status: *self.status.lock(),
+            session_id: self.id.clone(),
+            cli_session_id: self.cli_session_id.lock().clone(),
+            working_dir: self.config.working_dir.clone(),
+            mode: self.mode,
+            options: self.config.options.clone(),
+            error: self.last_error.lock().clone(),
+        }
     }
 
-    /// Check if Claude CLI is available
-    pub fn is_cli_available(&self) -> bool {
-        check_claude_cli().unwrap_or(false)
+    /// Whether the session is running or ready for messages
+    pub fn is_active(&self) -> bool {
+        matches!(
+            *self.status.lock(),
+            SessionStatus::Starting | SessionStatus::Active | SessionStatus::Processing
+        )
+    }
+
+    /// Move to `next`, emitting `claude:status`
+    ///
+    /// `error` is recorded when entering `Error`; any other state clears
+    /// the last error.
+    fn transition(&self, events: &EventEmitter, next: SessionStatus, error: Option<String>) -> Result<()> {
+        {
+            let mut status = self.status.lock();
+            if !status.can_transition_to(next) {
+                return Err(ClaudeError::InvalidTransition {
+                    from: *status,
+                    to: next,
+                });
+            }
+            eprintln!("[Claude] Session {} status: {:?} -> {:?}", self.id, *status, next);
+            *status = next;
+            *self.last_error.lock() = if next == SessionStatus::Error { error } else { None };
+        }
+        events.emit("claude:status", self.get_state());
+        Ok(())
     }
 
-    /// Check if authenticated
-    pub fn is_authenticated(&self) -> bool {
-        self.session_manager.lock().is_authenticated()
+    /// Transition only if currently in `expected` (used by background tasks
+    /// that may race with `stop`)
+    fn transition_from(
+        &self,
+        events: &EventEmitter,
+        expected: SessionStatus,
+        next: SessionStatus,
+        error: Option<String>,
+    ) -> bool {
+        if *self.status.lock() != expected {
+            return false;
+        }
+        self.transition(events, next, error).is_ok()
     }
 
-    /// Get current session state
-    pub fn get_state(&self) -> SessionState {
-        SessionState {
-            status: self.status.lock().clone(),
-            session_id: self.current_session_id.lock().clone(),
-            error: None,
+    /// Emit a `claude:error` event tagged with this session
+    fn emit_error(&self, events: &EventEmitter, error: &ClaudeError, exit: Option<&ProcessExit>) {
+        eprintln!("[Claude] Session {} error: {}", self.id, error);
+        self.activity.record_error(&self.id, error, exit);
+        events.emit(
+            "claude:error",
+            ClaudeErrorEvent::new(self.id.clone(), error, exit),
+        );
+    }
+
+    /// Bring the session up
+    ///
+    /// In per-message mode no process is spawned until the first message.
+    /// In persistent mode the stream-json child is started right away and
+    /// lives until the session is stopped.
+    fn start(self: &Arc<Self>, events: &EventEmitter) -> Result<()> {
+        self.transition(events, SessionStatus::Starting, None)?;
+
+        if self.mode == ProcessMode::Persistent {
+            if let Err(e) = self.spawn_persistent(events.clone()) {
+                let _ = self.transition(events, SessionStatus::Error, Some(e.to_string()));
+                return Err(e);
+            }
         }
+
+        self.transition(events, SessionStatus::Active, None)?;
+        self.dispatch_next(events);
+        Ok(())
     }
 
-    /// Initialize a session (marks as active, but doesn't spawn a process yet)
-    /// Process is spawned on first message
-    pub fn start_session(
-        &self,
-        _app: AppHandle,
-        resume_id: Option<String>,
-    ) -> Result<String> {
-        // Check if already active
+    /// The conversation the next spawned process should attach to
+    ///
+    /// Until the CLI has reported a session id, the app id is passed as
+    /// `--session-id` so both refer to the same conversation; a fork
+    /// passes its parent and gets a new id from the CLI.
+    fn session_arg(&self) -> SessionArg {
+        match (self.cli_session_id.lock().clone(), &self.fork) {
+            (Some(id), _) => SessionArg::Resume(id),
+            (None, Some(fork)) => SessionArg::Fork(fork.conversation_id.clone()),
+            (None, None) => SessionArg::New(self.id.clone()),
+        }
+    }
+
+    /// Record the CLI-issued session id and notify the frontend of changes
+    fn update_cli_session_id(&self, events: &EventEmitter, cli_session_id: String) {
         {
-            let status = self.status.lock();
-            if *status == SessionStatus::Active || *status == SessionStatus::Starting || *status == SessionStatus::Processing {
-                return Err(ClaudeError::SessionAlreadyExists(
-                    "A session is already active".to_string(),
-                ));
+            let mut current = self.cli_session_id.lock();
+            if current.as_deref() == Some(cli_session_id.as_str()) {
+                return;
             }
+            eprintln!("[Claude] Session {} CLI session id: {}", self.id, cli_session_id);
+            // The first id of a fork is the new conversation branching off
+            if let (None, Some(fork)) = (current.as_ref(), &self.fork) {
+                if let Err(e) = self.store.record_fork(&cli_session_id, fork) {
+                    eprintln!("[Claude] Failed to record fork of {}: {}", fork.conversation_id, e);
+                }
+            }
+            *current = Some(cli_session_id);
         }
+        events.emit("claude:session", self.get_state());
+    }
 
-        // Generate or use provided session ID
-        let session_id = resume_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    /// Spawn the long-lived process and the task forwarding its events
+    fn spawn_persistent(self: &Arc<Self>, events: EventEmitter) -> Result<()> {
+        let (tx, mut rx) = mpsc::channel::<ParsedOutput>(OUTPUT_CHANNEL_CAPACITY);
+        let process = self.backend.spawn_persistent(&self.config, &self.session_arg(), tx)?;
+        *self.persistent.lock() = Some(process);
 
-        *self.current_session_id.lock() = Some(session_id.clone());
-        *self.status.lock() = SessionStatus::Active;
+        let session = Arc::clone(self);
+        tokio::spawn(async move {
+            while let Some(output) = rx.recv().await {
+                match output {
+                    ParsedOutput::SessionId(id) => session.update_cli_session_id(&events, id),
+                    ParsedOutput::Usage(turn) => {
+                        let message_id = session.current_message_id.lock().clone().unwrap_or_default();
+                        session.record_usage(&events, message_id, turn);
+                    }
+                    ParsedOutput::Complete => session.finish_turn(&events, None),
+                    ParsedOutput::Error(err) => {
+                        let error = ClaudeError::from_cli_message(&err);
+                        session.emit_error(&events, &error, None);
+                        session.finish_turn(&events, Some(error.to_string()));
+                    }
+                    ParsedOutput::Timeout { kind, secs } => {
+                        let error = ClaudeError::Timeout { kind, secs };
+                        session.emit_error(&events, &error, None);
+                        session.finish_turn(&events, Some(error.to_string()));
+                    }
+                    other => session.forward_output(&events, other),
+                }
+            }
 
-        Ok(session_id)
+            // Channel closed: the process exited or was killed. Only clear the
+            // slot if it still holds a dead child, not a respawned one. The
+            // child may close stdout slightly before it can be reaped.
+            let mut exit = None;
+            for _ in 0..10 {
+                {
+                    let mut persistent = session.persistent.lock();
+                    match persistent.as_mut() {
+                        Some(process) => exit = process.exit(),
+                        None => break,
+                    }
+                    if exit.is_some() {
+                        persistent.take();
+                        break;
+                    }
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            if *session.status.lock() == SessionStatus::Processing {
+                let cancelled = session.running.lock().as_ref().is_some_and(|h| h.is_cancelled());
+                let error = (!cancelled).then(|| {
+                    exit.as_ref().and_then(|e| e.error()).unwrap_or(ClaudeError::ProcessTerminated)
+                });
+                if let Some(ref e) = error {
+                    session.emit_error(&events, e, exit.as_ref());
+                }
+                session.finish_turn(&events, error.map(|e| e.to_string()));
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Persist and emit the usage of a completed turn
+    fn record_usage(&self, events: &EventEmitter, message_id: String, turn: TurnUsage) {
+        let record = UsageRecord {
+            session_id: self.id.clone(),
+            message_id,
+            turn,
+        };
+        if let Err(e) = self.store.record_usage(&record) {
+            eprintln!("[Claude] Failed to record usage: {}", e);
+        }
+        events.emit("claude:usage", record);
+    }
+
+    /// Emit an output event, keeping track of streamed text
+    fn forward_output(&self, events: &EventEmitter, output: ParsedOutput) {
+        if let ParsedOutput::Text {
+            ref text,
+            parent_tool_use_id: None,
+        } = output
+        {
+            self.partial_output.lock().push_str(text);
+        }
+        emit_parsed_output(events, &self.id, output);
+    }
+
+    /// Reset per-turn bookkeeping before a new response starts
+    fn begin_turn(&self, kill_handle: Option<KillHandle>) {
+        *self.running.lock() = kill_handle;
+        *self.current_message_id.lock() = Some(uuid::Uuid::new_v4().to_string());
+        self.partial_output.lock().clear();
+    }
+
+    /// Emit the completion marker and leave `Processing`
+    ///
+    /// The session returns to `Active` and dispatches the next queued
+    /// message, or goes to `Error` if the turn failed, which pauses the queue
+    /// until the user sends again. A cancelled turn additionally reports
+    /// `claude:cancelled` with the text received before the process was
+    /// killed.
+    fn finish_turn(self: &Arc<Self>, events: &EventEmitter, error: Option<String>) {
+        let cancelled = self.running.lock().take().is_some_and(|h| h.is_cancelled());
+        if cancelled {
+            let partial_output = std::mem::take(&mut *self.partial_output.lock());
+            events.emit(
+                "claude:cancelled",
+                ClaudeCancelled {
+                    session_id: self.id.clone(),
+                    partial_output,
+                },
+            );
+        }
+
+        let complete_output = ClaudeOutput {
+            content: String::new(),
+            is_complete: true,
+            session_id: self.id.clone(),
+        };
+        events.emit("claude:output", complete_output);
+
+        *self.current_message_id.lock() = None;
+        let failed = error.is_some();
+        let next = if failed { SessionStatus::Error } else { SessionStatus::Active };
+        if self.transition_from(events, SessionStatus::Processing, next, error) && !failed {
+            self.dispatch_next(events);
+        }
+    }
+
+    /// Send a message to Claude
+    ///
+    /// The message is dispatched right away when the session is idle;
+    /// otherwise it joins the queue and runs once the turns ahead of it
+    /// have completed. Sending while the queue is paused by a failed turn
+    /// resumes it, oldest message first.
+    pub fn send_message(self: &Arc<Self>, events: EventEmitter, message: &str) -> Result<SendOutcome> {
+        // Reject oversized prompts before touching the session state
+        self.config.check_prompt_size(message)?;
+
+        let queued = {
+            // Held across the dispatch so concurrent sends keep their order
+            let mut queue = self.queue.lock();
+            let status = *self.status.lock();
+            match status {
+                SessionStatus::Inactive | SessionStatus::Stopping => {
+                    return Err(ClaudeError::SessionNotRunning(self.id.clone()));
+                }
+                SessionStatus::Active | SessionStatus::Error if queue.is_empty() => {
+                    self.dispatch(events, message)?;
+                    return Ok(SendOutcome::Sent);
+                }
+                _ => queue.push(message.to_string())?,
+            }
+        };
+        self.emit_queue(&events);
+        self.dispatch_next(&events);
+
+        Ok(SendOutcome::Queued { message: queued })
     }
 
-    /// Send a message to Claude (spawns a new process for each message)
-    pub fn send_message(&self, app: AppHandle, message: &str) -> Result<()> {
-        // Check if session is active
+    /// Dispatch the oldest queued message if the session is idle
+    fn dispatch_next(self: &Arc<Self>, events: &EventEmitter) {
         {
-            let status = self.status.lock();
-            if *status != SessionStatus::Active {
-                return Err(ClaudeError::NoActiveSession);
+            let mut queue = self.queue.lock();
+            if !matches!(*self.status.lock(), SessionStatus::Active | SessionStatus::Error) {
+                return;
+            }
+            let Some(next) = queue.pop() else { return };
+            eprintln!("[Claude] Session {} dispatching queued message {}", self.id, next.id);
+            if let Err(e) = self.dispatch(events.clone(), &next.content) {
+                // Keep it and pause the queue until the next send
+                queue.requeue(next);
+                self.emit_error(events, &e, None);
+                let error = Some(e.to_string());
+                self.transition_from(events, SessionStatus::Active, SessionStatus::Error, error);
             }
         }
+        self.emit_queue(events);
+    }
+
+    /// Emit the pending messages as `claude:queue`
+    fn emit_queue(&self, events: &EventEmitter) {
+        events.emit(
+            "claude:queue",
+            ClaudeQueue {
+                session_id: self.id.clone(),
+                messages: self.queue.lock().list(),
+            },
+        );
+    }
+
+    /// Pending messages, oldest first
+    pub fn queued_messages(&self) -> Vec<QueuedMessage> {
+        self.queue.lock().list()
+    }
+
+    /// Change a message that has not been dispatched yet
+    pub fn edit_queued_message(&self, events: &EventEmitter, id: &str, content: &str) -> Result<QueuedMessage> {
+        self.config.check_prompt_size(content)?;
+        let message = self.queue.lock().edit(id, content.to_string())?;
+        self.emit_queue(events);
+        Ok(message)
+    }
+
+    /// Drop a message that has not been dispatched yet
+    pub fn remove_queued_message(&self, events: &EventEmitter, id: &str) -> Result<()> {
+        self.queue.lock().remove(id)?;
+        self.emit_queue(events);
+        Ok(())
+    }
+
+    /// Start a turn for `message`
+    ///
+    /// Persistent sessions write the turn to the running child (respawning
+    /// it with `--resume` if it has exited); otherwise a new process is
+    /// spawned for the message.
+    fn dispatch(self: &Arc<Self>, events: EventEmitter, message: &str) -> Result<()> {
+        // Fail early (e.g. CLI uninstalled or outdated) without leaving `Active`
+        self.backend.check()?;
+
+        // A failed turn recovers on the next message
+        if *self.status.lock() == SessionStatus::Error {
+            self.transition(&events, SessionStatus::Active, None)?;
+        }
 
         // Mark as processing
-        *self.status.lock() = SessionStatus::Processing;
+        self.transition(&events, SessionStatus::Processing, None)?;
+
+        if self.mode == ProcessMode::Persistent {
+            return self.send_persistent(events, message);
+        }
 
-        // Get session ID for potential resume
-        let resume_id = self.current_session_id.lock().clone();
-        let session_id = resume_id.clone().unwrap_or_else(|| "unknown".to_string());
+        // CLI session for --session-id/--resume
+        let session_arg = self.session_arg();
 
         // Create output channel
-        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
-
-        // Create process config
-        let config = ProcessConfig {
-            working_dir: self.working_dir.clone(),
-            mcp_config_path: self.mcp_config_path.clone(),
-            system_prompt: self.system_prompt.clone(),
-        };
+        let (tx, mut rx) = mpsc::channel::<ParsedOutput>(OUTPUT_CHANNEL_CAPACITY);
 
-        // Create and store process
-        let mut process = ClaudeProcess::new(config);
+        // Create the process and keep its kill handle for cancellation
+        let mut process = self.backend.create(&self.config);
+        self.begin_turn(Some(process.kill_handle()));
+        let message_id = self.current_message_id.lock().clone().unwrap_or_default();
 
         // Clone message for the spawned task
         let message = message.to_string();
-        let app_clone = app.clone();
-        let session_id_clone = session_id.clone();
-
-        // Spawn the process in a blocking task
-        let process_handle = std::thread::spawn(move || {
-            let result = process.send_message(
-                &message,
-                resume_id.as_deref(),
-                tx,
-            );
+        let session = Arc::clone(self);
+
+        // Run the process on its own task
+        let process_handle = tokio::spawn(async move {
+            let result = process.send_message(&message, &session_arg, tx).await;
             (process, result)
         });
 
-        // Store a reference (we'll update this when the process completes)
-        // Note: In this non-interactive model, the process lives only for one message
-
         // Spawn task to forward output to frontend
-        let status_clone = Arc::new(Mutex::new(SessionStatus::Processing));
-        let status_for_task = status_clone.clone();
-
         tokio::spawn(async move {
-            while let Some(content) = rx.recv().await {
-                let output = ClaudeOutput {
-                    content,
-                    is_complete: false,
-                    session_id: session_id_clone.clone(),
-                };
-
-                let _ = app_clone.emit("claude:output", output);
+            let mut turn_error: Option<ClaudeError> = None;
+            while let Some(output) = rx.recv().await {
+                match output {
+                    ParsedOutput::SessionId(id) => session.update_cli_session_id(&events, id),
+                    ParsedOutput::Usage(turn) => {
+                        session.record_usage(&events, message_id.clone(), turn);
+                    }
+                    ParsedOutput::Error(err) => {
+                        turn_error.get_or_insert_with(|| ClaudeError::from_cli_message(&err));
+                    }
+                    output => session.forward_output(&events, output),
+                }
             }
 
             // Wait for process to complete
-            if let Ok((_process, result)) = process_handle.join() {
+            let mut exit = None;
+            if let Ok((process, result)) = process_handle.await {
+                exit = process.last_exit().cloned();
                 match result {
                     Ok(new_session_id) => {
-                        // Update session ID if we got a new one
-                        if let Some(_id) = new_session_id {
-                            // Could update current_session_id here if needed
+                        // Usually already seen on the stream; keeps the mapping current
+                        if let Some(id) = new_session_id {
+                            session.update_cli_session_id(&events, id);
                         }
                     }
-                    Err(e) => {
-                        let _ = app_clone.emit("claude:error", e.to_string());
-                    }
+                    Err(e) => turn_error = Some(prefer_specific_error(turn_error, e)),
                 }
             }
 
+            if let Some(ref e) = turn_error {
+                session.emit_error(&events, e, exit.as_ref());
+            } else if let Some(ref exit) = exit {
+                // Warnings from a successful turn still help diagnose later failures
+                session.activity.record_stderr(&session.id, &exit.stderr);
+            }
+
             // Mark message as complete
-            let complete_output = ClaudeOutput {
-                content: String::new(),
-                is_complete: true,
-                session_id: session_id_clone.clone(),
-            };
-            let _ = app_clone.emit("claude:output", complete_output);
-
-            *status_for_task.lock() = SessionStatus::Active;
+            session.finish_turn(&events, turn_error.map(|e| e.to_string()));
         });
 
-        // Update our status back to active after spawning
-        // The actual status update happens in the spawned task
-
         Ok(())
     }
 
-    /// Stop the current session
-    pub fn stop_session(&self) -> Result<()> {
-        *self.status.lock() = SessionStatus::Stopping;
+    /// Write a turn to the persistent process
+    fn send_persistent(self: &Arc<Self>, events: EventEmitter, message: &str) -> Result<()> {
+        let alive = self.persistent.lock().as_mut().is_some_and(|p| p.is_running());
+        if !alive {
+            // The child went away between turns; pick the conversation back up
+            if let Err(e) = self.spawn_persistent(events.clone()) {
+                let _ = self.transition(&events, SessionStatus::Error, Some(e.to_string()));
+                return Err(e);
+            }
+        }
+
+        let result = match self.persistent.lock().as_mut() {
+            Some(process) => {
+                self.begin_turn(Some(process.kill_handle()));
+                process.send_message(message)
+            }
+            None => Err(ClaudeError::ProcessTerminated),
+        };
+        if let Err(ref e) = result {
+            *self.running.lock() = None;
+            let _ = self.transition(&events, SessionStatus::Error, Some(e.to_string()));
+        }
+        result
+    }
+
+    /// Interrupt the running turn of a persistent session
+    pub fn interrupt(&self) -> Result<()> {
+        match self.persistent.lock().as_mut() {
+            Some(process) => process.interrupt(),
+            None => Err(ClaudeError::SendFailed(
+                "Interrupt requires a persistent session".to_string(),
+            )),
+        }
+    }
+
+    /// Cancel the in-flight response
+    ///
+    /// Kills the CLI and its process group. The forwarding task then emits
+    /// `claude:cancelled` with the partial output and the session stays
+    /// active, so the next message resumes the conversation.
+    pub fn cancel_message(&self) -> Result<()> {
+        let handle = self
+            .running
+            .lock()
+            .clone()
+            .ok_or(ClaudeError::NoResponseInProgress)?;
+        handle.kill()
+    }
+
+    /// Stop the session and kill its process
+    pub fn stop(&self, events: &EventEmitter) -> Result<()> {
+        if *self.status.lock() == SessionStatus::Inactive {
+            return Ok(());
+        }
+        self.transition(events, SessionStatus::Stopping, None)?;
+
+        // Pending messages die with the session
+        self.queue.lock().clear();
+        self.emit_queue(events);
 
         // Kill any running process
-        let mut process_lock = self.process.lock();
-        if let Some(ref mut process) = *process_lock {
+        if let Some(handle) = self.running.lock().take() {
+            let _ = handle.kill();
+        }
+
+        if let Some(mut process) = self.persistent.lock().take() {
             let _ = process.kill();
         }
-        *process_lock = None;
 
-        *self.current_session_id.lock() = None;
-        *self.status.lock() = SessionStatus::Inactive;
+        self.transition(events, SessionStatus::Inactive, None)
+    }
+}
 
-        Ok(())
+/// Manager for Claude CLI integration
+pub struct ClaudeManager {
+    sessions: Mutex<HashMap<String, Arc<ClaudeSession>>>,
+    session_manager: Mutex<SessionManager>,
+    backend: Arc<dyn BackendFactory>,
+    cli: Arc<CliLocator>,
+    api: Arc<ApiBackendFactory>,
+    store: Arc<ClaudeStore>,
+    activity: Arc<ActivityLog>,
+    working_dir: String,
+    mcp_config_path: Option<String>,
+    system_prompt: Option<String>,
+    /// Live conversation list updates, once started
+    watcher: Mutex<Option<ConversationWatcher>>,
+    /// What the retention policy did at startup
+    retention_report: Mutex<Option<RetentionReport>>,
+}
+
+impl ClaudeManager {
+    /// Create a new Claude manager backed by the Claude CLI, or the
+    /// Messages API per the saved backend preference
+    pub fn new(working_dir: String, store: Arc<ClaudeStore>, api: Arc<ApiBackendFactory>) -> Self {
+        let cli = Arc::new(CliLocator::new(Arc::clone(&store)));
+        let backend = Arc::new(SelectingBackend::new(
+            Arc::new(CliBackend::new(Arc::clone(&cli))),
+            Arc::clone(&api),
+        ));
+        Self::with_backend(working_dir, store, api, cli, backend)
     }
 
-    /// List conversation history
-    pub fn list_conversations(&self) -> Result<Vec<ConversationInfo>> {
-        self.session_manager.lock().list_conversations()
+    /// Create a manager running turns through `backend`; `cli` is the
+    /// locator reported on by `cli_status`
+    pub fn with_backend(
+        working_dir: String,
+        store: Arc<ClaudeStore>,
+        api: Arc<ApiBackendFactory>,
+        cli: Arc<CliLocator>,
+        backend: Arc<dyn BackendFactory>,
+    ) -> Self {
+        let mut session_manager = SessionManager::new();
+        session_manager.set_project_dir(&working_dir);
+
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            session_manager: Mutex::new(session_manager),
+            backend,
+            cli,
+            api,
+            store,
+            activity: Arc::default(),
+            working_dir,
+            mcp_config_path: None,
+            watcher: Mutex::new(None),
+            retention_report: Mutex::new(None),
+            system_prompt: Some(
+                "You are an assistant for the Personal Assistant app. \
+                You have access to MCP tools to manage tasks, projects, and time entries. \
+                Use list_tasks, create_task, list_projects, and other tools to help the user. \
+                Be concise and helpful.".to_string()
+            ),
+        }
     }
 
-    /// Get a specific conversation
+    /// Set MCP config path
     #[allow(dead_code)]
-    pub fn get_conversation(&self, id: &str) -> Result<Option<ConversationInfo>> {
-        self.session_manager.lock().get_conversation(id)
+    pub fn set_mcp_config(&mut self, path: String) {
+        self.mcp_config_path = Some(path);
     }
 
-    /// Check if a session is active
+    /// Set system prompt
     #[allow(dead_code)]
-    pub fn is_active(&self) -> bool {
-        let status = self.status.lock();
-        *status == SessionStatus::Active || *status == SessionStatus::Processing
+    pub fn set_system_prompt(&mut self, prompt: String) {
+        self.system_prompt = Some(prompt);
     }
-}
 
-/// State wrapper for Tauri
-pub struct ClaudeManagerState(pub Arc<ClaudeManager>);
+    /// Check if Claude CLI is available
+    pub fn is_cli_available(&self) -> bool {
+        self.backend.is_available()
+    }
 
-impl ClaudeManagerState {
-    pub fn new(working_dir: String) -> Self {
-        Self(Arc::new(ClaudeManager::new(working_dir)))
+    /// Login state of the Claude CLI
+    pub fn auth_status(&self) -> AuthStatus {
+        auth_status()
     }
-}
 
-// Tauri commands
-// These will be registered in lib.rs
+    /// Look up a session by its app id
+    pub fn session(&self, session_id: &str) -> Result<Arc<ClaudeSession>> {
+        self.sessions
+            .lock()
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| ClaudeError::SessionNotFound(session_id.to_string()))
+    }
+
+    /// State of every open session
+    pub fn list_sessions(&self) -> Vec<SessionState> {
+        self.sessions.lock().values().map(|s| s.get_state()).collect()
+    }
+
+    /// Get the state of one session
+    pub fn get_state(&self, session_id: &str) -> Result<SessionState> {
+        Ok(self.session(session_id)?.get_state())
+    }
+
+    /// Start a new session and register it
+    ///
+    /// `resume_id` is a CLI conversation id to continue; without it a new
+    /// id is generated and handed to the CLI via `--session-id`.
+    /// `working_dir` defaults to the app's project directory.
+    pub fn start_session(
+        &self,
+        events: EventEmitter,
+        resume_id: Option<String>,
+        mode: ProcessMode,
+        working_dir: Option<String>,
+        options: SessionOptions,
+    ) -> Result<String> {
+        self.launch_session(events, resume_id, None, mode, working_dir, options)
+    }
+
+    /// Start a session continuing a copy of a conversation through message
+    /// `message_index` (as numbered by `get_conversation_messages`); the
+    /// original is left untouched. Forks of an earlier message start from
+    /// a trimmed copy of the transcript, forks of the last message use the
+    /// CLI's `--fork-session`. Either way the parent is recorded.
+    pub fn fork_session(
+        &self,
+        events: EventEmitter,
+        conversation_id: &str,
+        message_index: usize,
+        mode: ProcessMode,
+        options: SessionOptions,
+    ) -> Result<String> {
+        let source = self
+            .session_manager
+            .lock()
+            .transcript_path(conversation_id)
+            .filter(|path| path.exists())
+            .ok_or_else(|| ClaudeError::ConversationNotFound(conversation_id.to_string()))?;
+        let through = transcript::load_messages(&source)?
+            .into_iter()
+            .nth(message_index)
+            .and_then(|message| message.uuid)
+            .ok_or_else(|| {
+                ClaudeError::InvalidOption(format!(
+                    "Conversation {} has no message {}",
+                    conversation_id, message_index
+                ))
+            })?;
+        let fork = ForkPoint {
+            conversation_id: conversation_id.to_string(),
+            message_index,
+        };
+
+        let fork_id = uuid::Uuid::new_v4().to_string();
+        let copy = source.with_file_name(format!("{}.jsonl", fork_id));
+        if !transcript::write_prefix(&source, &through, &copy, &fork_id)? {
+            return self.launch_session(events, None, Some(fork), mode, None, options);
+        }
+        // Link the copy to its parent before a session can use it
+        let started = self
+            .store
+            .record_fork(&fork_id, &fork)
+            .and_then(|()| self.start_session(events, Some(fork_id.clone()), mode, None, options));
+        if started.is_err() {
+            let _ = self.store.forget_conversation(&fork_id);
+            let _ = std::fs::remove_file(&copy);
+        }
+        started
+    }
+
+    fn launch_session(
+        &self,
+        events: EventEmitter,
+        resume_id: Option<String>,
+        fork: Option<ForkPoint>,
+        mode: ProcessMode,
+        working_dir: Option<String>,
+        options: SessionOptions,
+    ) -> Result<String> {
+        let working_dir = working_dir.unwrap_or_else(|| self.working_dir.clone());
+        if !Path::new(&working_dir).is_dir() {
+            return Err(ClaudeError::InvalidWorkingDir(working_dir));
+        }
+        options.validate(Some(Path::new(&working_dir)))?;
+        self.backend.check()?;
+
+        let session_id = resume_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let config = ProcessConfig {
+            working_dir,
+            mcp_config_path: self.mcp_config_path.clone(),
+            system_prompt: self.system_prompt.clone(),
+            options,
+        };
+        let session = Arc::new(ClaudeSession::new(
+            session_id.clone(),
+            mode,
+            config,
+            self.backend.clone(),
+            self.store.clone(),
+            self.activity.clone(),
+            resume_id.clone(),
+        )
+        .forking(fork));
+
+        {
+            let mut sessions = self.sessions.lock();
+            // Two processes writing to the same CLI conversation would clash
+            let conflict = sessions.values().any(|s| {
+                s.id == session_id
+                    || (resume_id.is_some() && *s.cli_session_id.lock() == resume_id)
+            });
+            if conflict {
+                return Err(ClaudeError::SessionAlreadyExists(session_id));
+            }
+            sessions.insert(session_id.clone(), session.clone());
+        }
+
+        if let Err(e) = session.start(&events) {
+            self.sessions.lock().remove(&session_id);
+            return Err(e);
+        }
+
+        Ok(session_id)
+    }
+
+    /// Send a message to a session, queueing it while a turn is running
+    pub fn send_message(&self, events: EventEmitter, session_id: &str, message: &str) -> Result<SendOutcome> {
+        self.session(session_id)?.send_message(events, message)
+    }
+
+    /// Messages queued on a session
+    pub fn queued_messages(&self, session_id: &str) -> Result<Vec<QueuedMessage>> {
+        Ok(self.session(session_id)?.queued_messages())
+    }
+
+    /// Edit a queued message
+    pub fn edit_queued_message(
+        &self,
+        events: &EventEmitter,
+        session_id: &str,
+        message_id: &str,
+        content: &str,
+    ) -> Result<QueuedMessage> {
+        self.session(session_id)?.edit_queued_message(events, message_id, content)
+    }
+
+    /// Drop a queued message
+    pub fn remove_queued_message(&self, events: &EventEmitter, session_id: &str, message_id: &str) -> Result<()> {
+        self.session(session_id)?.remove_queued_message(events, message_id)
+    }
+
+    /// Interrupt the running turn of a persistent session
+    pub fn interrupt(&self, session_id: &str) -> Result<()> {
+        self.session(session_id)?.interrupt()
+    }
+
+    /// Cancel the in-flight response of a session
+    pub fn cancel_message(&self, session_id: &str) -> Result<()> {
+        self.session(session_id)?.cancel_message()
+    }
+
+    /// Stop a session and remove it from the registry
+    pub fn stop_session(&self, events: &EventEmitter, session_id: &str) -> Result<()> {
+        let session = self.session(session_id)?;
+        let result = session.stop(events);
+        self.sessions.lock().remove(session_id);
+        result
+    }
+
+    /// List conversation history with the app's titles, pins and tags
+    pub fn list_conversations(&self, filter: &ConversationFilter) -> Result<Vec<ConversationInfo>> {
+        // Scan on a copy so sessions aren't held up while transcripts are read
+        let sessions = self.session_manager.lock().clone();
+        let conversations = sessions.list_conversations()?;
+        let meta = self.store.conversation_meta()?;
+        Ok(metadata::apply(conversations, &meta, filter))
+    }
+
+    /// Get a specific conversation
+    pub fn get_conversation(&self, id: &str) -> Result<Option<ConversationInfo>> {
+        let sessions = self.session_manager.lock().clone();
+        let conversation = sessions.get_conversation(id)?;
+        let meta = match conversation {
+            Some(_) => self.store.conversation_meta()?.remove(id),
+            None => None,
+        };
+        Ok(conversation.map(|c| metadata::merge(c, meta.as_ref())))
+    }
+
+    /// Fail unless the project has a conversation with this id
+    fn ensure_conversation(&self, id: &str) -> Result<()> {
+        match self.session_manager.lock().transcript_path(id) {
+            Some(path) if path.exists() => Ok(()),
+            _ => Err(ClaudeError::ConversationNotFound(id.to_string())),
+        }
+    }
+
+    /// Emit `claude:conversations-changed` whenever a transcript of the
+    /// project is created, appended to or removed
+    pub fn watch_conversations(&self, events: EventEmitter) -> Result<()> {
+        let sessions = self.session_manager.lock().clone();
+        let watcher = ConversationWatcher::start(sessions, Arc::clone(&self.store), events)?;
+        *self.watcher.lock() = Some(watcher);
+        Ok(())
+    }
+
+    /// Give a conversation a custom title; `None` restores the derived one
+    pub fn rename_conversation(&self, id: &str, title: Option<&str>) -> Result<()> {
+        self.ensure_conversation(id)?;
+        self.store.set_conversation_title(id, title)
+    }
+
+    pub fn pin_conversation(&self, id: &str, pinned: bool) -> Result<()> {
+        self.ensure_conversation(id)?;
+        self.store.set_conversation_pinned(id, pinned)
+    }
+
+    pub fn archive_conversation(&self, id: &str, archived: bool) -> Result<()> {
+        self.ensure_conversation(id)?;
+        self.store.set_conversation_archived(id, archived)
+    }
+
+    /// Replace a conversation's tags by name, creating missing tags
+    pub fn set_conversation_tags(&self, id: &str, tags: &[String]) -> Result<Vec<ConversationTag>> {
+        self.ensure_conversation(id)?;
+        self.store.set_conversation_tags(id, tags)
+    }
+
+    /// Fail if a running session is attached to the conversation
+    fn ensure_not_open(&self, id: &str) -> Result<()> {
+        let open = self
+            .sessions
+            .lock()
+            .values()
+            .any(|s| s.id == id || s.cli_session_id.lock().as_deref() == Some(id));
+        if open {
+            return Err(ClaudeError::ConversationInUse(id.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Move a conversation to the trash, keeping its title, pin and tags
+    /// for when it is restored
+    pub fn trash_conversation(&self, id: &str) -> Result<()> {
+        self.ensure_not_open(id)?;
+        self.session_manager.lock().trash_conversation(id)
+    }
+
+    pub fn restore_conversation(&self, id: &str) -> Result<()> {
+        self.session_manager.lock().restore_conversation(id)
+    }
+
+    /// Delete a conversation and everything the app kept about it
+    pub fn purge_conversation(&self, id: &str) -> Result<()> {
+        self.ensure_not_open(id)?;
+        self.session_manager.lock().purge_conversation(id)?;
+        self.store.forget_conversation(id)?;
+        self.store.remove_from_search_index(id)
+    }
+
+    /// Conversations in the trash, with the app's titles, pins and tags
+    pub fn list_trash(&self) -> Result<Vec<TrashedConversation>> {
+        let sessions = self.session_manager.lock().clone();
+        let trash = sessions.trashed_conversations()?;
+        let meta = self.store.conversation_meta()?;
+        Ok(trash
+            .into_iter()
+            .map(|t| {
+                let meta = meta.get(&t.conversation.id);
+                TrashedConversation {
+                    conversation: metadata::merge(t.conversation, meta),
+                    ..t
+                }
+            })
+            .collect())
+    }
+
+    pub fn retention_settings(&self) -> RetentionSettings {
+        self.store.retention_settings()
+    }
+
+    /// Save the retention policy applied from the next startup on,
+    /// returning what it would archive and delete if it ran now
+    pub fn save_retention_settings(&self, settings: &RetentionSettings) -> Result<RetentionReport> {
+        let report = self.run_retention(Some(settings), true)?;
+        self.store.save_retention_settings(settings)?;
+        Ok(report)
+    }
+
+    /// Apply a retention policy, the saved one by default; a dry run only
+    /// reports what would be archived and deleted
+    pub fn run_retention(
+        &self,
+        settings: Option<&RetentionSettings>,
+        dry_run: bool,
+    ) -> Result<RetentionReport> {
+        let settings = match settings {
+            Some(settings) => {
+                settings.validate()?;
+                settings.clone()
+            }
+            None => self.retention_settings(),
+        };
+        let conversations = self.list_conversations(&ConversationFilter::all())?;
+        let sessions = self.session_manager.lock().clone();
+        let trash = sessions.trashed_conversations()?;
+        let plan = retention::plan(&settings, conversations, trash, chrono::Utc::now(), dry_run);
+        if dry_run {
+            return Ok(plan);
+        }
+
+        // One conversation that can't be changed shouldn't keep the rest;
+        // the report only lists what was actually done
+        let mut failed = Vec::new();
+        let mut fail = |id: String, action: RetentionAction, error: ClaudeError| {
+            eprintln!("[Claude] Retention failed to {:?} {}: {}", action, id, error);
+            failed.push(RetentionFailure {
+                id,
+                action,
+                error: error.to_string(),
+            });
+        };
+        let mut archived = Vec::new();
+        for conversation in plan.archived {
+            match self.store.set_conversation_archived(&conversation.id, true) {
+                Ok(()) => archived.push(conversation),
+                Err(e) => fail(conversation.id, RetentionAction::Archive, e),
+            }
+        }
+        let mut purged = Vec::new();
+        for trashed in plan.purged {
+            match self.purge_conversation(&trashed.conversation.id) {
+                Ok(()) => purged.push(trashed),
+                Err(e) => fail(trashed.conversation.id, RetentionAction::Purge, e),
+            }
+        }
+        Ok(RetentionReport {
+            dry_run,
+            archived,
+            purged,
+            failed,
+        })
+    }
+
+    /// Apply the saved retention policy, as done at startup, and emit
+    /// `claude:retention-applied` with the report if anything changed
+    pub fn apply_retention(&self, events: &EventEmitter) {
+        let report = match self.run_retention(None, false) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("[Claude] Failed to apply retention policy: {}", e);
+                return;
+            }
+        };
+        if !report.archived.is_empty() || !report.purged.is_empty() || !report.failed.is_empty() {
+            eprintln!(
+                "[Claude] Retention archived {} conversations and deleted {} from the trash ({} failed)",
+                report.archived.len(),
+                report.purged.len(),
+                report.failed.len()
+            );
+            events.emit("claude:retention-applied", report.clone());
+        }
+        *self.retention_report.lock() = Some(report);
+    }
+
+    /// The report of the retention run at startup, once it has finished;
+    /// the window may open after `claude:retention-applied` was emitted
+    pub fn retention_report(&self) -> Option<RetentionReport> {
+        self.retention_report.lock().clone()
+    }
+
+    /// A page of a past conversation's messages
+    pub fn get_conversation_messages(
+        &self,
+        id: &str,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<MessagePage> {
+        let sessions = self.session_manager.lock().clone();
+        sessions.get_conversation_messages(id, offset, limit)
+    }
+
+    pub fn export_settings(&self) -> ExportSettings {
+        self.store.export_settings()
+    }
+
+    /// Choose the vault folder notes are exported to
+    pub fn save_export_settings(&self, settings: &ExportSettings) -> Result<()> {
+        self.store.save_export_settings(settings)
+    }
+
+    /// Write a conversation as a note into the vault (the project directory)
+    pub fn export_conversation(&self, id: &str) -> Result<ExportedNote> {
+        let info = self
+            .get_conversation(id)?
+            .ok_or_else(|| ClaudeError::ConversationNotFound(id.to_string()))?;
+        let transcript = self
+            .session_manager
+            .lock()
+            .transcript_path(id)
+            .ok_or_else(|| ClaudeError::ConversationNotFound(id.to_string()))?;
+        export::export_conversation(
+            &self.store,
+            Path::new(&self.working_dir),
+            &self.working_dir,
+            &info,
+            &transcript,
+        )
+    }
+
+    /// Export every conversation that has no note yet
+    pub fn sync_exports(&self) -> Result<Vec<ExportedNote>> {
+        let exported = self.store.exported_conversations()?;
+        let mut notes = Vec::new();
+        for info in self.list_conversations(&ConversationFilter::all())? {
+            if exported.contains(&info.id) {
+                continue;
+            }
+            // One unreadable transcript shouldn't hold up the rest
+            match self.export_conversation(&info.id) {
+                Ok(note) => notes.push(note),
+                Err(e) => eprintln!("[Claude] Failed to export {}: {}", info.id, e),
+            }
+        }
+        Ok(notes)
+    }
+
+    /// Search the project's conversations, indexing new transcript lines first
+    pub fn search_conversations(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchHit>> {
+        let files = self.session_manager.lock().transcript_files()?;
+        self.store.sync_search_index(&files)?;
+        self.store.search_conversations(query, limit)
+    }
+
+    /// Pick the options for a new session: explicit options win over a
+    /// named preset, otherwise the CLI defaults apply
+    pub fn resolve_options(
+        &self,
+        options: Option<SessionOptions>,
+        preset: Option<&str>,
+    ) -> Result<SessionOptions> {
+        match (options, preset) {
+            (Some(options), _) => Ok(options),
+            (None, Some(name)) => Ok(self.store.get_preset(name)?.options),
+            (None, None) => Ok(SessionOptions::default()),
+        }
+    }
+
+    /// List saved option presets
+    pub fn list_presets(&self) -> Result<Vec<SessionPreset>> {
+        self.store.list_presets()
+    }
+
+    /// Save an option preset
+    pub fn save_preset(&self, name: &str, options: &SessionOptions) -> Result<()> {
+        self.store.save_preset(name, options)
+    }
+
+    /// Delete an option preset
+    pub fn delete_preset(&self, name: &str) -> Result<()> {
+        self.store.delete_preset(name)
+    }
+
+    /// Where the CLI was found, its version and any problem with it
+    pub fn cli_status(&self) -> CliStatus {
+        self.cli.status()
+    }
+
+    /// Use the CLI at `path`, or go back to automatic discovery with `None`
+    pub fn set_cli_path(&self, path: Option<&str>) -> Result<()> {
+        self.cli.set_configured_path(path)
+    }
+
+    /// Health report for the settings page and bug reports
+    pub async fn diagnostics(&self, stderr_lines: usize) -> DiagnosticsReport {
+        let cli = self.cli_status();
+        let (_, global_config) = config_paths();
+        let mcp_servers = check_mcp_servers(
+            self.mcp_config_path.as_deref(),
+            Path::new(&self.working_dir),
+            &global_config,
+            cli.cli.as_ref().map(|info| Path::new(&info.path)),
+        )
+        .await;
+        DiagnosticsReport {
+            generated_at: chrono::Utc::now().to_rfc3339(),
+            app_version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            cli,
+            auth: self.auth_status(),
+            api: self.api_status(),
+            working_dir: self.working_dir.clone(),
+            working_dir_exists: Path::new(&self.working_dir).is_dir(),
+            mcp_config_path: self.mcp_config_path.clone(),
+            mcp_servers,
+            sessions: self.list_sessions(),
+            recent_stderr: self.activity.recent_stderr(stderr_lines),
+            recent_errors: self.activity.recent_errors(),
+        }
+    }
+
+    /// Messages API settings and whether a key is saved
+    pub fn api_status(&self) -> ApiStatus {
+        self.api.status()
+    }
+
+    /// Save the Messages API settings and backend preference
+    pub fn save_api_settings(&self, settings: &ApiSettings) -> Result<()> {
+        self.api.save_settings(settings)
+    }
+
+    /// Store a new API key, or forget the saved one with `None`
+    pub fn set_api_key(&self, key: Option<&str>) -> Result<()> {
+        match key {
+            Some(key) => self.api.keys().save(key),
+            None => self.api.keys().clear(),
+        }
+    }
+
+    /// Get token usage and cost aggregates
+    pub fn get_usage(&self, session_id: Option<&str>, days: u32, months: u32) -> Result<UsageReport> {
+        self.store.usage_report(session_id, days, months)
+    }
+
+    /// Check if any session is active
+    #[allow(dead_code)]
+    pub fn is_active(&self) -> bool {
+        self.sessions.lock().values().any(|s| s.is_active())
+    }
+}
+
+/// Forward a parsed CLI output to the frontend as its matching Tauri event
+fn emit_parsed_output(events: &EventEmitter, session_id: &str, output: ParsedOutput) {
+    let session_id = session_id.to_string();
+    match output {
+        ParsedOutput::Text {
+            text,
+            parent_tool_use_id: None,
+        } => events.emit(
+            "claude:output",
+            ClaudeOutput {
+                content: text,
+                is_complete: false,
+                session_id,
+            },
+        ),
+        ParsedOutput::Text {
+            text,
+            parent_tool_use_id: Some(parent_tool_use_id),
+        } => events.emit(
+            "claude:subagent_output",
+            ClaudeSubagentOutput {
+                content: text,
+                parent_tool_use_id,
+                session_id,
+            },
+        ),
+        ParsedOutput::Init(init) => events.emit("claude:init", ClaudeInit { init, session_id }),
+        ParsedOutput::ToolUse {
+            id,
+            name,
+            input,
+            parent_tool_use_id,
+        } => events.emit(
+            "claude:tool_use",
+            ClaudeToolUse {
+                id,
+                name,
+                input,
+                parent_tool_use_id,
+                session_id,
+            },
+        ),
+        ParsedOutput::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+            parent_tool_use_id,
+        } => events.emit(
+            "claude:tool_result",
+            ClaudeToolResult {
+                tool_use_id,
+                content,
+                is_error,
+                parent_tool_use_id,
+                session_id,
+            },
+        ),
+        ParsedOutput::Thinking {
+            thinking,
+            parent_tool_use_id,
+        } => events.emit(
+            "claude:thinking",
+            ClaudeThinking {
+                content: thinking,
+                parent_tool_use_id,
+                session_id,
+            },
+        ),
+        ParsedOutput::UserText(content) => events.emit(
+            "claude:user",
+            ClaudeUserMessage {
+                content,
+                session_id,
+            },
+        ),
+        ParsedOutput::Partial {
+            event,
+            parent_tool_use_id,
+        } => events.emit(
+            "claude:partial",
+            ClaudePartial {
+                event,
+                parent_tool_use_id,
+                session_id,
+            },
+        ),
+        ParsedOutput::ControlResponse(response) => events.emit(
+            "claude:control_response",
+            ClaudeControlResponse {
+                response,
+                session_id,
+            },
+        ),
+        ParsedOutput::Error(message) => events.emit(
+            "claude:error",
+            ClaudeErrorEvent::new(session_id, &ClaudeError::from_cli_message(&message), None),
+        ),
+        ParsedOutput::Timeout { kind, secs } => events.emit(
+            "claude:error",
+            ClaudeErrorEvent::new(session_id, &ClaudeError::Timeout { kind, secs }, None),
+        ),
+        // Session ids, usage and completion are handled by the caller
+        ParsedOutput::SessionId(_) | ParsedOutput::Usage(_) | ParsedOutput::Complete => {}
+    }
+}
+
+/// Pick the more informative of the error streamed by the CLI and the one
+/// derived from its exit status
+///
+/// A classified stream error wins; an unclassified one only gives way to a
+/// known failure found on stderr.
+fn prefer_specific_error(streamed: Option<ClaudeError>, exit: ClaudeError) -> ClaudeError {
+    match streamed {
+        Some(streamed)
+            if streamed.kind() != ErrorKind::Other
+                || exit.kind() == ErrorKind::ProcessTerminated =>
+        {
+            streamed
+        }
+        _ => exit,
+    }
+}
+
+/// Stderr lines included in `claude_diagnostics` unless asked otherwise
+const DEFAULT_DIAGNOSTICS_STDERR_LINES: usize = 100;
+
+/// File holding the Messages API key, next to the app database
+const API_KEY_FILE: &str = "claude-api-key";
+
+/// State wrapper for Tauri
+pub struct ClaudeManagerState(pub Arc<ClaudeManager>);
+
+impl ClaudeManagerState {
+    pub fn new(working_dir: String, db_path: &Path) -> Self {
+        let store = ClaudeStore::open(db_path).unwrap_or_else(|e| {
+            eprintln!("[Claude] Failed to open database, usage will not persist: {}", e);
+            ClaudeStore::open_in_memory().expect("in-memory SQLite database")
+        });
+        let store = Arc::new(store);
+        let key_path = db_path.with_file_name(API_KEY_FILE);
+        let api = Arc::new(ApiBackendFactory::new(Arc::clone(&store), key_path));
+        Self(Arc::new(ClaudeManager::new(working_dir, store, api)))
+    }
+}
+
+// Tauri commands
+// These will be registered in lib.rs
 
 /// Check Claude CLI status
 #[tauri::command]
@@ -277,30 +1561,66 @@ pub async fn claude_check_status(
     state: tauri::State<'_, ClaudeManagerState>,
 ) -> std::result::Result<serde_json::Value, String> {
     let manager = &state.0;
+    let auth = manager.auth_status();
 
     Ok(serde_json::json!({
         "cli_available": manager.is_cli_available(),
-        "authenticated": manager.is_authenticated(),
-        "session_state": manager.get_state(),
+        "authenticated": auth.authenticated,
+        "auth": auth,
+        "cli": manager.cli_status(),
+        "api": manager.api_status(),
+        "sessions": manager.list_sessions(),
     }))
 }
 
-/// Start a new Claude session
+/// Start a new Claude session, returning its id
+///
+/// `options` override the named `preset`; with neither, CLI defaults apply.
 #[tauri::command]
 pub async fn claude_start_session(
     app: AppHandle,
     state: tauri::State<'_, ClaudeManagerState>,
     resume_id: Option<String>,
+    mode: Option<ProcessMode>,
+    working_dir: Option<String>,
+    options: Option<SessionOptions>,
+    preset: Option<String>,
 ) -> std::result::Result<String, String> {
     let manager = &state.0;
 
-    // Check CLI availability
-    if !manager.is_cli_available() {
-        return Err("Claude CLI not found. Please install Claude Code CLI.".to_string());
-    }
+    let options = manager
+        .resolve_options(options, preset.as_deref())
+        .map_err(|e| e.to_string())?;
+    manager
+        .start_session(app.into(), resume_id, mode.unwrap_or_default(), working_dir, options)
+        .map_err(|e| e.to_string())
+}
 
+/// Start a session from a copy of a conversation through `message_index`,
+/// returning its id
+#[tauri::command]
+pub async fn claude_fork_session(
+    app: AppHandle,
+    state: tauri::State<'_, ClaudeManagerState>,
+    conversation_id: String,
+    message_index: usize,
+    mode: Option<ProcessMode>,
+    options: Option<SessionOptions>,
+    preset: Option<String>,
+) -> std::result::Result<String, String> {
+    let manager = &state.0;
+
+    let options = manager
+        .resolve_options(options, preset.as_deref())
+        .map_err(|e| e.to_string())?;
     manager
-        .start_session(app, resume_id)
+        .fork_session(
+            app.into(),
+            &conversation_id,
+            message_index,
+            mode.unwrap_or_default(),
+            options,
+        )
         .map_err(|e| e.to_string())
 }
 
@@ -309,35 +1629,834 @@ pub async fn claude_start_session(
 pub async fn claude_send_message(
     app: AppHandle,
     state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
     message: String,
+) -> std::result::Result<SendOutcome, String> {
+    let manager = &state.0;
+    manager
+        .send_message(app.into(), &session_id, &message)
+        .map_err(|e| e.to_string())
+}
+
+/// List the messages waiting behind the current response
+#[tauri::command]
+pub async fn claude_list_queued_messages(
+    state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
+) -> std::result::Result<Vec<QueuedMessage>, String> {
+    let manager = &state.0;
+    manager.queued_messages(&session_id).map_err(|e| e.to_string())
+}
+
+/// Edit a queued message before it is sent
+#[tauri::command]
+pub async fn claude_edit_queued_message(
+    app: AppHandle,
+    state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
+    message_id: String,
+    content: String,
+) -> std::result::Result<QueuedMessage, String> {
+    let manager = &state.0;
+    manager
+        .edit_queued_message(&app.into(), &session_id, &message_id, &content)
+        .map_err(|e| e.to_string())
+}
+
+/// Drop a queued message before it is sent
+#[tauri::command]
+pub async fn claude_remove_queued_message(
+    app: AppHandle,
+    state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
+    message_id: String,
 ) -> std::result::Result<(), String> {
     let manager = &state.0;
-    manager.send_message(app, &message).map_err(|e| e.to_string())
+    manager
+        .remove_queued_message(&app.into(), &session_id, &message_id)
+        .map_err(|e| e.to_string())
 }
 
-/// Stop the current Claude session
+/// Cancel the in-flight response, keeping the session resumable
+#[tauri::command]
+pub async fn claude_cancel_message(
+    state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.cancel_message(&session_id).map_err(|e| e.to_string())
+}
+
+/// Interrupt the running turn without ending a persistent session
+#[tauri::command]
+pub async fn claude_interrupt(
+    state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.interrupt(&session_id).map_err(|e| e.to_string())
+}
+
+/// Stop a Claude session
 #[tauri::command]
 pub async fn claude_stop_session(
+    app: AppHandle,
     state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
 ) -> std::result::Result<(), String> {
     let manager = &state.0;
-    manager.stop_session().map_err(|e| e.to_string())
+    manager
+        .stop_session(&app.into(), &session_id)
+        .map_err(|e| e.to_string())
 }
 
 /// List conversation history
 #[tauri::command]
 pub async fn claude_list_conversations(
     state: tauri::State<'_, ClaudeManagerState>,
+    filter: Option<ConversationFilter>,
 ) -> std::result::Result<Vec<ConversationInfo>, String> {
+    let manager = state.0.clone();
+    // Listing parses every transcript of the project
+    tokio::task::spawn_blocking(move || manager.list_conversations(&filter.unwrap_or_default()))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Rename a conversation; without `title` the derived title is used again
+#[tauri::command]
+pub async fn claude_rename_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+    title: Option<String>,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager
+        .rename_conversation(&id, title.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Pin or unpin a conversation
+#[tauri::command]
+pub async fn claude_pin_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+    pinned: bool,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.pin_conversation(&id, pinned).map_err(|e| e.to_string())
+}
+
+/// Archive or unarchive a conversation
+#[tauri::command]
+pub async fn claude_archive_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+    archived: bool,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager
+        .archive_conversation(&id, archived)
+        .map_err(|e| e.to_string())
+}
+
+/// Move a conversation to the trash
+#[tauri::command]
+pub async fn claude_trash_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.trash_conversation(&id).map_err(|e| e.to_string())
+}
+
+/// Move a conversation back out of the trash
+#[tauri::command]
+pub async fn claude_restore_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+) -> std::result::Result<(), String> {
     let manager = &state.0;
-    manager.list_conversations().map_err(|e| e.to_string())
+    manager.restore_conversation(&id).map_err(|e| e.to_string())
+}
+
+/// Delete a conversation permanently, whether or not it is in the trash
+#[tauri::command]
+pub async fn claude_purge_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.purge_conversation(&id).map_err(|e| e.to_string())
+}
+
+/// List the conversations in the trash, most recently trashed first
+#[tauri::command]
+pub async fn claude_list_trash(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<Vec<TrashedConversation>, String> {
+    let manager = state.0.clone();
+    // Every trashed transcript is parsed
+    tokio::task::spawn_blocking(move || manager.list_trash())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Get the retention policy applied at startup
+#[tauri::command]
+pub async fn claude_get_retention_settings(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<RetentionSettings, String> {
+    let manager = &state.0;
+    Ok(manager.retention_settings())
+}
+
+/// Save the retention policy applied at startup, returning a dry run of it
+#[tauri::command]
+pub async fn claude_save_retention_settings(
+    state: tauri::State<'_, ClaudeManagerState>,
+    settings: RetentionSettings,
+) -> std::result::Result<RetentionReport, String> {
+    let manager = state.0.clone();
+    tokio::task::spawn_blocking(move || manager.save_retention_settings(&settings))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// What the retention policy archived and deleted at startup
+#[tauri::command]
+pub async fn claude_get_retention_report(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<Option<RetentionReport>, String> {
+    let manager = &state.0;
+    Ok(manager.retention_report())
+}
+
+/// Run a retention policy (the saved one by default); unless `dry_run` is
+/// false this only reports what it would archive and delete
+#[tauri::command]
+pub async fn claude_run_retention(
+    state: tauri::State<'_, ClaudeManagerState>,
+    settings: Option<RetentionSettings>,
+    dry_run: Option<bool>,
+) -> std::result::Result<RetentionReport, String> {
+    let manager = state.0.clone();
+    // Every transcript is read to find the old conversations
+    tokio::task::spawn_blocking(move || {
+        manager.run_retention(settings.as_ref(), dry_run.unwrap_or(true))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+/// Set a conversation's tags by name, returning them as stored
+#[tauri::command]
+pub async fn claude_set_conversation_tags(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+    tags: Vec<String>,
+) -> std::result::Result<Vec<ConversationTag>, String> {
+    let manager = &state.0;
+    manager
+        .set_conversation_tags(&id, &tags)
+        .map_err(|e| e.to_string())
+}
+
+/// Messages of a past conversation, e.g. to show its history when it is
+/// reopened with `claude_start_session(resume_id)`. Without `offset` the
+/// latest `limit` messages are returned.
+#[tauri::command]
+pub async fn claude_get_conversation_messages(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> std::result::Result<MessagePage, String> {
+    let manager = state.0.clone();
+    tokio::task::spawn_blocking(move || manager.get_conversation_messages(&id, offset, limit))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Get the vault folder conversations are exported to
+#[tauri::command]
+pub async fn claude_get_export_settings(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<ExportSettings, String> {
+    let manager = &state.0;
+    Ok(manager.export_settings())
+}
+
+/// Save the vault folder conversations are exported to
+#[tauri::command]
+pub async fn claude_save_export_settings(
+    state: tauri::State<'_, ClaudeManagerState>,
+    settings: ExportSettings,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.save_export_settings(&settings).map_err(|e| e.to_string())
+}
+
+/// Export a conversation as a Markdown note, returning where it was written
+#[tauri::command]
+pub async fn claude_export_conversation(
+    state: tauri::State<'_, ClaudeManagerState>,
+    id: String,
+) -> std::result::Result<ExportedNote, String> {
+    let manager = state.0.clone();
+    tokio::task::spawn_blocking(move || manager.export_conversation(&id))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Export every conversation that hasn't been exported yet
+#[tauri::command]
+pub async fn claude_sync_exports(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<Vec<ExportedNote>, String> {
+    let manager = state.0.clone();
+    tokio::task::spawn_blocking(move || manager.sync_exports())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+/// Full-text search across the project's conversations
+#[tauri::command]
+pub async fn claude_search_conversations(
+    state: tauri::State<'_, ClaudeManagerState>,
+    query: String,
+    limit: Option<usize>,
+) -> std::result::Result<Vec<SearchHit>, String> {
+    let manager = state.0.clone();
+    // The first search indexes every transcript, which can take a while
+    tokio::task::spawn_blocking(move || manager.search_conversations(&query, limit))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
 }
 
 /// Get session state
 #[tauri::command]
 pub async fn claude_get_session_state(
     state: tauri::State<'_, ClaudeManagerState>,
+    session_id: String,
 ) -> std::result::Result<SessionState, String> {
     let manager = &state.0;
-    Ok(manager.get_state())
+    manager.get_state(&session_id).map_err(|e| e.to_string())
+}
+
+/// List all open sessions
+#[tauri::command]
+pub async fn claude_list_sessions(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<Vec<SessionState>, String> {
+    let manager = &state.0;
+    Ok(manager.list_sessions())
+}
+
+/// Get token usage and cost, optionally for a single session
+#[tauri::command]
+pub async fn claude_get_usage(
+    state: tauri::State<'_, ClaudeManagerState>,
+    session_id: Option<String>,
+    days: Option<u32>,
+    months: Option<u32>,
+) -> std::result::Result<UsageReport, String> {
+    let manager = &state.0;
+    manager
+        .get_usage(session_id.as_deref(), days.unwrap_or(30), months.unwrap_or(12))
+        .map_err(|e| e.to_string())
+}
+
+/// List saved session option presets
+#[tauri::command]
+pub async fn claude_list_presets(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<Vec<SessionPreset>, String> {
+    let manager = &state.0;
+    manager.list_presets().map_err(|e| e.to_string())
+}
+
+/// Create or update a session option preset
+#[tauri::command]
+pub async fn claude_save_preset(
+    state: tauri::State<'_, ClaudeManagerState>,
+    name: String,
+    options: SessionOptions,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.save_preset(&name, &options).map_err(|e| e.to_string())
+}
+
+/// Delete a session option preset
+#[tauri::command]
+pub async fn claude_delete_preset(
+    state: tauri::State<'_, ClaudeManagerState>,
+    name: String,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.delete_preset(&name).map_err(|e| e.to_string())
+}
+
+/// Collect a health report (CLI, auth, MCP servers, recent stderr and errors)
+#[tauri::command]
+pub async fn claude_diagnostics(
+    state: tauri::State<'_, ClaudeManagerState>,
+    stderr_lines: Option<usize>,
+) -> std::result::Result<DiagnosticsReport, String> {
+    let manager = state.0.clone();
+    Ok(manager.diagnostics(stderr_lines.unwrap_or(DEFAULT_DIAGNOSTICS_STDERR_LINES)).await)
+}
+
+/// Get the CLI location and version
+#[tauri::command]
+pub async fn claude_get_cli_status(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<CliStatus, String> {
+    let manager = &state.0;
+    Ok(manager.cli_status())
+}
+
+/// Set the CLI path, or clear it to use automatic discovery
+#[tauri::command]
+pub async fn claude_set_cli_path(
+    state: tauri::State<'_, ClaudeManagerState>,
+    path: Option<String>,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.set_cli_path(path.as_deref()).map_err(|e| e.to_string())
+}
+
+/// Get the Messages API settings
+#[tauri::command]
+pub async fn claude_get_api_settings(
+    state: tauri::State<'_, ClaudeManagerState>,
+) -> std::result::Result<ApiStatus, String> {
+    let manager = &state.0;
+    Ok(manager.api_status())
+}
+
+/// Save the Messages API settings and backend preference
+#[tauri::command]
+pub async fn claude_save_api_settings(
+    state: tauri::State<'_, ClaudeManagerState>,
+    settings: ApiSettings,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.save_api_settings(&settings).map_err(|e| e.to_string())
+}
+
+/// Save the Anthropic API key, or remove it when `key` is omitted
+#[tauri::command]
+pub async fn claude_set_api_key(
+    state: tauri::State<'_, ClaudeManagerState>,
+    key: Option<String>,
+) -> std::result::Result<(), String> {
+    let manager = &state.0;
+    manager.set_api_key(key.as_deref()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_lifecycle_transitions() {
+        use SessionStatus::*;
+        let path = [Inactive, Starting, Active, Processing, Active, Stopping, Inactive];
+        for pair in path.windows(2) {
+            assert!(pair[0].can_transition_to(pair[1]), "{:?} -> {:?}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_error_recovery_transitions() {
+        use SessionStatus::*;
+        assert!(Processing.can_transition_to(Error));
+        assert!(Starting.can_transition_to(Error));
+        assert!(Error.can_transition_to(Active));
+        assert!(Error.can_transition_to(Starting));
+        assert!(Error.can_transition_to(Stopping));
+    }
+
+    #[test]
+    fn test_invalid_transitions() {
+        use SessionStatus::*;
+        assert!(!Inactive.can_transition_to(Active));
+        assert!(!Inactive.can_transition_to(Processing));
+        assert!(!Active.can_transition_to(Active));
+        assert!(!Stopping.can_transition_to(Active));
+        assert!(!Error.can_transition_to(Processing));
+    }
+
+    /// API backend without a saved key
+    fn test_api(store: &Arc<ClaudeStore>) -> Arc<ApiBackendFactory> {
+        let key_path = std::env::temp_dir().join(format!("claude-api-key-{}", uuid::Uuid::new_v4()));
+        Arc::new(ApiBackendFactory::new(Arc::clone(store), key_path))
+    }
+
+    #[test]
+    fn test_session_registry_lookup() {
+        let store = Arc::new(ClaudeStore::open_in_memory().unwrap());
+        let manager = ClaudeManager::new(".".to_string(), Arc::clone(&store), test_api(&store));
+        assert!(matches!(
+            manager.session("missing"),
+            Err(ClaudeError::SessionNotFound(_))
+        ));
+        assert!(manager.list_sessions().is_empty());
+    }
+
+    // Integration tests: the full chat flow against the scripted fake CLI
+
+    use super::super::events::RecordingSink;
+    use super::super::fake::{self, FakeBackendFactory};
+    use super::super::testutil::TempDir;
+    use serde_json::Value;
+
+    fn fake_manager(fixtures: &[&str]) -> (ClaudeManager, Arc<FakeBackendFactory>, RecordingSink) {
+        let backend = Arc::new(FakeBackendFactory::new(fixtures));
+        let store = Arc::new(ClaudeStore::open_in_memory().unwrap());
+        let working_dir = std::env::temp_dir().to_string_lossy().to_string();
+        let api = test_api(&store);
+        let cli = Arc::new(CliLocator::new(Arc::clone(&store)));
+        let manager = ClaudeManager::with_backend(working_dir, store, api, cli, backend.clone());
+        (manager, backend, RecordingSink::default())
+    }
+
+    fn start(manager: &ClaudeManager, sink: &RecordingSink, options: SessionOptions) -> String {
+        manager
+            .start_session(sink.emitter(), None, ProcessMode::PerMessage, None, options)
+            .unwrap()
+    }
+
+    fn is_complete(payload: &Value) -> bool {
+        payload["is_complete"] == true
+    }
+
+    fn streamed_text(sink: &RecordingSink) -> String {
+        sink.payloads("claude:output")
+            .iter()
+            .filter_map(|p| p["content"].as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fake_turn_streams_text_and_tools() {
+        let (manager, backend, sink) = fake_manager(&[fake::TEXT_AND_TOOL]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        let outcome = manager.send_message(sink.emitter(), &id, "What's open?").unwrap();
+        assert!(matches!(outcome, SendOutcome::Sent));
+        sink.wait_for("claude:output", is_complete).await;
+
+        assert_eq!(
+            streamed_text(&sink),
+            "Let me check your tasks.You have one open task: Write report."
+        );
+        // Subagent narration is tagged with its call, not part of the reply
+        let subagent = &sink.payloads("claude:subagent_output")[0];
+        assert_eq!(subagent["content"], "Querying the task server.");
+        assert_eq!(subagent["parent_tool_use_id"], "toolu_1");
+        let tool_use = &sink.payloads("claude:tool_use")[0];
+        assert_eq!(tool_use["name"], "mcp__tasks__list_tasks");
+        assert_eq!(tool_use["input"]["status"], "open");
+        let tool_result = &sink.payloads("claude:tool_result")[0];
+        assert_eq!(tool_result["tool_use_id"], "toolu_1");
+        assert!(tool_result["content"].as_str().unwrap().contains("Write report"));
+        assert!(sink.payloads("claude:error").is_empty());
+
+        let state = manager.get_state(&id).unwrap();
+        assert_eq!(state.status, SessionStatus::Active);
+        assert_eq!(state.cli_session_id.as_deref(), Some("cli-session-1"));
+        assert_eq!(backend.prompts(), vec!["What's open?"]);
+
+        let usage = manager.get_usage(Some(&id), 1, 1).unwrap();
+        assert_eq!(usage.session.unwrap().output_tokens, 45);
+    }
+
+    #[tokio::test]
+    async fn test_fake_api_error_then_recovery() {
+        let (manager, _backend, sink) = fake_manager(&[fake::API_ERROR, fake::TEXT_AND_TOOL]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        manager.send_message(sink.emitter(), &id, "hello").unwrap();
+        let error = sink.wait_for("claude:error", |_| true).await;
+        assert_eq!(error["kind"], "overloaded");
+        assert_eq!(error["session_id"], id.as_str());
+        sink.wait_for("claude:status", |p| p["status"] == "error").await;
+
+        // The next message recovers the session
+        manager.send_message(sink.emitter(), &id, "try again").unwrap();
+        sink.wait_for_count("claude:output", is_complete, 2).await;
+        assert_eq!(manager.get_state(&id).unwrap().status, SessionStatus::Active);
+        assert_eq!(sink.payloads("claude:error").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fake_auth_failure_reports_stderr() {
+        let (manager, _backend, sink) = fake_manager(&[fake::AUTH_FAILURE]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        manager.send_message(sink.emitter(), &id, "hello").unwrap();
+        let error = sink.wait_for("claude:error", |_| true).await;
+        assert_eq!(error["kind"], "not_authenticated");
+        assert_eq!(error["exit_code"], 1);
+        assert_eq!(error["stderr"][0], "Invalid API key · Please run /login");
+    }
+
+    #[tokio::test]
+    async fn test_fake_cancel_keeps_partial_output() {
+        let (manager, _backend, sink) = fake_manager(&[fake::SLOW_STREAM]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        manager.send_message(sink.emitter(), &id, "take your time").unwrap();
+        sink.wait_for("claude:output", |p| p["content"] == "Working on it. ").await;
+        manager.cancel_message(&id).unwrap();
+
+        let cancelled = sink.wait_for("claude:cancelled", |_| true).await;
+        assert_eq!(cancelled["partial_output"], "Working on it. ");
+        sink.wait_for("claude:output", is_complete).await;
+        assert_eq!(manager.get_state(&id).unwrap().status, SessionStatus::Active);
+        assert!(sink.payloads("claude:error").is_empty());
+        assert!(!streamed_text(&sink).contains("Done."));
+    }
+
+    #[tokio::test]
+    async fn test_fake_queue_dispatches_in_order() {
+        let (manager, backend, sink) = fake_manager(&[fake::SLOW_STREAM, fake::TEXT_AND_TOOL]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        assert!(matches!(
+            manager.send_message(sink.emitter(), &id, "first").unwrap(),
+            SendOutcome::Sent
+        ));
+        let SendOutcome::Queued { message: second } =
+            manager.send_message(sink.emitter(), &id, "second").unwrap()
+        else {
+            panic!("second message should be queued");
+        };
+        let SendOutcome::Queued { message: third } =
+            manager.send_message(sink.emitter(), &id, "third").unwrap()
+        else {
+            panic!("third message should be queued");
+        };
+        assert_eq!(manager.queued_messages(&id).unwrap().len(), 2);
+
+        manager
+            .edit_queued_message(&sink.emitter(), &id, &second.id, "second, edited")
+            .unwrap();
+        manager.remove_queued_message(&sink.emitter(), &id, &third.id).unwrap();
+
+        sink.wait_for_count("claude:output", is_complete, 2).await;
+        assert_eq!(backend.prompts(), vec!["first", "second, edited"]);
+        assert!(manager.queued_messages(&id).unwrap().is_empty());
+        assert_eq!(manager.get_state(&id).unwrap().status, SessionStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn test_fake_queue_pauses_when_backend_missing() {
+        let (manager, backend, sink) =
+            fake_manager(&[fake::SLOW_STREAM, fake::TEXT_AND_TOOL, fake::TEXT_AND_TOOL]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        manager.send_message(sink.emitter(), &id, "first").unwrap();
+        manager.send_message(sink.emitter(), &id, "second").unwrap();
+        backend.set_available(false);
+        sink.wait_for("claude:output", is_complete).await;
+
+        // The queued message waits in Error instead of being dropped
+        sink.wait_for("claude:status", |p| p["status"] == "error").await;
+        assert_eq!(manager.queued_messages(&id).unwrap()[0].content, "second");
+        assert!(sink.payloads("claude:error").iter().any(|p| p["kind"] == "cli_not_found"));
+
+        // The next send resumes the queue, oldest message first
+        backend.set_available(true);
+        manager.send_message(sink.emitter(), &id, "third").unwrap();
+        sink.wait_for_count("claude:output", is_complete, 3).await;
+        assert_eq!(backend.prompts(), vec!["first", "second", "third"]);
+        assert_eq!(manager.get_state(&id).unwrap().status, SessionStatus::Active);
+    }
+
+    #[tokio::test]
+    async fn test_fake_idle_timeout() {
+        let (manager, _backend, sink) = fake_manager(&[fake::HANG]);
+        let options = SessionOptions {
+            idle_timeout_secs: Some(1),
+            ..Default::default()
+        };
+        let id = start(&manager, &sink, options);
+
+        manager.send_message(sink.emitter(), &id, "hello").unwrap();
+        let error = sink.wait_for("claude:error", |_| true).await;
+        assert_eq!(error["kind"], "timeout");
+        sink.wait_for("claude:status", |p| p["status"] == "error").await;
+    }
+
+    #[tokio::test]
+    async fn test_fake_fork_session() {
+        let (manager, backend, sink) = fake_manager(&[fake::TEXT_AND_TOOL, fake::TEXT_AND_TOOL]);
+        let claude_dir = TempDir::new("fork");
+        let mut sessions = SessionManager::with_claude_dir(claude_dir.to_path_buf());
+        sessions.set_project_dir(&manager.working_dir);
+        let dir = sessions.project_conversations_dir().unwrap();
+        *manager.session_manager.lock() = sessions;
+
+        std::fs::create_dir_all(&dir).unwrap();
+        let lines = [
+            r#"{"type":"user","uuid":"u1","sessionId":"parent","message":{"role":"user","content":"First question"}}"#,
+            r#"{"type":"assistant","uuid":"a1","sessionId":"parent","message":{"id":"m1","content":[{"type":"text","text":"First answer"}]}}"#,
+            r#"{"type":"user","uuid":"u2","sessionId":"parent","message":{"role":"user","content":"Second question"}}"#,
+            r#"{"type":"assistant","uuid":"a2","sessionId":"parent","message":{"id":"m2","content":[{"type":"text","text":"Second answer"}]}}"#,
+        ];
+        std::fs::write(dir.join("parent.jsonl"), lines.join("\n") + "\n").unwrap();
+        assert!(manager
+            .fork_session(sink.emitter(), "parent", 4, ProcessMode::PerMessage, SessionOptions::default())
+            .is_err());
+
+        // An earlier message: the session resumes a trimmed copy
+        let id = manager
+            .fork_session(sink.emitter(), "parent", 1, ProcessMode::PerMessage, SessionOptions::default())
+            .unwrap();
+        let copy = manager.get_conversation_messages(&id, None, None).unwrap();
+        assert_eq!(copy.total, 2);
+        let fork = manager.get_conversation(&id).unwrap().unwrap().forked_from.unwrap();
+        assert_eq!((fork.conversation_id.as_str(), fork.message_index), ("parent", 1));
+        manager.send_message(sink.emitter(), &id, "Try again").unwrap();
+        sink.wait_for("claude:output", is_complete).await;
+        assert_eq!(backend.session_args(), vec![SessionArg::Resume(id.clone())]);
+        manager.stop_session(&sink.emitter(), &id).unwrap();
+
+        // The last message: the CLI forks and reports the new conversation
+        let id = manager
+            .fork_session(sink.emitter(), "parent", 3, ProcessMode::PerMessage, SessionOptions::default())
+            .unwrap();
+        manager.send_message(sink.emitter(), &id, "Go on").unwrap();
+        sink.wait_for("claude:session", |p| {
+            p["session_id"] == id && p["cli_session_id"] == "cli-session-1"
+        })
+        .await;
+        assert_eq!(backend.session_args()[1], SessionArg::Fork("parent".to_string()));
+        let meta = manager.store.conversation_meta().unwrap();
+        assert_eq!(meta["cli-session-1"].forked_from.as_ref().unwrap().message_index, 3);
+        manager.stop_session(&sink.emitter(), &id).unwrap();
+
+        // A fork that can't be linked to its parent isn't started
+        let copies = std::fs::read_dir(&dir).unwrap().count();
+        manager
+            .store
+            .with_conn(|conn| conn.execute("DROP TABLE claude_conversation_forks", []))
+            .unwrap();
+        assert!(manager
+            .fork_session(sink.emitter(), "parent", 1, ProcessMode::PerMessage, SessionOptions::default())
+            .is_err());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), copies);
+        assert!(manager.list_sessions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_trash_and_retention() {
+        let (manager, _backend, sink) = fake_manager(&[fake::HANG]);
+        let claude_dir = TempDir::new("retention");
+        let mut sessions = SessionManager::with_claude_dir(claude_dir.to_path_buf());
+        sessions.set_project_dir(&manager.working_dir);
+        let dir = sessions.project_conversations_dir().unwrap();
+        *manager.session_manager.lock() = sessions;
+        std::fs::create_dir_all(&dir).unwrap();
+        let line = r#"{"type":"user","uuid":"u1","timestamp":"2025-01-01T10:00:00Z","message":{"role":"user","content":"Old question"}}"#;
+        std::fs::write(dir.join("old-1.jsonl"), format!("{}\n", line)).unwrap();
+        std::fs::write(dir.join("old-2.jsonl"), format!("{}\n", line)).unwrap();
+        manager.store.set_conversation_title("old-2", Some("Kept")).unwrap();
+
+        // A conversation open in a session stays put
+        let id = manager
+            .start_session(
+                sink.emitter(),
+                Some("old-1".to_string()),
+                ProcessMode::PerMessage,
+                None,
+                SessionOptions::default(),
+            )
+            .unwrap();
+        assert!(matches!(
+            manager.trash_conversation("old-1"),
+            Err(ClaudeError::ConversationInUse(_))
+        ));
+        manager.stop_session(&sink.emitter(), &id).unwrap();
+
+        manager.trash_conversation("old-2").unwrap();
+        assert_eq!(manager.list_trash().unwrap()[0].conversation.title.as_deref(), Some("Kept"));
+
+        // Saving reports a dry run and changes nothing; trashed just now,
+        // old-2 isn't due
+        let settings = RetentionSettings {
+            archive_after_days: Some(30),
+            purge_trash_after_days: Some(1),
+        };
+        let report = manager.save_retention_settings(&settings).unwrap();
+        assert!(report.dry_run);
+        assert_eq!((report.archived.len(), report.purged.len()), (1, 0));
+        assert!(!manager.get_conversation("old-1").unwrap().unwrap().archived);
+        assert!(manager.retention_report().is_none());
+
+        manager.apply_retention(&sink.emitter());
+        assert!(manager.get_conversation("old-1").unwrap().unwrap().archived);
+        let applied = &sink.payloads("claude:retention-applied")[0];
+        assert_eq!(applied["dry_run"], false);
+        assert_eq!(applied["archived"][0]["id"], "old-1");
+        assert!(!manager.retention_report().unwrap().dry_run);
+
+        // A purge that fails is reported as such, not as purged
+        let trashed = dir.join(".trash").join("old-2.jsonl");
+        let two_days_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 86400);
+        std::fs::File::options()
+            .write(true)
+            .open(&trashed)
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+        let id = manager
+            .start_session(
+                sink.emitter(),
+                Some("old-2".to_string()),
+                ProcessMode::PerMessage,
+                None,
+                SessionOptions::default(),
+            )
+            .unwrap();
+        let report = manager.run_retention(None, false).unwrap();
+        assert!(report.purged.is_empty());
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!((report.failed[0].id.as_str(), report.failed[0].action), ("old-2", RetentionAction::Purge));
+        assert!(trashed.exists());
+        manager.stop_session(&sink.emitter(), &id).unwrap();
+
+        let report = manager.run_retention(None, false).unwrap();
+        assert_eq!(report.purged[0].conversation.id, "old-2");
+        assert!(report.failed.is_empty());
+        assert!(manager.list_trash().unwrap().is_empty());
+        assert!(!manager.store.conversation_meta().unwrap().contains_key("old-2"));
+    }
+
+    #[tokio::test]
+    async fn test_fake_stop_session() {
+        let (manager, _backend, sink) = fake_manager(&[fake::HANG]);
+        let id = start(&manager, &sink, SessionOptions::default());
+
+        manager.send_message(sink.emitter(), &id, "hello").unwrap();
+        manager.send_message(sink.emitter(), &id, "queued").unwrap();
+        sink.wait_for("claude:output", |p| p["content"] == "Starting...").await;
+        manager.stop_session(&sink.emitter(), &id).unwrap();
+
+        sink.wait_for("claude:status", |p| p["status"] == "inactive").await;
+        assert!(manager.session(&id).is_err());
+        let queue = sink.payloads("claude:queue");
+        assert_eq!(queue.last().unwrap()["messages"], serde_json::json!([]));
+    }
 }
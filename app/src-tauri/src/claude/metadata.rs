@@ -0,0 +1,383 @@
+//! App-side organisation of CLI conversations
+//!
+//! Transcripts belong to the CLI, so custom titles, pins, tags and the
+//! archived state live in an overlay keyed by the CLI session id
+//! (`claude_conversation_meta`). Tags are the app's own `tags`, linked
+//! through `claude_conversation_tags` the way tasks use `task_tags`, and
+//! forks point at their parent in `claude_conversation_forks`.
+
+use rusqlite::{params, ToSql};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::error::{ClaudeError, Result};
+use super::sessions::ConversationInfo;
+use super::store::ClaudeStore;
+
+/// Longest custom title accepted, in characters
+const MAX_TITLE_CHARS: usize = 200;
+
+/// A tag from the app's `tags` table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationTag {
+    pub id: String,
+    pub name: String,
+    pub color: Option<String>,
+}
+
+/// Where a forked conversation branched off its parent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForkPoint {
+    pub conversation_id: String,
+    /// Last message of the parent included in the fork
+    pub message_index: usize,
+}
+
+/// Overlay stored for one conversation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConversationMeta {
+    pub title: Option<String>,
+    pub pinned: bool,
+    pub archived: bool,
+    pub tags: Vec<ConversationTag>,
+    pub forked_from: Option<ForkPoint>,
+}
+
+/// Which conversations to list, by archived state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFilter {
+    #[default]
+    Active,
+    Archived,
+    All,
+}
+
+/// Filters for `claude_list_conversations`; the default lists every
+/// conversation that isn't archived
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConversationFilter {
+    pub archived: ArchiveFilter,
+    pub pinned_only: bool,
+    /// Tag ids the conversation must all have
+    pub tags: Vec<String>,
+    /// Case-insensitive text the title must contain
+    pub title: Option<String>,
+}
+
+impl ConversationFilter {
+    /// Every conversation, archived or not
+    pub fn all() -> Self {
+        Self {
+            archived: ArchiveFilter::All,
+            ..Self::default()
+        }
+    }
+
+    fn matches(&self, conversation: &ConversationInfo) -> bool {
+        let archived = match self.archived {
+            ArchiveFilter::Active => !conversation.archived,
+            ArchiveFilter::Archived => conversation.archived,
+            ArchiveFilter::All => true,
+        };
+        let tagged = self
+            .tags
+            .iter()
+            .all(|id| conversation.tags.iter().any(|tag| &tag.id == id));
+        let titled = match self.title.as_deref().map(str::trim) {
+            None | Some("") => true,
+            Some(text) => conversation
+                .title
+                .as_deref()
+                .is_some_and(|title| title.to_lowercase().contains(&text.to_lowercase())),
+        };
+        archived && tagged && titled && (conversation.pinned || !self.pinned_only)
+    }
+}
+
+/// Apply the overlay to a conversation
+pub fn merge(mut conversation: ConversationInfo, meta: Option<&ConversationMeta>) -> ConversationInfo {
+    if let Some(meta) = meta {
+        if meta.title.is_some() {
+            conversation.title = meta.title.clone();
+        }
+        conversation.pinned = meta.pinned;
+        conversation.archived = meta.archived;
+        conversation.tags = meta.tags.clone();
+        conversation.forked_from = meta.forked_from.clone();
+    }
+    conversation
+}
+
+/// Merge the overlay into `conversations` (most recent first) and filter
+/// them, keeping pinned conversations at the top
+pub fn apply(
+    conversations: Vec<ConversationInfo>,
+    meta: &HashMap<String, ConversationMeta>,
+    filter: &ConversationFilter,
+) -> Vec<ConversationInfo> {
+    let mut conversations: Vec<_> = conversations
+        .into_iter()
+        .map(|c| {
+            let meta = meta.get(&c.id);
+            merge(c, meta)
+        })
+        .filter(|c| filter.matches(c))
+        .collect();
+    // Stable, so each group stays most recent first
+    conversations.sort_by_key(|c| !c.pinned);
+    conversations
+}
+
+impl ClaudeStore {
+    /// The overlay of every conversation that has one
+    pub fn conversation_meta(&self) -> Result<HashMap<String, ConversationMeta>> {
+        self.with_conn(|conn| {
+            let mut meta: HashMap<String, ConversationMeta> = HashMap::new();
+
+            let mut stmt = conn.prepare(
+                "SELECT conversation_id, title, pinned, archived FROM claude_conversation_meta",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    ConversationMeta {
+                        title: row.get(1)?,
+                        pinned: row.get(2)?,
+                        archived: row.get(3)?,
+                        ..ConversationMeta::default()
+                    },
+                ))
+            })?;
+            for row in rows {
+                let (id, entry) = row?;
+                meta.insert(id, entry);
+            }
+
+            let mut stmt = conn.prepare(
+                "SELECT ct.conversation_id, t.id, t.name, t.color
+                 FROM claude_conversation_tags ct JOIN tags t ON t.id = ct.tag_id
+                 ORDER BY t.name",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    ConversationTag {
+                        id: row.get(1)?,
+                        name: row.get(2)?,
+                        color: row.get(3)?,
+                    },
+                ))
+            })?;
+            for row in rows {
+                let (id, tag) = row?;
+                meta.entry(id).or_default().tags.push(tag);
+            }
+
+            let mut stmt = conn.prepare(
+                "SELECT conversation_id, parent_id, message_index FROM claude_conversation_forks",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    ForkPoint {
+                        conversation_id: row.get(1)?,
+                        message_index: row.get::<_, i64>(2)? as usize,
+                    },
+                ))
+            })?;
+            for row in rows {
+                let (id, fork) = row?;
+                meta.entry(id).or_default().forked_from = Some(fork);
+            }
+
+            Ok(meta)
+        })
+    }
+
+    /// Give a conversation a custom title, or go back to the derived one
+    /// with `None` (or a blank title)
+    pub fn set_conversation_title(&self, id: &str, title: Option<&str>) -> Result<()> {
+        let title = title.map(str::trim).filter(|t| !t.is_empty());
+        if title.is_some_and(|t| t.chars().count() > MAX_TITLE_CHARS) {
+            return Err(ClaudeError::InvalidOption(format!(
+                "Title is longer than {} characters",
+                MAX_TITLE_CHARS
+            )));
+        }
+        self.set_meta_field(id, "title", &title)
+    }
+
+    pub fn set_conversation_pinned(&self, id: &str, pinned: bool) -> Result<()> {
+        self.set_meta_field(id, "pinned", &pinned)
+    }
+
+    pub fn set_conversation_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.set_meta_field(id, "archived", &archived)
+    }
+
+    fn set_meta_field(&self, id: &str, column: &'static str, value: &dyn ToSql) -> Result<()> {
+        let sql = format!(
+            "INSERT INTO claude_conversation_meta (conversation_id, {column}) VALUES (?1, ?2)
+             ON CONFLICT(conversation_id) DO UPDATE SET {column} = excluded.{column},
+                 updated_at = datetime('now')",
+            column = column
+        );
+        self.with_conn(|conn| conn.execute(&sql, params![id, value]).map(|_| ()))
+    }
+
+    /// Remember that conversation `id` was forked from `fork`
+    pub fn record_fork(&self, id: &str, fork: &ForkPoint) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO claude_conversation_forks
+                     (conversation_id, parent_id, message_index)
+                 VALUES (?1, ?2, ?3)",
+                params![id, fork.conversation_id, fork.message_index as i64],
+            )
+            .map(|_| ())
+        })
+    }
+
+    /// Drop the app's overlay of a conversation deleted for good
+    pub fn forget_conversation(&self, id: &str) -> Result<()> {
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            for table in [
+                "claude_conversation_meta",
+                "claude_conversation_tags",
+                "claude_conversation_forks",
+            ] {
+                let sql = format!("DELETE FROM {} WHERE conversation_id = ?1", table);
+                tx.execute(&sql, params![id])?;
+            }
+            tx.commit()
+        })
+    }
+
+    /// Replace a conversation's tags by name, creating tags that don't exist
+    pub fn set_conversation_tags(&self, id: &str, names: &[String]) -> Result<Vec<ConversationTag>> {
+        let mut seen = HashSet::new();
+        let names: Vec<&str> = names
+            .iter()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty() && seen.insert(name.to_string()))
+            .collect();
+
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "DELETE FROM claude_conversation_tags WHERE conversation_id = ?1",
+                params![id],
+            )?;
+            for name in &names {
+                tx.execute(
+                    "INSERT OR IGNORE INTO tags (id, name) VALUES (?1, ?2)",
+                    params![uuid::Uuid::new_v4().to_string(), name],
+                )?;
+                tx.execute(
+                    "INSERT OR IGNORE INTO claude_conversation_tags (conversation_id, tag_id)
+                     SELECT ?1, id FROM tags WHERE name = ?2",
+                    params![id, name],
+                )?;
+            }
+            tx.commit()
+        })?;
+
+        Ok(self
+            .conversation_meta()?
+            .remove(id)
+            .map(|meta| meta.tags)
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn conversation(id: &str, age_hours: i64) -> ConversationInfo {
+        let at = Utc::now() - Duration::hours(age_hours);
+        ConversationInfo {
+            id: id.to_string(),
+            title: Some(format!("Derived {}", id)),
+            preview: None,
+            created_at: at,
+            updated_at: at,
+            message_count: 1,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+            forked_from: None,
+        }
+    }
+
+    fn ids(conversations: &[ConversationInfo]) -> Vec<&str> {
+        conversations.iter().map(|c| c.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_overlay_and_filters() {
+        let store = ClaudeStore::open_in_memory().unwrap();
+        store.set_conversation_title("b", Some("  DATEV export fix ")).unwrap();
+        store.set_conversation_pinned("c", true).unwrap();
+        store.set_conversation_archived("d", true).unwrap();
+        let tags = store
+            .set_conversation_tags("b", &["finance".to_string(), " finance".to_string(), "tax".to_string()])
+            .unwrap();
+        assert_eq!(tags.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["finance", "tax"]);
+        // Existing tags are reused
+        let finance = store.set_conversation_tags("c", &["finance".to_string()]).unwrap();
+        assert_eq!(finance[0].id, tags[0].id);
+
+        let all = vec![conversation("a", 1), conversation("b", 2), conversation("c", 3), conversation("d", 4)];
+        let meta = store.conversation_meta().unwrap();
+
+        let listed = apply(all.clone(), &meta, &ConversationFilter::default());
+        assert_eq!(ids(&listed), ["c", "a", "b"]);
+        assert_eq!(listed[2].title.as_deref(), Some("DATEV export fix"));
+
+        let archived = ConversationFilter {
+            archived: ArchiveFilter::Archived,
+            ..ConversationFilter::default()
+        };
+        assert_eq!(ids(&apply(all.clone(), &meta, &archived)), ["d"]);
+
+        let tagged = ConversationFilter {
+            tags: vec![tags[0].id.clone(), tags[1].id.clone()],
+            ..ConversationFilter::default()
+        };
+        assert_eq!(ids(&apply(all.clone(), &meta, &tagged)), ["b"]);
+
+        let titled = ConversationFilter {
+            title: Some("datev".to_string()),
+            ..ConversationFilter::default()
+        };
+        assert_eq!(ids(&apply(all.clone(), &meta, &titled)), ["b"]);
+
+        let pinned = ConversationFilter {
+            pinned_only: true,
+            ..ConversationFilter::default()
+        };
+        assert_eq!(ids(&apply(all.clone(), &meta, &pinned)), ["c"]);
+
+        // Clearing the custom title and tags falls back to the derived data
+        store.set_conversation_title("b", Some(" ")).unwrap();
+        assert!(store.set_conversation_tags("b", &[]).unwrap().is_empty());
+        let meta = store.conversation_meta().unwrap();
+        let b = merge(conversation("b", 2), meta.get("b"));
+        assert_eq!((b.title.as_deref(), b.tags.len()), (Some("Derived b"), 0));
+
+        assert!(store.set_conversation_title("b", Some(&"x".repeat(201))).is_err());
+
+        let fork = ForkPoint {
+            conversation_id: "a".to_string(),
+            message_index: 3,
+        };
+        store.record_fork("e", &fork).unwrap();
+        let e = merge(conversation("e", 0), store.conversation_meta().unwrap().get("e"));
+        assert_eq!(e.forked_from, Some(fork));
+    }
+}
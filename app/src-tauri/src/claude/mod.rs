@@ -1,16 +1,47 @@
 //! Claude CLI integration module
 //!
-//! This module provides integration with the Claude Code CLI,
-//! using non-interactive print mode with streaming JSON output.
+//! This module provides integration with the Claude Code CLI, either
+//! spawning it per message in print mode or keeping one process alive with
+//! `--input-format stream-json`, both with streaming JSON output. Sessions
+//! can also talk to the Anthropic Messages API directly, without the CLI.
 
+mod api;
+mod auth;
+mod backend;
+mod diagnostics;
+mod discovery;
 mod error;
+mod events;
+mod export;
+#[cfg(test)]
+mod fake;
 mod manager;
+mod metadata;
+mod options;
 mod pty;
+mod queue;
+mod retention;
+mod search;
 mod sessions;
+mod store;
+#[cfg(test)]
+mod testutil;
+mod transcript;
+mod usage;
+mod watcher;
 
 // Re-export only what's needed by lib.rs
 pub use manager::{
-    claude_check_status, claude_get_session_state, claude_list_conversations,
-    claude_send_message, claude_start_session, claude_stop_session,
-    ClaudeManagerState,
+    claude_archive_conversation, claude_cancel_message, claude_check_status, claude_delete_preset,
+    claude_diagnostics, claude_edit_queued_message, claude_export_conversation, claude_fork_session,
+    claude_get_api_settings, claude_get_cli_status, claude_get_conversation_messages,
+    claude_get_export_settings, claude_get_retention_report, claude_get_retention_settings,
+    claude_get_session_state, claude_get_usage, claude_interrupt, claude_list_conversations,
+    claude_list_presets, claude_list_queued_messages, claude_list_sessions, claude_list_trash,
+    claude_pin_conversation, claude_purge_conversation, claude_remove_queued_message,
+    claude_rename_conversation, claude_restore_conversation, claude_run_retention,
+    claude_save_api_settings, claude_save_export_settings, claude_save_preset,
+    claude_save_retention_settings, claude_search_conversations, claude_send_message,
+    claude_set_api_key, claude_set_cli_path, claude_set_conversation_tags, claude_start_session,
+    claude_stop_session, claude_sync_exports, claude_trash_conversation, ClaudeManagerState,
 };
@@ -0,0 +1,426 @@
+//! Per-session Claude CLI options and named presets
+//!
+//! Options are validated before they reach the command line, and can be
+//! saved under a name (e.g. "triage" on a cheap model, "code" on a strong
+//! one) in the `claude_presets` table.
+
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::Command;
+
+use super::error::{ClaudeError, Result};
+use super::store::ClaudeStore;
+
+/// Model aliases understood by the CLI in addition to full model ids, with
+/// the id the API backend sends for each (`None`: the configured default)
+const MODEL_ALIASES: &[(&str, Option<&str>)] = &[
+    ("default", None),
+    ("sonnet", None),
+    ("sonnet[1m]", None),
+    ("opusplan", None),
+    ("opus", Some("claude-opus-4-1")),
+    ("haiku", Some("claude-haiku-4-5")),
+];
+
+/// Upper bound for `--max-turns`
+const MAX_TURNS_LIMIT: u32 = 200;
+
+/// Upper bound for `--append-system-prompt`, in bytes
+const MAX_APPEND_PROMPT_BYTES: usize = 32 * 1024;
+
+/// Highest prompt size limit a session may configure, in bytes
+const MAX_PROMPT_BYTES_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Upper bound for the message and idle timeouts, in seconds
+const MAX_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Tool permission mode (`--permission-mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionMode {
+    Default,
+    AcceptEdits,
+    BypassPermissions,
+    Plan,
+}
+
+impl PermissionMode {
+    /// Value passed on the command line
+    pub fn as_arg(self) -> &'static str {
+        match self {
+            PermissionMode::Default => "default",
+            PermissionMode::AcceptEdits => "acceptEdits",
+            PermissionMode::BypassPermissions => "bypassPermissions",
+            PermissionMode::Plan => "plan",
+        }
+    }
+}
+
+/// Options chosen per session (or stored in a preset)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionOptions {
+    pub model: Option<String>,
+    pub permission_mode: Option<PermissionMode>,
+    pub allowed_tools: Vec<String>,
+    pub disallowed_tools: Vec<String>,
+    pub max_turns: Option<u32>,
+    pub append_system_prompt: Option<String>,
+    pub add_dirs: Vec<String>,
+    /// Largest prompt accepted for sending, in bytes
+    pub max_prompt_bytes: Option<usize>,
+    /// Overall time limit for one response, in seconds
+    pub message_timeout_secs: Option<u64>,
+    /// Time without CLI output before the process is killed, in seconds
+    pub idle_timeout_secs: Option<u64>,
+    /// Stream the reply as it is generated, as `claude:partial` events
+    /// (`--include-partial-messages`)
+    pub include_partial_messages: bool,
+}
+
+impl SessionOptions {
+    /// Check every option, returning the first problem found
+    ///
+    /// The CLI resolves relative `add_dirs` against the session's working
+    /// directory, so they are only checked for existence when one is given;
+    /// presets aren't tied to a directory and are checked at launch.
+    pub fn validate(&self, working_dir: Option<&Path>) -> Result<()> {
+        if let Some(ref model) = self.model {
+            validate_model(model)?;
+        }
+        for rule in self.allowed_tools.iter().chain(&self.disallowed_tools) {
+            validate_tool_rule(rule)?;
+        }
+        if let Some(max_turns) = self.max_turns {
+            if max_turns == 0 || max_turns > MAX_TURNS_LIMIT {
+                return Err(ClaudeError::InvalidOption(format!(
+                    "max_turns must be between 1 and {}",
+                    MAX_TURNS_LIMIT
+                )));
+            }
+        }
+        if let Some(ref prompt) = self.append_system_prompt {
+            if prompt.len() > MAX_APPEND_PROMPT_BYTES {
+                return Err(ClaudeError::InvalidOption(format!(
+                    "append_system_prompt exceeds {} bytes",
+                    MAX_APPEND_PROMPT_BYTES
+                )));
+            }
+        }
+        if let Some(limit) = self.max_prompt_bytes {
+            if limit == 0 || limit > MAX_PROMPT_BYTES_LIMIT {
+                return Err(ClaudeError::InvalidOption(format!(
+                    "max_prompt_bytes must be between 1 and {}",
+                    MAX_PROMPT_BYTES_LIMIT
+                )));
+            }
+        }
+        for (name, secs) in [
+            ("message_timeout_secs", self.message_timeout_secs),
+            ("idle_timeout_secs", self.idle_timeout_secs),
+        ] {
+            if secs.is_some_and(|s| s == 0 || s > MAX_TIMEOUT_SECS) {
+                return Err(ClaudeError::InvalidOption(format!(
+                    "{} must be between 1 and {}",
+                    name, MAX_TIMEOUT_SECS
+                )));
+            }
+        }
+        for dir in &self.add_dirs {
+            let exists = match working_dir {
+                Some(working_dir) => working_dir.join(dir).is_dir(),
+                None => true,
+            };
+            if dir.trim().is_empty() || !exists {
+                return Err(ClaudeError::InvalidOption(format!(
+                    "add_dirs entry is not a directory: {}",
+                    dir
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Append the list-valued flags
+    ///
+    /// These flags take a variable number of values, so they must come
+    /// before any other option to keep later arguments from being swallowed.
+    pub fn apply_list_args(&self, cmd: &mut Command) {
+        if !self.allowed_tools.is_empty() {
+            cmd.arg("--allowedTools").args(&self.allowed_tools);
+        }
+        if !self.disallowed_tools.is_empty() {
+            cmd.arg("--disallowedTools").args(&self.disallowed_tools);
+        }
+        if !self.add_dirs.is_empty() {
+            cmd.arg("--add-dir").args(&self.add_dirs);
+        }
+    }
+
+    /// Append the single-valued flags
+    pub fn apply_args(&self, cmd: &mut Command) {
+        if let Some(ref model) = self.model {
+            cmd.arg("--model").arg(model);
+        }
+        if let Some(mode) = self.permission_mode {
+            cmd.arg("--permission-mode").arg(mode.as_arg());
+        }
+        if let Some(max_turns) = self.max_turns {
+            cmd.arg("--max-turns").arg(max_turns.to_string());
+        }
+        if let Some(ref prompt) = self.append_system_prompt {
+            cmd.arg("--append-system-prompt").arg(prompt);
+        }
+        if self.include_partial_messages {
+            cmd.arg("--include-partial-messages");
+        }
+    }
+}
+
+/// Accept CLI aliases and `claude-*` model ids (optionally with a `[1m]`
+/// context suffix)
+fn validate_model(model: &str) -> Result<()> {
+    let is_alias = MODEL_ALIASES.iter().any(|(alias, _)| *alias == model);
+    if is_alias || is_model_id(model.strip_suffix("[1m]").unwrap_or(model)) {
+        Ok(())
+    } else {
+        Err(ClaudeError::InvalidOption(format!("Unknown model: {}", model)))
+    }
+}
+
+/// Whether `id` is a well-formed full model id such as `claude-sonnet-4-5`
+pub fn is_model_id(id: &str) -> bool {
+    id.starts_with("claude-")
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_')
+}
+
+/// Model id to request from the Messages API for a session's `model`
+/// option; `default_model` stands in for the aliases that follow the
+/// CLI's default
+pub fn api_model_id(model: Option<&str>, default_model: &str) -> String {
+    let Some(model) = model else {
+        return default_model.to_string();
+    };
+    match MODEL_ALIASES.iter().find(|(alias, _)| *alias == model) {
+        Some((_, id)) => id.unwrap_or(default_model).to_string(),
+        None => model.strip_suffix("[1m]").unwrap_or(model).to_string(),
+    }
+}
+
+/// Accept `Tool`, `mcp__server__tool` and `Tool(specifier)` rules
+fn validate_tool_rule(rule: &str) -> Result<()> {
+    let invalid = || ClaudeError::InvalidOption(format!("Invalid tool rule: {:?}", rule));
+    let (name, specifier) = match rule.split_once('(') {
+        Some((name, rest)) => (name, Some(rest.strip_suffix(')').ok_or_else(invalid)?)),
+        None => (rule, None),
+    };
+    let name_ok = !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let specifier_ok = specifier.is_none_or(|s| !s.trim().is_empty());
+    if name_ok && specifier_ok {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// A named set of session options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPreset {
+    pub name: String,
+    pub options: SessionOptions,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+}
+
+impl ClaudeStore {
+    /// All presets, ordered by name
+    pub fn list_presets(&self) -> Result<Vec<SessionPreset>> {
+        let rows: Vec<(String, String, Option<String>)> = self.with_conn(|conn| {
+            let mut stmt =
+                conn.prepare("SELECT name, options, updated_at FROM claude_presets ORDER BY name")?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+            rows.collect()
+        })?;
+
+        rows.into_iter()
+            .map(|(name, options, updated_at)| {
+                Ok(SessionPreset {
+                    options: parse_preset_options(&name, &options)?,
+                    name,
+                    updated_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Look up a preset by name
+    pub fn get_preset(&self, name: &str) -> Result<SessionPreset> {
+        let row: Option<(String, Option<String>)> = self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT options, updated_at FROM claude_presets WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+        })?;
+        let (options, updated_at) = row.ok_or_else(|| ClaudeError::PresetNotFound(name.to_string()))?;
+
+        Ok(SessionPreset {
+            name: name.to_string(),
+            options: parse_preset_options(name, &options)?,
+            updated_at,
+        })
+    }
+
+    /// Create or replace a preset after validating its options
+    pub fn save_preset(&self, name: &str, options: &SessionOptions) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() || name.len() > 64 {
+            return Err(ClaudeError::InvalidOption(
+                "Preset name must be 1-64 characters".to_string(),
+            ));
+        }
+        options.validate(None)?;
+        let json = serde_json::to_string(options)
+            .map_err(|e| ClaudeError::InvalidOption(e.to_string()))?;
+
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO claude_presets (name, options) VALUES (?1, ?2)
+                 ON CONFLICT(name) DO UPDATE SET options = excluded.options,
+                     updated_at = datetime('now')",
+                params![name, json],
+            )
+            .map(|_| ())
+        })
+    }
+
+    /// Delete a preset
+    pub fn delete_preset(&self, name: &str) -> Result<()> {
+        let deleted = self.with_conn(|conn| {
+            conn.execute("DELETE FROM claude_presets WHERE name = ?1", params![name])
+        })?;
+        if deleted == 0 {
+            return Err(ClaudeError::PresetNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn parse_preset_options(name: &str, json: &str) -> Result<SessionOptions> {
+    serde_json::from_str(json)
+        .map_err(|e| ClaudeError::InvalidOption(format!("Preset {} is corrupt: {}", name, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testutil::TempDir;
+
+    #[test]
+    fn test_validate_models() {
+        assert!(validate_model("sonnet").is_ok());
+        assert!(validate_model("claude-sonnet-4-5-20250929").is_ok());
+        assert!(validate_model("claude-sonnet-4-5[1m]").is_ok());
+        assert!(validate_model("gpt-4").is_err());
+        assert!(validate_model("claude-x; rm -rf /").is_err());
+    }
+
+    #[test]
+    fn test_api_model_ids() {
+        assert_eq!(api_model_id(None, "claude-sonnet-4-5"), "claude-sonnet-4-5");
+        assert_eq!(api_model_id(Some("opusplan"), "claude-sonnet-4-5"), "claude-sonnet-4-5");
+        assert_eq!(api_model_id(Some("haiku"), "claude-sonnet-4-5"), "claude-haiku-4-5");
+        assert_eq!(api_model_id(Some("claude-opus-4-1[1m]"), "claude-sonnet-4-5"), "claude-opus-4-1");
+        assert!(is_model_id("claude-3_5-custom"));
+        assert!(!is_model_id("sonnet"));
+    }
+
+    #[test]
+    fn test_validate_tool_rules() {
+        assert!(validate_tool_rule("Read").is_ok());
+        assert!(validate_tool_rule("mcp__tasks__list_tasks").is_ok());
+        assert!(validate_tool_rule("Bash(git log:*)").is_ok());
+        assert!(validate_tool_rule("").is_err());
+        assert!(validate_tool_rule("Bash(git").is_err());
+        assert!(validate_tool_rule("Bash()").is_err());
+        assert!(validate_tool_rule("Two Words").is_err());
+    }
+
+    #[test]
+    fn test_validate_options() {
+        let mut options = SessionOptions {
+            model: Some("haiku".to_string()),
+            max_turns: Some(5),
+            ..Default::default()
+        };
+        assert!(options.validate(None).is_ok());
+
+        options.max_turns = Some(0);
+        assert!(options.validate(None).is_err());
+
+        options.max_turns = None;
+        options.add_dirs = vec!["/definitely/not/a/dir".to_string()];
+        assert!(options.validate(Some(Path::new("/"))).is_err());
+        assert!(options.validate(None).is_ok());
+
+        // Relative entries resolve against the session's directory
+        let dir = TempDir::new("options");
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        options.add_dirs = vec!["docs".to_string()];
+        assert!(options.validate(Some(&dir)).is_ok());
+        assert!(options.validate(Some(&dir.join("docs"))).is_err());
+    }
+
+    #[test]
+    fn test_apply_args() {
+        let options = SessionOptions {
+            model: Some("haiku".to_string()),
+            include_partial_messages: true,
+            ..Default::default()
+        };
+        let mut cmd = Command::new("claude");
+        options.apply_args(&mut cmd);
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect();
+        assert_eq!(args, ["--model", "haiku", "--include-partial-messages"]);
+    }
+
+    #[test]
+    fn test_preset_roundtrip() {
+        let store = ClaudeStore::open_in_memory().unwrap();
+        let options = SessionOptions {
+            model: Some("haiku".to_string()),
+            permission_mode: Some(PermissionMode::Plan),
+            allowed_tools: vec!["mcp__tasks__list_tasks".to_string()],
+            ..Default::default()
+        };
+        store.save_preset("triage", &options).unwrap();
+        assert_eq!(store.get_preset("triage").unwrap().options, options);
+
+        let updated = SessionOptions {
+            model: Some("opus".to_string()),
+            ..Default::default()
+        };
+        store.save_preset("triage", &updated).unwrap();
+        let presets = store.list_presets().unwrap();
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].options, updated);
+
+        store.delete_preset("triage").unwrap();
+        assert!(matches!(
+            store.get_preset("triage"),
+            Err(ClaudeError::PresetNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_permission_mode_serde() {
+        let mode: PermissionMode = serde_json::from_str("\"acceptEdits\"").unwrap();
+        assert_eq!(mode, PermissionMode::AcceptEdits);
+        assert_eq!(mode.as_arg(), "acceptEdits");
+    }
+}
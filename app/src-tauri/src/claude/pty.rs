@@ -2,27 +2,84 @@
 //!
 //! Uses `claude -p --output-format stream-json` for clean, non-TUI output.
 
-use serde::Deserialize;
-use std::io::{BufRead, BufReader};
-use std::process::{Child, Command, Stdio};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command as AsyncCommand};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
-use super::error::{ClaudeError, Result};
+use super::discovery::path_env_for;
+use super::error::{ClaudeError, Result, TimeoutKind};
+use super::options::SessionOptions;
 
 /// Configuration for the Claude process
+#[derive(Debug, Clone, Default)]
 pub struct ProcessConfig {
     pub working_dir: String,
     pub mcp_config_path: Option<String>,
     pub system_prompt: Option<String>,
+    pub options: SessionOptions,
 }
 
-impl Default for ProcessConfig {
-    fn default() -> Self {
-        Self {
-            working_dir: String::new(),
-            mcp_config_path: None,
-            system_prompt: None,
+/// Default prompt size limit when the session options don't set one
+pub const DEFAULT_MAX_PROMPT_BYTES: usize = 2 * 1024 * 1024;
+
+/// Default overall time limit for one message
+pub const DEFAULT_MESSAGE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Default time without any CLI output before the process is considered
+/// hung; long enough for a slow tool call (Bash allows up to 10 minutes)
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(11 * 60);
+
+/// Capacity of the channel carrying parsed output to the manager
+pub const OUTPUT_CHANNEL_CAPACITY: usize = 256;
+
+/// Time limits applied to each message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    /// Overall deadline for the response
+    pub message: Duration,
+    /// Maximum gap between two lines of output
+    pub idle: Duration,
+}
+
+impl ProcessConfig {
+    /// Time limits from the session options, falling back to the defaults
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            message: self
+                .options
+                .message_timeout_secs
+                .map_or(DEFAULT_MESSAGE_TIMEOUT, Duration::from_secs),
+            idle: self
+                .options
+                .idle_timeout_secs
+                .map_or(DEFAULT_IDLE_TIMEOUT, Duration::from_secs),
+        }
+    }
+
+    /// Prompt size limit in bytes
+    pub fn max_prompt_bytes(&self) -> usize {
+        self.options.max_prompt_bytes.unwrap_or(DEFAULT_MAX_PROMPT_BYTES)
+    }
+
+    /// Reject prompts above the configured size limit
+    pub fn check_prompt_size(&self, message: &str) -> Result<()> {
+        let limit = self.max_prompt_bytes();
+        if message.len() > limit {
+            return Err(ClaudeError::PromptTooLarge {
+                size: message.len(),
+                limit,
+            });
         }
+        Ok(())
     }
 }
 
@@ -37,24 +94,88 @@ pub enum StreamEvent {
         session_id: String,
         #[serde(default)]
         tools: Vec<String>,
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        cwd: Option<String>,
+        #[serde(default, rename = "permissionMode")]
+        permission_mode: Option<String>,
+        #[serde(default)]
+        mcp_servers: Vec<McpServerStatus>,
     },
     #[serde(rename = "assistant")]
     Assistant {
         message: AssistantMessage,
         session_id: String,
+        #[serde(default)]
+        parent_tool_use_id: Option<String>,
     },
+    #[serde(rename = "user")]
+    User {
+        message: UserMessage,
+        session_id: String,
+        #[serde(default)]
+        parent_tool_use_id: Option<String>,
+    },
+    #[serde(rename = "stream_event")]
+    Partial {
+        event: serde_json::Value,
+        session_id: String,
+        #[serde(default)]
+        parent_tool_use_id: Option<String>,
+    },
+    #[serde(rename = "control_response")]
+    ControlResponse { response: ControlResponse },
     #[serde(rename = "result")]
     Result {
         subtype: String,
+        #[serde(default)]
         result: String,
         session_id: String,
         #[serde(default)]
         is_error: bool,
+        #[serde(default)]
+        usage: Usage,
+        #[serde(default)]
+        total_cost_usd: f64,
+        #[serde(default)]
+        duration_ms: u64,
+        #[serde(default)]
+        duration_api_ms: u64,
+        #[serde(default)]
+        num_turns: u32,
     },
     #[serde(other)]
     Unknown,
 }
 
+/// MCP server connection state reported in the `system` init event
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct McpServerStatus {
+    pub name: String,
+    pub status: String,
+}
+
+/// Session details reported by the `system` init event
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct SessionInit {
+    pub model: Option<String>,
+    pub tools: Vec<String>,
+    pub cwd: Option<String>,
+    pub permission_mode: Option<String>,
+    pub mcp_servers: Vec<McpServerStatus>,
+}
+
+/// Answer to a `control_request` we wrote (e.g. an interrupt)
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ControlResponse {
+    pub subtype: String,
+    #[serde(default)]
+    pub request_id: String,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 #[allow(dead_code)]
 pub struct AssistantMessage {
@@ -64,228 +185,857 @@ pub struct AssistantMessage {
     pub content: Vec<ContentBlock>,
     #[serde(default)]
     pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
     // Allow any other fields to be ignored
     #[serde(flatten)]
     pub _extra: std::collections::HashMap<String, serde_json::Value>,
 }
 
+/// User turn echoed back by the CLI (prompts and tool results)
+#[derive(Debug, Deserialize)]
+pub struct UserMessage {
+    pub content: UserContent,
+}
+
+/// User content is either a plain string or a list of content blocks
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum UserContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+/// Token usage as reported by the Messages API
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default)]
+    pub cache_read_input_tokens: u64,
+}
+
+/// Usage and cost of a completed turn, from the `result` event
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct TurnUsage {
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub total_cost_usd: f64,
+    pub duration_ms: u64,
+    pub duration_api_ms: u64,
+    pub num_turns: u32,
+    pub is_error: bool,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 pub enum ContentBlock {
     #[serde(rename = "text")]
     Text { text: String },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: serde_json::Value,
+    },
+    #[serde(rename = "tool_result")]
+    ToolResult {
+        tool_use_id: String,
+        #[serde(default)]
+        content: Option<ToolResultContent>,
+        #[serde(default)]
+        is_error: bool,
+    },
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking,
     #[serde(other)]
     Other,
 }
 
+/// Tool result payload: plain text or a list of text/image blocks
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ToolResultContent {
+    Text(String),
+    Blocks(Vec<ToolResultBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToolResultBlock {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(other)]
+    Other,
+}
+
+impl ToolResultContent {
+    /// Flatten the result into displayable text, skipping non-text blocks
+    pub fn into_text(self) -> String {
+        match self {
+            ToolResultContent::Text(text) => text,
+            ToolResultContent::Blocks(blocks) => blocks
+                .into_iter()
+                .filter_map(|b| match b {
+                    ToolResultBlock::Text { text } => Some(text),
+                    ToolResultBlock::Other => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
 /// Output from parsing a stream event
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParsedOutput {
     SessionId(String),
-    Text(String),
+    /// Model, tools and MCP servers the session started with
+    Init(SessionInit),
+    /// `parent_tool_use_id` is set on everything a subagent produced
+    Text {
+        text: String,
+        parent_tool_use_id: Option<String>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+        parent_tool_use_id: Option<String>,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        is_error: bool,
+        parent_tool_use_id: Option<String>,
+    },
+    Thinking {
+        thinking: String,
+        parent_tool_use_id: Option<String>,
+    },
+    UserText(String),
+    /// Raw Messages API stream event, sent when the session enabled
+    /// `include_partial_messages`
+    Partial {
+        event: serde_json::Value,
+        parent_tool_use_id: Option<String>,
+    },
+    ControlResponse(ControlResponse),
+    Usage(TurnUsage),
     Complete,
     Error(String),
+    /// The watchdog killed the process
+    Timeout { kind: TimeoutKind, secs: u64 },
 }
 
 /// Parse a JSON line from Claude CLI output
-pub fn parse_stream_line(line: &str) -> Option<ParsedOutput> {
+///
+/// A single event can carry several content blocks (e.g. text followed by
+/// a tool call), so this returns every output in stream order.
+pub fn parse_stream_line(line: &str) -> Vec<ParsedOutput> {
     // Try to parse as JSON
     let event: StreamEvent = match serde_json::from_str(line) {
         Ok(e) => e,
         Err(e) => {
-            eprintln!("[Claude] JSON parse error: {} for line: {}", e, &line[..line.len().min(200)]);
-            return None;
+            eprintln!("[Claude] JSON parse error: {} for line: {}", e, truncate_for_log(line, 200));
+            return Vec::new();
         }
     };
 
     match event {
-        StreamEvent::System { session_id, .. } => {
-            eprintln!("[Claude] Got session_id: {}", session_id);
-            Some(ParsedOutput::SessionId(session_id))
+        StreamEvent::System {
+            subtype,
+            session_id,
+            tools,
+            model,
+            cwd,
+            permission_mode,
+            mcp_servers,
+        } => {
+            eprintln!("[Claude] Got session_id: {} ({})", session_id, subtype);
+            let mut outputs = vec![ParsedOutput::SessionId(session_id)];
+            if subtype == "init" {
+                outputs.push(ParsedOutput::Init(SessionInit {
+                    model,
+                    tools,
+                    cwd,
+                    permission_mode,
+                    mcp_servers,
+                }));
+            }
+            outputs
         }
-        StreamEvent::Assistant { message, .. } => {
-            // Extract text from content blocks
+        StreamEvent::Assistant {
+            message,
+            parent_tool_use_id,
+            ..
+        } => {
+            // Keep consecutive text blocks together, as before
+            let mut outputs = Vec::new();
             let mut text_parts = Vec::new();
             for block in message.content {
                 if let ContentBlock::Text { text } = block {
                     text_parts.push(text);
+                    continue;
+                }
+                if !text_parts.is_empty() {
+                    outputs.push(ParsedOutput::Text {
+                        text: text_parts.join(""),
+                        parent_tool_use_id: parent_tool_use_id.clone(),
+                    });
+                    text_parts.clear();
+                }
+                if let Some(output) = parse_content_block(block, &parent_tool_use_id) {
+                    outputs.push(output);
                 }
             }
             if !text_parts.is_empty() {
                 let combined = text_parts.join("");
                 eprintln!("[Claude] Got text: {} chars", combined.len());
-                return Some(ParsedOutput::Text(combined));
+                outputs.push(ParsedOutput::Text {
+                    text: combined,
+                    parent_tool_use_id,
+                });
             }
-            None
+            outputs
+        }
+        StreamEvent::User {
+            message,
+            parent_tool_use_id,
+            ..
+        } => match message.content {
+            UserContent::Text(text) => vec![ParsedOutput::UserText(text)],
+            UserContent::Blocks(blocks) => blocks
+                .into_iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(ParsedOutput::UserText(text)),
+                    other => parse_content_block(other, &parent_tool_use_id),
+                })
+                .collect(),
+        },
+        StreamEvent::Partial {
+            event,
+            parent_tool_use_id,
+            ..
+        } => vec![ParsedOutput::Partial {
+            event,
+            parent_tool_use_id,
+        }],
+        StreamEvent::ControlResponse { response } => {
+            eprintln!("[Claude] Got control_response: {}", response.subtype);
+            vec![ParsedOutput::ControlResponse(response)]
         }
-        StreamEvent::Result { result, is_error, .. } => {
-            eprintln!("[Claude] Got result (is_error={})", is_error);
+        StreamEvent::Result {
+            result,
+            is_error,
+            usage,
+            total_cost_usd,
+            duration_ms,
+            duration_api_ms,
+            num_turns,
+            ..
+        } => {
+            eprintln!("[Claude] Got result (is_error={}, cost=${:.4})", is_error, total_cost_usd);
+            let usage = ParsedOutput::Usage(TurnUsage {
+                usage,
+                total_cost_usd,
+                duration_ms,
+                duration_api_ms,
+                num_turns,
+                is_error,
+            });
             if is_error {
-                Some(ParsedOutput::Error(result))
+                vec![usage, ParsedOutput::Error(result)]
             } else {
-                Some(ParsedOutput::Complete)
+                vec![usage, ParsedOutput::Complete]
             }
         }
         StreamEvent::Unknown => {
-            eprintln!("[Claude] Unknown event type in line: {}", &line[..line.len().min(100)]);
-            None
+            eprintln!("[Claude] Unknown event type in line: {}", truncate_for_log(line, 100));
+            Vec::new()
         }
     }
 }
 
-/// Wrapper around Claude CLI process (non-interactive)
-pub struct ClaudeProcess {
-    config: ProcessConfig,
-    current_child: Option<Child>,
+/// Convert a non-text content block into its parsed output
+fn parse_content_block(block: ContentBlock, parent_tool_use_id: &Option<String>) -> Option<ParsedOutput> {
+    match block {
+        ContentBlock::Text { text } => Some(ParsedOutput::Text {
+            text,
+            parent_tool_use_id: parent_tool_use_id.clone(),
+        }),
+        ContentBlock::ToolUse { id, name, input } => {
+            eprintln!("[Claude] Got tool_use: {} ({})", name, id);
+            Some(ParsedOutput::ToolUse {
+                id,
+                name,
+                input,
+                parent_tool_use_id: parent_tool_use_id.clone(),
+            })
+        }
+        ContentBlock::ToolResult { tool_use_id, content, is_error } => {
+            Some(ParsedOutput::ToolResult {
+                tool_use_id,
+                content: content.map(ToolResultContent::into_text).unwrap_or_default(),
+                is_error,
+                parent_tool_use_id: parent_tool_use_id.clone(),
+            })
+        }
+        ContentBlock::Thinking { thinking } => Some(ParsedOutput::Thinking {
+            thinking,
+            parent_tool_use_id: parent_tool_use_id.clone(),
+        }),
+        ContentBlock::RedactedThinking | ContentBlock::Other => None,
+    }
 }
 
-impl ClaudeProcess {
-    pub fn new(config: ProcessConfig) -> Self {
-        Self {
-            config,
-            current_child: None,
-        }
+/// How the CLI is driven for a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessMode {
+    /// Spawn `claude -p` per message and continue with `--resume`
+    #[default]
+    PerMessage,
+    /// Keep one stream-json child alive and write turns to its stdin
+    Persistent,
+}
+
+/// Which CLI conversation a spawned process should attach to
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionArg {
+    /// Start a conversation with this id (`--session-id`)
+    New(String),
+    /// Continue a conversation the CLI already knows (`--resume`)
+    Resume(String),
+    /// Continue a copy of a conversation under a new id (`--fork-session`)
+    Fork(String),
+}
+
+/// Build the base `claude -p` command shared by both process modes
+fn build_command(cli_path: &str, config: &ProcessConfig, session: &SessionArg) -> Result<Command> {
+    let mut cmd = Command::new(cli_path);
+
+    // Let npm-installed CLIs find the `node` they were installed with
+    if let Some(path_env) = path_env_for(Path::new(cli_path)) {
+        cmd.env("PATH", path_env);
     }
 
-    /// Send a message to Claude and stream the response
-    /// Returns the session_id for future resume operations
-    pub fn send_message(
-        &mut self,
-        message: &str,
-        resume_id: Option<&str>,
-        output_tx: mpsc::UnboundedSender<String>,
-    ) -> Result<Option<String>> {
-        // Find claude CLI path
-        let claude_path = find_claude_path()
-            .ok_or_else(|| ClaudeError::SpawnFailed("Claude CLI not found".to_string()))?;
-
-        // Build command
-        let mut cmd = Command::new(&claude_path);
-        cmd.arg("-p")  // Print mode (non-interactive)
-            .arg("--output-format")
-            .arg("stream-json")
-            .arg("--verbose");  // Required for stream-json
-
-        // Set working directory
-        if !self.config.working_dir.is_empty() {
-            cmd.current_dir(&self.config.working_dir);
+    // Variadic flags first; the fixed flags below terminate their value lists
+    config.options.apply_list_args(&mut cmd);
+
+    cmd.arg("-p")  // Print mode (non-interactive)
+        .arg("--output-format")
+        .arg("stream-json")
+        .arg("--verbose");  // Required for stream-json
+
+    // Set working directory
+    if !config.working_dir.is_empty() {
+        cmd.current_dir(&config.working_dir);
+    }
+
+    // Pin the session id for new conversations, resume existing ones
+    match session {
+        SessionArg::New(id) => cmd.arg("--session-id").arg(id),
+        SessionArg::Resume(id) => cmd.arg("--resume").arg(id),
+        SessionArg::Fork(id) => cmd.arg("--resume").arg(id).arg("--fork-session"),
+    };
+
+    // Add MCP config if available
+    if let Some(ref mcp_path) = config.mcp_config_path {
+        cmd.arg("--mcp-config").arg(mcp_path);
+    }
+
+    // Add system prompt if configured
+    if let Some(ref prompt) = config.system_prompt {
+        cmd.arg("--system-prompt").arg(prompt);
+    }
+
+    // Model, permission mode, max turns, appended prompt, partial messages
+    config.options.apply_args(&mut cmd);
+
+    // Own process group, so cancelling also stops MCP servers and tools
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    eprintln!("[Claude] Spawning: {} -p --output-format stream-json --verbose", cli_path);
+    if !config.working_dir.is_empty() {
+        eprintln!("[Claude] Working dir: {}", config.working_dir);
+    }
+    eprintln!("[Claude] Session: {:?}", session);
+
+    Ok(cmd)
+}
+
+/// Shorten a string for logging without splitting a UTF-8 character
+fn truncate_for_log(s: &str, max: usize) -> &str {
+    match s.char_indices().nth(max) {
+        Some((idx, _)) => &s[..idx],
+        None => s,
+    }
+}
+
+/// Cloneable handle for killing a running CLI process from another task
+///
+/// Killing before the process has spawned cancels the token, and the runner
+/// kills the child as soon as it exists.
+#[derive(Clone, Default)]
+pub struct KillHandle {
+    pid: Arc<Mutex<Option<u32>>>,
+    token: CancellationToken,
+}
+
+impl KillHandle {
+    /// Record the running child, killing it right away if already cancelled
+    fn attach(&self, pid: u32) {
+        let mut slot = self.pid.lock();
+        *slot = Some(pid);
+        if self.is_cancelled() {
+            let _ = kill_process_tree(pid);
         }
+    }
 
-        // Resume existing session
-        if let Some(id) = resume_id {
-            cmd.arg("--resume").arg(id);
+    /// Forget the child once it has been reaped
+    fn detach(&self) {
+        *self.pid.lock() = None;
+    }
+
+    /// Kill the process and its whole process group
+    pub fn kill(&self) -> Result<()> {
+        self.token.cancel();
+        self.terminate()
+    }
+
+    /// Kill the process group without marking the handle cancelled, so the
+    /// turn is reported as failed rather than cancelled (e.g. on timeout)
+    fn terminate(&self) -> Result<()> {
+        if let Some(pid) = *self.pid.lock() {
+            eprintln!("[Claude] Killing process group {}", pid);
+            kill_process_tree(pid)?;
         }
+        Ok(())
+    }
+
+    /// Whether `kill` was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Resolves once `kill` is requested
+    pub async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+}
+
+/// Kill a child spawned as process group leader, together with its
+/// descendants (MCP servers, tool subprocesses)
+#[cfg(unix)]
+fn kill_process_tree(pid: u32) -> std::io::Result<()> {
+    // A negative pid signals every process in the group
+    let ret = unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+    if ret == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::ESRCH) {
+        Ok(()) // Already gone
+    } else {
+        Err(err)
+    }
+}
+
+#[cfg(windows)]
+fn kill_process_tree(pid: u32) -> std::io::Result<()> {
+    Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .output()
+        .map(|_| ())
+}
 
-        // Add MCP config if available
-        if let Some(ref mcp_path) = self.config.mcp_config_path {
-            cmd.arg("--mcp-config").arg(mcp_path);
+/// Number of stderr lines kept per process
+const STDERR_BUFFER_LINES: usize = 200;
+
+/// Bounded ring buffer of the most recent stderr lines of a process
+///
+/// Lines are still echoed to the app's own stderr for debugging.
+#[derive(Clone)]
+pub struct StderrBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl Default for StderrBuffer {
+    fn default() -> Self {
+        Self::with_capacity(STDERR_BUFFER_LINES)
+    }
+}
+
+impl StderrBuffer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
         }
+    }
 
-        // Add system prompt if configured
-        if let Some(ref prompt) = self.config.system_prompt {
-            cmd.arg("--system-prompt").arg(prompt);
+    /// Append a line, dropping the oldest one when full
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock();
+        if lines.len() == self.capacity {
+            lines.pop_front();
         }
+        lines.push_back(line);
+    }
 
-        // Add the message
-        cmd.arg(message);
+    /// Snapshot of the buffered lines, oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().iter().cloned().collect()
+    }
 
-        // Configure stdio - inherit stderr so it doesn't block, pipe stdout for JSON
-        cmd.stdout(Stdio::piped())
-            .stderr(Stdio::inherit());  // Let stderr go to console for debugging
+    /// Read `stderr` line by line on a background task until it closes
+    pub fn capture(&self, stderr: impl AsyncRead + Unpin + Send + 'static) -> JoinHandle<()> {
+        let buffer = self.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                eprintln!("[Claude stderr] {}", line);
+                buffer.push(line);
+            }
+        })
+    }
+}
+
+/// Wait briefly for the stderr reader to drain
+///
+/// Grandchildren (e.g. MCP servers) can keep the pipe open after the CLI
+/// exits, so this never blocks for long.
+async fn drain_stderr(reader: Option<JoinHandle<()>>) {
+    if let Some(reader) = reader {
+        let _ = tokio::time::timeout(Duration::from_millis(500), reader).await;
+    }
+}
+
+/// How a CLI process ended
+#[derive(Debug, Clone, Default)]
+pub struct ProcessExit {
+    /// Exit code, `None` if the process was killed by a signal
+    pub code: Option<i32>,
+    /// Most recent stderr lines
+    pub stderr: Vec<String>,
+}
 
-        eprintln!("[Claude] Spawning: {} -p --output-format stream-json --verbose", claude_path);
-        eprintln!("[Claude] Message: {}", &message[..message.len().min(100)]);
-        if !self.config.working_dir.is_empty() {
-            eprintln!("[Claude] Working dir: {}", self.config.working_dir);
+impl ProcessExit {
+    fn new(status: &ExitStatus, stderr: &StderrBuffer) -> Self {
+        Self {
+            code: status.code(),
+            stderr: stderr.lines(),
         }
-        if let Some(id) = resume_id {
-            eprintln!("[Claude] Resume ID: {}", id);
+    }
+
+    /// Map an unsuccessful exit onto a `ClaudeError`
+    ///
+    /// Known messages on stderr (auth, rate limit, overload) take priority;
+    /// anything else is reported as an unexpected termination.
+    pub fn error(&self) -> Option<ClaudeError> {
+        if self.code == Some(0) {
+            return None;
         }
+        Some(ClaudeError::from_cli_output(&self.stderr.join("\n")).unwrap_or(ClaudeError::ProcessTerminated))
+    }
+}
 
-        // Spawn process
-        let mut child = cmd.spawn()
-            .map_err(|e| {
-                eprintln!("[Claude] Failed to spawn: {}", e);
-                ClaudeError::SpawnFailed(e.to_string())
-            })?;
+/// How often the watchdog checks for expired timeouts
+pub const WATCHDOG_TICK: Duration = Duration::from_secs(1);
 
-        eprintln!("[Claude] Process spawned successfully");
+/// How long a process may take to exit after its output has ended
+const EXIT_GRACE: Duration = Duration::from_secs(10);
 
-        let stdout = child.stdout.take()
-            .ok_or_else(|| ClaudeError::PtyError("Failed to capture stdout".to_string()))?;
+/// Tracks the per-message deadline and idle-output limit of a turn
+#[derive(Debug)]
+pub struct Watchdog {
+    timeouts: Timeouts,
+    /// Start of the current turn, `None` while idle between turns
+    started: Option<Instant>,
+    last_output: Instant,
+}
 
-        self.current_child = Some(child);
+impl Watchdog {
+    pub fn new(timeouts: Timeouts) -> Self {
+        Self {
+            timeouts,
+            started: None,
+            last_output: Instant::now(),
+        }
+    }
 
-        // Read and parse output
-        let reader = BufReader::new(stdout);
-        let mut session_id: Option<String> = None;
-        let mut line_count = 0;
+    /// Arm the watchdog for a new turn
+    pub fn start(&mut self) {
+        let now = Instant::now();
+        self.started = Some(now);
+        self.last_output = now;
+    }
 
-        eprintln!("[Claude] Starting to read stdout lines...");
+    /// Disarm once the turn has finished
+    pub fn stop(&mut self) {
+        self.started = None;
+    }
+
+    /// Record output from the CLI
+    pub fn touch(&mut self) {
+        self.last_output = Instant::now();
+    }
 
-        for line in reader.lines() {
-            line_count += 1;
-            match line {
-                Ok(json_line) => {
+    /// The limit that was exceeded, if any
+    pub fn expired(&self) -> Option<ClaudeError> {
+        let started = self.started?;
+        if started.elapsed() >= self.timeouts.message {
+            Some(ClaudeError::Timeout {
+                kind: TimeoutKind::Deadline,
+                secs: self.timeouts.message.as_secs(),
+            })
+        } else if self.last_output.elapsed() >= self.timeouts.idle {
+            Some(ClaudeError::Timeout {
+                kind: TimeoutKind::Idle,
+                secs: self.timeouts.idle.as_secs(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// What `read_turn` observed
+pub struct TurnOutput {
+    /// Session id reported by the CLI
+    pub session_id: Option<String>,
+    /// Set if the watchdog stopped reading
+    pub timeout: Option<ClaudeError>,
+}
+
+/// Read one turn of stream-json output, forwarding parsed events
+///
+/// Stops at the `result` event, at end of output, when `kill_handle` is
+/// cancelled, or when the message deadline or idle timeout expires. The
+/// caller is responsible for killing the process on timeout.
+pub async fn read_turn(
+    stdout: impl AsyncRead + Unpin,
+    kill_handle: &KillHandle,
+    timeouts: Timeouts,
+    output_tx: &mpsc::Sender<ParsedOutput>,
+) -> TurnOutput {
+    let mut lines = BufReader::new(stdout).lines();
+    let mut session_id: Option<String> = None;
+    let mut line_count = 0;
+    let mut watchdog = Watchdog::new(timeouts);
+    let mut tick = tokio::time::interval(WATCHDOG_TICK);
+    let mut timeout = None;
+    watchdog.start();
+
+    eprintln!("[Claude] Starting to read stdout lines...");
+
+    'lines: loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(json_line)) => {
+                    line_count += 1;
+                    watchdog.touch();
                     eprintln!("[Claude] Line {}: {} chars", line_count, json_line.len());
-                    if let Some(output) = parse_stream_line(&json_line) {
+                    for output in parse_stream_line(&json_line) {
                         match output {
                             ParsedOutput::SessionId(id) => {
                                 eprintln!("[Claude] Captured session_id: {}", id);
-                                session_id = Some(id);
-                            }
-                            ParsedOutput::Text(text) => {
-                                eprintln!("[Claude] Sending text ({} chars) through channel", text.len());
-                                if output_tx.send(text).is_err() {
-                                    eprintln!("[Claude] Channel closed, stopping");
-                                    break; // Channel closed
-                                }
+                                session_id = Some(id.clone());
+                                let _ = output_tx.send(ParsedOutput::SessionId(id)).await;
                             }
                             ParsedOutput::Complete => {
                                 eprintln!("[Claude] Got completion signal");
                                 // Final result received, we're done
-                                break;
+                                break 'lines;
                             }
                             ParsedOutput::Error(err) => {
                                 eprintln!("[Claude] Got error: {}", err);
-                                let _ = output_tx.send(format!("Error: {}", err));
-                                break;
+                                let _ = output_tx.send(ParsedOutput::Error(err)).await;
+                                break 'lines;
+                            }
+                            other => {
+                                if output_tx.send(other).await.is_err() {
+                                    eprintln!("[Claude] Channel closed, stopping");
+                                    break 'lines; // Channel closed
+                                }
                             }
                         }
                     }
                 }
+                Ok(None) => break,
                 Err(e) => {
                     eprintln!("[Claude] Read error: {}", e);
-                    let _ = output_tx.send(format!("Read error: {}", e));
+                    let _ = output_tx.send(ParsedOutput::Error(format!("Read error: {}", e))).await;
+                    break;
+                }
+            },
+            _ = kill_handle.cancelled() => {
+                eprintln!("[Claude] Message cancelled");
+                break;
+            }
+            _ = tick.tick() => {
+                if let Some(e) = watchdog.expired() {
+                    eprintln!("[Claude] {}", e);
+                    timeout = Some(e);
                     break;
                 }
             }
         }
+    }
+
+    eprintln!("[Claude] Finished reading, got {} lines", line_count);
+
+    TurnOutput { session_id, timeout }
+}
+
+/// Wrapper around Claude CLI process (non-interactive)
+pub struct ClaudeProcess {
+    cli_path: String,
+    config: ProcessConfig,
+    kill_handle: KillHandle,
+    stderr: StderrBuffer,
+    last_exit: Option<ProcessExit>,
+}
+
+impl ClaudeProcess {
+    pub fn new(cli_path: String, config: ProcessConfig) -> Self {
+        Self {
+            cli_path,
+            config,
+            kill_handle: KillHandle::default(),
+            stderr: StderrBuffer::default(),
+            last_exit: None,
+        }
+    }
+
+    /// How the last message's process ended
+    pub fn last_exit(&self) -> Option<&ProcessExit> {
+        self.last_exit.as_ref()
+    }
+
+    /// Handle for killing the in-flight message from another task
+    pub fn kill_handle(&self) -> KillHandle {
+        self.kill_handle.clone()
+    }
+
+    /// Send a message to Claude and stream the response
+    ///
+    /// Returns the session_id for future resume operations, or the
+    /// classified error if the process exited unsuccessfully. The process
+    /// is killed if it exceeds the message deadline or goes quiet for
+    /// longer than the idle timeout.
+    pub async fn send_message(
+        &mut self,
+        message: &str,
+        session: &SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<Option<String>> {
+        self.config.check_prompt_size(message)?;
+        let mut cmd = AsyncCommand::from(build_command(&self.cli_path, &self.config, session)?);
+
+        // The prompt goes over stdin (plain text input format), never argv:
+        // argv is size-limited and visible to other users in `ps`
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        eprintln!("[Claude] Message: {}", truncate_for_log(message, 100));
+
+        // Spawn process
+        let mut child = cmd.spawn()
+            .map_err(|e| {
+                eprintln!("[Claude] Failed to spawn: {}", e);
+                ClaudeError::SpawnFailed(e.to_string())
+            })?;
+
+        eprintln!("[Claude] Process spawned successfully");
+
+        let stdout = child.stdout.take()
+            .ok_or_else(|| ClaudeError::PtyError("Failed to capture stdout".to_string()))?;
+        let mut stdin = child.stdin.take()
+            .ok_or_else(|| ClaudeError::PtyError("Failed to capture stdin".to_string()))?;
+        let stderr_reader = child.stderr.take().map(|stderr| self.stderr.capture(stderr));
+        self.last_exit = None;
+
+        // Write concurrently with reading so a large prompt can't deadlock
+        // against a full stdout pipe; dropping stdin marks the end of input
+        let prompt = message.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(prompt.as_bytes()).await {
+                eprintln!("[Claude] Failed to write prompt to stdin: {}", e);
+            }
+        });
+
+        let kill_handle = self.kill_handle.clone();
+        if let Some(pid) = child.id() {
+            kill_handle.attach(pid);
+        }
 
-        eprintln!("[Claude] Finished reading, got {} lines", line_count);
+        let TurnOutput { session_id, timeout } =
+            read_turn(stdout, &kill_handle, self.config.timeouts(), &output_tx).await;
 
-        // Wait for child to complete
-        if let Some(ref mut child) = self.current_child {
-            let _ = child.wait();
+        // Wait for the child to exit, killing it if it hangs around
+        if timeout.is_some() {
+            let _ = kill_handle.terminate();
         }
-        self.current_child = None;
+        let status = match tokio::time::timeout(EXIT_GRACE, child.wait()).await {
+            Ok(status) => status.ok(),
+            Err(_) => {
+                eprintln!("[Claude] Process did not exit, killing it");
+                let _ = kill_handle.terminate();
+                child.wait().await.ok()
+            }
+        };
+        kill_handle.detach();
+        drain_stderr(stderr_reader).await;
+
+        let Some(status) = status else {
+            // The exit status is lost, but a timeout still failed the turn
+            return match timeout {
+                Some(e) => Err(e),
+                None => Ok(session_id),
+            };
+        };
+        let exit = ProcessExit::new(&status, &self.stderr);
+        eprintln!("[Claude] Process exited with {}", status);
+        let error = match timeout {
+            Some(e) => Some(e),
+            None if kill_handle.is_cancelled() => None,
+            None => exit.error(),
+        };
+        self.last_exit = Some(exit);
 
-        Ok(session_id)
+        match error {
+            Some(e) => Err(e),
+            None => Ok(session_id),
+        }
     }
 
     /// Kill the current process if running
     pub fn kill(&mut self) -> Result<()> {
-        if let Some(ref mut child) = self.current_child {
-            child.kill().map_err(|e| ClaudeError::IoError(e))?;
+        if self.is_running() {
+            self.kill_handle.kill()?;
         }
-        self.current_child = None;
         Ok(())
     }
 
     /// Check if a process is currently running
-    #[allow(dead_code)]
     pub fn is_running(&self) -> bool {
-        self.current_child.is_some()
+        self.kill_handle.pid.lock().is_some()
     }
 }
 
@@ -295,42 +1045,450 @@ impl Drop for ClaudeProcess {
     }
 }
 
-/// Find the full path to claude CLI
-pub fn find_claude_path() -> Option<String> {
-    // Common locations for claude CLI
-    let possible_paths = [
-        "/usr/local/bin/claude",
-        "/opt/homebrew/bin/claude",
-        &format!("{}/.local/bin/claude", std::env::var("HOME").unwrap_or_default()),
-        &format!("{}/.npm-global/bin/claude", std::env::var("HOME").unwrap_or_default()),
-    ];
-
-    // First check if it's in PATH
-    if let Ok(output) = Command::new("which").arg("claude").output() {
-        if output.status.success() {
-            if let Ok(path) = String::from_utf8(output.stdout) {
-                let path = path.trim();
-                if !path.is_empty() {
-                    eprintln!("[Claude] Found claude at: {}", path);
-                    return Some(path.to_string());
+/// Lines buffered for the persistent process's stdin
+const STDIN_CHANNEL_CAPACITY: usize = 16;
+
+/// Long-lived Claude CLI process speaking stream-json on stdin and stdout
+///
+/// Each user turn is written to stdin as a stream-json `user` message; the
+/// reader task forwards every parsed event for the lifetime of the child
+/// and closes the channel when the process exits. While a turn is running
+/// the same deadline and idle watchdog as in per-message mode apply.
+pub struct PersistentProcess {
+    child: Child,
+    stdin_tx: mpsc::Sender<String>,
+    kill_handle: KillHandle,
+    stderr: StderrBuffer,
+    watchdog: Arc<Mutex<Watchdog>>,
+}
+
+impl PersistentProcess {
+    /// Spawn the CLI in bidirectional stream-json mode
+    pub fn spawn(
+        cli_path: &str,
+        config: &ProcessConfig,
+        session: &SessionArg,
+        output_tx: mpsc::Sender<ParsedOutput>,
+    ) -> Result<Self> {
+        let mut std_cmd = build_command(cli_path, config, session)?;
+        std_cmd.arg("--input-format").arg("stream-json");
+        let mut cmd = AsyncCommand::from(std_cmd);
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = cmd.spawn().map_err(|e| {
+            eprintln!("[Claude] Failed to spawn: {}", e);
+            ClaudeError::SpawnFailed(e.to_string())
+        })?;
+
+        let mut stdin = child.stdin.take()
+            .ok_or_else(|| ClaudeError::PtyError("Failed to capture stdin".to_string()))?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| ClaudeError::PtyError("Failed to capture stdout".to_string()))?;
+        let stderr = StderrBuffer::default();
+        if let Some(pipe) = child.stderr.take() {
+            stderr.capture(pipe);
+        }
+
+        let kill_handle = KillHandle::default();
+        if let Some(pid) = child.id() {
+            eprintln!("[Claude] Persistent process spawned (pid {})", pid);
+            kill_handle.attach(pid);
+        }
+
+        // Writer task: lines are queued by `send_message`/`interrupt`
+        let (stdin_tx, mut stdin_rx) = mpsc::channel::<String>(STDIN_CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(line) = stdin_rx.recv().await {
+                let written = async {
+                    stdin.write_all(line.as_bytes()).await?;
+                    stdin.flush().await
+                };
+                if let Err(e) = written.await {
+                    eprintln!("[Claude] Failed to write to stdin: {}", e);
+                    break;
                 }
             }
-        }
+        });
+
+        // Reader task: forward events and enforce the turn timeouts
+        let watchdog = Arc::new(Mutex::new(Watchdog::new(config.timeouts())));
+        let reader_watchdog = Arc::clone(&watchdog);
+        let reader_kill_handle = kill_handle.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            let mut tick = tokio::time::interval(WATCHDOG_TICK);
+            loop {
+                tokio::select! {
+                    line = lines.next_line() => {
+                        let Ok(Some(json_line)) = line else { break };
+                        reader_watchdog.lock().touch();
+                        for output in parse_stream_line(&json_line) {
+                            if matches!(output, ParsedOutput::Complete | ParsedOutput::Error(_)) {
+                                reader_watchdog.lock().stop();
+                            }
+                            if output_tx.send(output).await.is_err() {
+                                return; // Channel closed
+                            }
+                        }
+                    }
+                    _ = tick.tick() => {
+                        let expired = reader_watchdog.lock().expired();
+                        if let Some(ClaudeError::Timeout { kind, secs }) = expired {
+                            eprintln!("[Claude] Persistent process timed out ({:?})", kind);
+                            reader_watchdog.lock().stop();
+                            let _ = output_tx.send(ParsedOutput::Timeout { kind, secs }).await;
+                            let _ = reader_kill_handle.terminate();
+                            break;
+                        }
+                    }
+                }
+            }
+            eprintln!("[Claude] Persistent process output ended");
+        });
+
+        Ok(Self {
+            child,
+            stdin_tx,
+            kill_handle,
+            stderr,
+            watchdog,
+        })
     }
 
-    // Check common locations
-    for path in &possible_paths {
-        if std::path::Path::new(path).exists() {
-            eprintln!("[Claude] Found claude at: {}", path);
-            return Some(path.to_string());
+    /// Write a user turn to the CLI's stdin
+    pub fn send_message(&mut self, message: &str) -> Result<()> {
+        let line = serde_json::json!({
+            "type": "user",
+            "message": {
+                "role": "user",
+                "content": [{ "type": "text", "text": message }],
+            },
+        });
+        eprintln!("[Claude] Message: {}", truncate_for_log(message, 100));
+        self.write_line(&line)?;
+        self.watchdog.lock().start();
+        Ok(())
+    }
+
+    /// Ask the CLI to abort the current turn, keeping the process alive
+    pub fn interrupt(&mut self) -> Result<()> {
+        let line = serde_json::json!({
+            "type": "control_request",
+            "request_id": uuid::Uuid::new_v4().to_string(),
+            "request": { "subtype": "interrupt" },
+        });
+        self.write_line(&line)
+    }
+
+    fn write_line(&mut self, value: &serde_json::Value) -> Result<()> {
+        self.stdin_tx
+            .try_send(format!("{}\n", value))
+            .map_err(|e| ClaudeError::SendFailed(e.to_string()))
+    }
+
+    /// Check whether the child is still alive
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// How the process ended, or `None` while it is still running
+    pub fn exit(&mut self) -> Option<ProcessExit> {
+        let status = self.child.try_wait().ok().flatten()?;
+        Some(ProcessExit::new(&status, &self.stderr))
+    }
+
+    /// Handle for killing the process from another task
+    pub fn kill_handle(&self) -> KillHandle {
+        self.kill_handle.clone()
+    }
+
+    /// Kill the process and its process group
+    ///
+    /// The child is reaped by tokio in the background.
+    pub fn kill(&mut self) -> Result<()> {
+        if self.is_running() {
+            self.kill_handle.kill()?;
         }
+        self.kill_handle.detach();
+        Ok(())
     }
+}
 
-    eprintln!("[Claude] Could not find claude CLI");
-    None
+impl Drop for PersistentProcess {
+    fn drop(&mut self) {
+        let _ = self.kill();
+    }
 }
 
-/// Check if Claude CLI is installed and accessible
-pub fn check_claude_cli() -> Result<bool> {
-    Ok(find_claude_path().is_some())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_system_init() {
+        let line = r#"{"type":"system","subtype":"init","session_id":"abc","tools":["Bash"],"model":"claude-sonnet-4-5","mcp_servers":[{"name":"tasks","status":"connected"}]}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![
+                ParsedOutput::SessionId("abc".to_string()),
+                ParsedOutput::Init(SessionInit {
+                    model: Some("claude-sonnet-4-5".to_string()),
+                    tools: vec!["Bash".to_string()],
+                    cwd: None,
+                    permission_mode: None,
+                    mcp_servers: vec![McpServerStatus {
+                        name: "tasks".to_string(),
+                        status: "connected".to_string(),
+                    }],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_assistant_text_and_tool_use() {
+        let line = r#"{"type":"assistant","session_id":"abc","message":{"id":"msg_1","content":[{"type":"text","text":"Let me "},{"type":"text","text":"check."},{"type":"tool_use","id":"toolu_1","name":"mcp__tasks__list_tasks","input":{"status":"open"}}],"usage":{"input_tokens":10,"output_tokens":5}}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![
+                ParsedOutput::Text {
+                    text: "Let me check.".to_string(),
+                    parent_tool_use_id: None,
+                },
+                ParsedOutput::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "mcp__tasks__list_tasks".to_string(),
+                    input: serde_json::json!({"status": "open"}),
+                    parent_tool_use_id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_thinking() {
+        let line = r#"{"type":"assistant","session_id":"abc","message":{"content":[{"type":"thinking","thinking":"Hmm","signature":"sig"},{"type":"redacted_thinking","data":"xyz"}]}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![ParsedOutput::Thinking {
+                thinking: "Hmm".to_string(),
+                parent_tool_use_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_user_tool_result() {
+        let line = r#"{"type":"user","session_id":"abc","parent_tool_use_id":null,"message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"toolu_1","content":[{"type":"text","text":"3 tasks"}],"is_error":false}]}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![ParsedOutput::ToolResult {
+                tool_use_id: "toolu_1".to_string(),
+                content: "3 tasks".to_string(),
+                is_error: false,
+                parent_tool_use_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_subagent_text() {
+        let line = r#"{"type":"assistant","session_id":"abc","parent_tool_use_id":"toolu_task","message":{"content":[{"type":"thinking","thinking":"Search first"},{"type":"text","text":"Reading the config."}]}}"#;
+        let subagent = Some("toolu_task".to_string());
+        assert_eq!(
+            parse_stream_line(line),
+            vec![
+                ParsedOutput::Thinking {
+                    thinking: "Search first".to_string(),
+                    parent_tool_use_id: subagent.clone(),
+                },
+                ParsedOutput::Text {
+                    text: "Reading the config.".to_string(),
+                    parent_tool_use_id: subagent,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_subagent_and_partial_events() {
+        let line = r#"{"type":"assistant","session_id":"abc","parent_tool_use_id":"toolu_task","message":{"content":[{"type":"tool_use","id":"toolu_2","name":"Read","input":{}}]}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![ParsedOutput::ToolUse {
+                id: "toolu_2".to_string(),
+                name: "Read".to_string(),
+                input: serde_json::json!({}),
+                parent_tool_use_id: Some("toolu_task".to_string()),
+            }]
+        );
+
+        let line = r#"{"type":"stream_event","session_id":"abc","event":{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![ParsedOutput::Partial {
+                event: serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hi"}}),
+                parent_tool_use_id: None,
+            }]
+        );
+
+        let line = r#"{"type":"control_response","response":{"subtype":"success","request_id":"req_1"}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![ParsedOutput::ControlResponse(ControlResponse {
+                subtype: "success".to_string(),
+                request_id: "req_1".to_string(),
+                error: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_parse_user_echo() {
+        let line = r#"{"type":"user","session_id":"abc","message":{"role":"user","content":"hello"}}"#;
+        assert_eq!(parse_stream_line(line), vec![ParsedOutput::UserText("hello".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_result_usage() {
+        let line = r#"{"type":"result","subtype":"success","session_id":"abc","is_error":false,"result":"Done","duration_ms":2100,"duration_api_ms":1900,"num_turns":3,"total_cost_usd":0.0123,"usage":{"input_tokens":120,"cache_creation_input_tokens":10,"cache_read_input_tokens":4000,"output_tokens":80}}"#;
+        assert_eq!(
+            parse_stream_line(line),
+            vec![
+                ParsedOutput::Usage(TurnUsage {
+                    usage: Usage {
+                        input_tokens: 120,
+                        output_tokens: 80,
+                        cache_creation_input_tokens: 10,
+                        cache_read_input_tokens: 4000,
+                    },
+                    total_cost_usd: 0.0123,
+                    duration_ms: 2100,
+                    duration_api_ms: 1900,
+                    num_turns: 3,
+                    is_error: false,
+                }),
+                ParsedOutput::Complete,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_result_without_text() {
+        let line = r#"{"type":"result","subtype":"error_max_turns","session_id":"abc","is_error":true}"#;
+        let outputs = parse_stream_line(line);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[1], ParsedOutput::Error(String::new()));
+    }
+
+    #[test]
+    fn test_prompt_size_limit() {
+        let mut config = ProcessConfig::default();
+        assert!(config.check_prompt_size("hello").is_ok());
+
+        config.options.max_prompt_bytes = Some(4);
+        assert!(matches!(
+            config.check_prompt_size("hello"),
+            Err(ClaudeError::PromptTooLarge { size: 5, limit: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_stderr_buffer_is_bounded() {
+        let buffer = StderrBuffer::with_capacity(2);
+        for line in ["one", "two", "three"] {
+            buffer.push(line.to_string());
+        }
+        assert_eq!(buffer.lines(), vec!["two", "three"]);
+    }
+
+    #[test]
+    fn test_process_exit_error() {
+        let exit = |code, stderr: &[&str]| ProcessExit {
+            code,
+            stderr: stderr.iter().map(|s| s.to_string()).collect(),
+        };
+        assert!(exit(Some(0), &["warning"]).error().is_none());
+        assert!(matches!(
+            exit(Some(1), &["Error: Invalid API key · Please run /login"]).error(),
+            Some(ClaudeError::NotAuthenticated)
+        ));
+        assert!(matches!(exit(None, &[]).error(), Some(ClaudeError::ProcessTerminated)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stderr_capture() {
+        let mut child = AsyncCommand::new("sh")
+            .args(["-c", "echo 'API Error: 529 overloaded' >&2; exit 1"])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let buffer = StderrBuffer::default();
+        let reader = buffer.capture(child.stderr.take().unwrap());
+        let status = child.wait().await.unwrap();
+        reader.await.unwrap();
+
+        let exit = ProcessExit::new(&status, &buffer);
+        assert_eq!(exit.code, Some(1));
+        assert!(matches!(exit.error(), Some(ClaudeError::Overloaded)));
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let mut watchdog = Watchdog::new(Timeouts {
+            message: Duration::from_secs(3600),
+            idle: Duration::ZERO,
+        });
+        assert!(watchdog.expired().is_none(), "disarmed between turns");
+
+        watchdog.start();
+        assert!(matches!(
+            watchdog.expired(),
+            Some(ClaudeError::Timeout { kind: TimeoutKind::Idle, .. })
+        ));
+
+        watchdog.timeouts.message = Duration::ZERO;
+        assert!(matches!(
+            watchdog.expired(),
+            Some(ClaudeError::Timeout { kind: TimeoutKind::Deadline, secs: 0 })
+        ));
+
+        watchdog.stop();
+        assert!(watchdog.expired().is_none());
+    }
+
+    #[test]
+    fn test_kill_handle_before_spawn() {
+        let handle = KillHandle::default();
+        assert!(!handle.is_cancelled());
+        handle.kill().unwrap();
+        assert!(handle.is_cancelled());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_handle_kills_process_group() {
+        use std::os::unix::process::CommandExt;
+
+        let mut child = Command::new("sh")
+            .args(["-c", "sleep 30 & sleep 30"])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let handle = KillHandle::default();
+        handle.attach(child.id());
+        handle.kill().unwrap();
+
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+    }
+
+    #[test]
+    fn test_parse_invalid_line() {
+        assert!(parse_stream_line("not json").is_empty());
+    }
 }
@@ -0,0 +1,133 @@
+//! Per-session queue of messages sent while a response is in progress
+//!
+//! Follow-ups typed during a turn are kept here, shown as pending in the
+//! UI, and dispatched one at a time in FIFO order as turns complete. Until
+//! a message is dispatched it can still be edited or dropped.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+use super::error::{ClaudeError, Result};
+
+/// Most messages a session may have waiting at once
+pub const MAX_QUEUED_MESSAGES: usize = 50;
+
+/// A message waiting for the current turn to finish
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueuedMessage {
+    pub id: String,
+    pub content: String,
+    pub queued_at: String,
+}
+
+impl QueuedMessage {
+    fn new(content: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            content,
+            queued_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// FIFO of pending messages
+#[derive(Debug, Default)]
+pub struct MessageQueue {
+    messages: VecDeque<QueuedMessage>,
+}
+
+impl MessageQueue {
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Snapshot of the pending messages, oldest first
+    pub fn list(&self) -> Vec<QueuedMessage> {
+        self.messages.iter().cloned().collect()
+    }
+
+    /// Append a message to the back of the queue
+    pub fn push(&mut self, content: String) -> Result<QueuedMessage> {
+        if self.messages.len() >= MAX_QUEUED_MESSAGES {
+            return Err(ClaudeError::QueueFull(MAX_QUEUED_MESSAGES));
+        }
+        let message = QueuedMessage::new(content);
+        self.messages.push_back(message.clone());
+        Ok(message)
+    }
+
+    /// Take the oldest message for dispatch
+    pub fn pop(&mut self) -> Option<QueuedMessage> {
+        self.messages.pop_front()
+    }
+
+    /// Put a message back at the front after a failed dispatch
+    pub fn requeue(&mut self, message: QueuedMessage) {
+        self.messages.push_front(message);
+    }
+
+    /// Replace the content of a pending message
+    pub fn edit(&mut self, id: &str, content: String) -> Result<QueuedMessage> {
+        let message = self
+            .messages
+            .iter_mut()
+            .find(|m| m.id == id)
+            .ok_or_else(|| ClaudeError::QueuedMessageNotFound(id.to_string()))?;
+        message.content = content;
+        Ok(message.clone())
+    }
+
+    /// Drop a pending message
+    pub fn remove(&mut self, id: &str) -> Result<QueuedMessage> {
+        let index = self
+            .messages
+            .iter()
+            .position(|m| m.id == id)
+            .ok_or_else(|| ClaudeError::QueuedMessageNotFound(id.to_string()))?;
+        Ok(self.messages.remove(index).expect("index from position"))
+    }
+
+    /// Drop every pending message
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fifo_order_and_edits() {
+        let mut queue = MessageQueue::default();
+        let first = queue.push("first".to_string()).unwrap();
+        let second = queue.push("second".to_string()).unwrap();
+        let third = queue.push("third".to_string()).unwrap();
+
+        queue.edit(&second.id, "second, edited".to_string()).unwrap();
+        queue.remove(&third.id).unwrap();
+        assert!(matches!(
+            queue.remove(&third.id),
+            Err(ClaudeError::QueuedMessageNotFound(_))
+        ));
+
+        let popped = queue.pop().unwrap();
+        assert_eq!(popped, first);
+        queue.requeue(popped);
+        assert_eq!(queue.pop().unwrap().content, "first");
+        assert_eq!(queue.pop().unwrap().content, "second, edited");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_queue_limit() {
+        let mut queue = MessageQueue::default();
+        for i in 0..MAX_QUEUED_MESSAGES {
+            queue.push(i.to_string()).unwrap();
+        }
+        assert!(matches!(
+            queue.push("overflow".to_string()),
+            Err(ClaudeError::QueueFull(_))
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+//! Retention policy for conversations
+//!
+//! Transcripts pile up in the CLI's project folder for as long as the
+//! project exists. The policy archives conversations nobody has touched for
+//! a while and deletes conversations that sat in the trash for a while; it
+//! runs at startup and does nothing until one of the limits is set. Saving
+//! a policy returns a dry run of it, which reports what it would do without
+//! changing anything, and what the startup run did is reported to the
+//! frontend.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::error::{ClaudeError, Result};
+use super::sessions::{ConversationInfo, TrashedConversation};
+use super::store::ClaudeStore;
+
+const SETTINGS_KEY: &str = "retention";
+
+/// Longest limit accepted, about ten years
+const MAX_DAYS: u32 = 3650;
+
+/// When conversations are archived and trashed ones deleted
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionSettings {
+    /// Archive conversations not updated for this many days; pinned ones
+    /// are kept
+    pub archive_after_days: Option<u32>,
+    /// Delete trashed conversations after this many days
+    pub purge_trash_after_days: Option<u32>,
+}
+
+impl RetentionSettings {
+    pub fn validate(&self) -> Result<()> {
+        for days in [self.archive_after_days, self.purge_trash_after_days].into_iter().flatten() {
+            if !(1..=MAX_DAYS).contains(&days) {
+                return Err(ClaudeError::InvalidOption(format!(
+                    "Retention must be between 1 and {} days",
+                    MAX_DAYS
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a run of the policy archived and deleted, or would have
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionReport {
+    /// Nothing was changed
+    pub dry_run: bool,
+    pub archived: Vec<ConversationInfo>,
+    pub purged: Vec<TrashedConversation>,
+    /// Conversations the policy was due to change but couldn't
+    pub failed: Vec<RetentionFailure>,
+}
+
+/// What the policy tried to do with a conversation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    Archive,
+    Purge,
+}
+
+/// A conversation the policy failed to archive or delete
+#[derive(Debug, Clone, Serialize)]
+pub struct RetentionFailure {
+    pub id: String,
+    pub action: RetentionAction,
+    pub error: String,
+}
+
+impl ClaudeStore {
+    pub fn retention_settings(&self) -> RetentionSettings {
+        self.get_setting(SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("[Claude] Failed to load retention settings: {}", e);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save_retention_settings(&self, settings: &RetentionSettings) -> Result<()> {
+        settings.validate()?;
+        self.set_setting(SETTINGS_KEY, settings)
+    }
+}
+
+/// The conversations `settings` archives and deletes at `now`;
+/// `conversations` must have the app's overlay merged in
+pub fn plan(
+    settings: &RetentionSettings,
+    conversations: Vec<ConversationInfo>,
+    trash: Vec<TrashedConversation>,
+    now: DateTime<Utc>,
+    dry_run: bool,
+) -> RetentionReport {
+    let cutoff = |days: Option<u32>| days.map(|days| now - Duration::days(days.into()));
+
+    let archived = match cutoff(settings.archive_after_days) {
+        Some(cutoff) => conversations
+            .into_iter()
+            .filter(|c| !c.pinned && !c.archived && c.updated_at < cutoff)
+            .collect(),
+        None => Vec::new(),
+    };
+    let purged = match cutoff(settings.purge_trash_after_days) {
+        Some(cutoff) => trash.into_iter().filter(|t| t.trashed_at < cutoff).collect(),
+        None => Vec::new(),
+    };
+
+    RetentionReport {
+        dry_run,
+        archived,
+        purged,
+        failed: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(id: &str, age_days: i64, now: DateTime<Utc>) -> ConversationInfo {
+        let at = now - Duration::days(age_days);
+        ConversationInfo {
+            id: id.to_string(),
+            title: None,
+            preview: None,
+            created_at: at,
+            updated_at: at,
+            message_count: 1,
+            pinned: false,
+            archived: false,
+            tags: Vec::new(),
+            forked_from: None,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = Utc::now();
+        let mut pinned = conversation("pinned", 90, now);
+        pinned.pinned = true;
+        let mut archived = conversation("archived", 90, now);
+        archived.archived = true;
+        let conversations = vec![
+            conversation("old", 31, now),
+            conversation("recent", 29, now),
+            pinned,
+            archived,
+        ];
+        let trash = vec![
+            TrashedConversation {
+                conversation: conversation("trashed-long-ago", 100, now),
+                trashed_at: now - Duration::days(8),
+            },
+            TrashedConversation {
+                conversation: conversation("just-trashed", 100, now),
+                trashed_at: now - Duration::days(1),
+            },
+        ];
+
+        let settings = RetentionSettings {
+            archive_after_days: Some(30),
+            purge_trash_after_days: Some(7),
+        };
+        let report = plan(&settings, conversations.clone(), trash.clone(), now, true);
+        let archived: Vec<_> = report.archived.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(archived, vec!["old"]);
+        let purged: Vec<_> = report.purged.iter().map(|t| t.conversation.id.as_str()).collect();
+        assert_eq!(purged, vec!["trashed-long-ago"]);
+
+        let report = plan(&RetentionSettings::default(), conversations, trash, now, true);
+        assert!(report.archived.is_empty() && report.purged.is_empty());
+    }
+
+    #[test]
+    fn test_settings() {
+        let store = ClaudeStore::open_in_memory().unwrap();
+        assert_eq!(store.retention_settings(), RetentionSettings::default());
+        let invalid = RetentionSettings {
+            archive_after_days: Some(0),
+            ..Default::default()
+        };
+        assert!(store.save_retention_settings(&invalid).is_err());
+        let settings = RetentionSettings {
+            purge_trash_after_days: Some(30),
+            ..Default::default()
+        };
+        store.save_retention_settings(&settings).unwrap();
+        assert_eq!(store.retention_settings(), settings);
+    }
+}
@@ -0,0 +1,240 @@
+//! Full-text search across conversation transcripts
+//!
+//! User and assistant text is indexed into the `claude_search` FTS5 table
+//! with the message's position in the conversation, as counted by
+//! `claude_get_conversation_messages`. Transcripts are append-only, so each
+//! one is indexed from the byte offset reached last time
+//! (`claude_search_files`); a file that shrank was rewritten and is indexed
+//! again from the start. The index is brought up to date before a search.
+
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::error::Result;
+use super::store::ClaudeStore;
+use super::transcript::{EntryReader, HistoryContent, MessageBuilder};
+
+/// Hits returned when no limit is given, and the most allowed
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 200;
+
+/// Words of context around the match in a snippet
+const SNIPPET_TOKENS: i64 = 16;
+
+/// A message matching a search
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub conversation_id: String,
+    /// Index of the message in the conversation's messages
+    pub position: usize,
+    /// `user` or `assistant`
+    pub role: String,
+    /// Excerpt of the message with the matched terms in `<mark>` tags
+    pub snippet: String,
+    /// bm25 relevance, lower is better; hits are sorted by it
+    pub score: f64,
+}
+
+impl ClaudeStore {
+    /// Bring the index up to date with `files` (conversation id and
+    /// transcript path), dropping conversations that are no longer there
+    pub fn sync_search_index(&self, files: &[(String, PathBuf)]) -> Result<()> {
+        for (id, path) in files {
+            self.index_transcript(id, path)?;
+        }
+
+        let current: HashSet<&str> = files.iter().map(|(id, _)| id.as_str()).collect();
+        let indexed: Vec<String> = self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT conversation_id FROM claude_search_files")?;
+            let ids = stmt.query_map([], |row| row.get(0))?;
+            ids.collect()
+        })?;
+        for id in indexed.iter().filter(|id| !current.contains(id.as_str())) {
+            self.remove_from_search_index(id)?;
+        }
+        Ok(())
+    }
+
+    /// Index the lines appended to a transcript since it was last indexed
+    fn index_transcript(&self, id: &str, path: &Path) -> Result<()> {
+        // The file may have been removed since it was listed
+        let size = match std::fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(()),
+        };
+        let indexed: Option<(i64, i64)> = self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT byte_offset, message_count FROM claude_search_files
+                 WHERE conversation_id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+        })?;
+        let (offset, first_position) = match indexed {
+            Some((offset, _)) if offset as u64 == size => return Ok(()),
+            Some((offset, count)) if (offset as u64) < size => (offset as u64, count as usize),
+            _ => (0, 0),
+        };
+
+        let mut reader = EntryReader::open(path, offset, true)?;
+        let mut builder = MessageBuilder::default();
+        let mut messages = Vec::new();
+        for entry in reader.by_ref() {
+            builder.push(entry, &mut messages);
+        }
+        let end = reader.offset();
+
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            if offset == 0 {
+                tx.execute("DELETE FROM claude_search WHERE conversation_id = ?1", params![id])?;
+            }
+            {
+                let mut insert = tx.prepare(
+                    "INSERT INTO claude_search (content, conversation_id, position, role)
+                     VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (i, message) in messages.iter().enumerate() {
+                    let (role, text) = match &message.content {
+                        HistoryContent::User { content } => ("user", content),
+                        HistoryContent::Assistant { content } => ("assistant", content),
+                        _ => continue,
+                    };
+                    insert.execute(params![text, id, (first_position + i) as i64, role])?;
+                }
+            }
+            tx.execute(
+                "INSERT INTO claude_search_files (conversation_id, byte_offset, message_count)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT(conversation_id) DO UPDATE SET byte_offset = excluded.byte_offset,
+                     message_count = excluded.message_count, indexed_at = datetime('now')",
+                params![id, end as i64, (first_position + messages.len()) as i64],
+            )?;
+            tx.commit()
+        })
+    }
+
+    /// Drop a conversation from the index
+    pub fn remove_from_search_index(&self, id: &str) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.execute("DELETE FROM claude_search WHERE conversation_id = ?1", params![id])?;
+            conn.execute("DELETE FROM claude_search_files WHERE conversation_id = ?1", params![id])?;
+            Ok(())
+        })
+    }
+
+    /// Messages matching `query`, best first
+    pub fn search_conversations(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchHit>> {
+        let query = match fts_query(query) {
+            Some(query) => query,
+            None => return Ok(Vec::new()),
+        };
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT conversation_id, position, role,
+                        snippet(claude_search, 0, '<mark>', '</mark>', '...', ?3), rank
+                 FROM claude_search WHERE claude_search MATCH ?1
+                 ORDER BY rank LIMIT ?2",
+            )?;
+            let hits = stmt.query_map(params![query, limit as i64, SNIPPET_TOKENS], |row| {
+                Ok(SearchHit {
+                    conversation_id: row.get(0)?,
+                    position: row.get::<_, i64>(1)? as usize,
+                    role: row.get(2)?,
+                    snippet: row.get(3)?,
+                    score: row.get(4)?,
+                })
+            })?;
+            hits.collect()
+        })
+    }
+}
+
+/// Turn typed text into an FTS5 query: every word must match, the last one
+/// as a prefix so results show up while typing. Words are quoted so FTS5
+/// operators and punctuation in the input are taken literally.
+fn fts_query(input: &str) -> Option<String> {
+    let words: Vec<String> = input
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    (!words.is_empty()).then(|| format!("{}*", words.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testutil::TempDir;
+    use std::io::Write;
+
+    fn user(uuid: &str, text: &str) -> String {
+        serde_json::json!({"type": "user", "uuid": uuid, "message": {"role": "user", "content": text}})
+            .to_string()
+            + "\n"
+    }
+
+    fn assistant(uuid: &str, text: &str) -> String {
+        serde_json::json!({"type": "assistant", "uuid": uuid, "message": {"id": uuid, "content": [{"type": "text", "text": text}]}})
+            .to_string()
+            + "\n"
+    }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("DATEV export").as_deref(), Some(r#""DATEV" "export"*"#));
+        assert_eq!(fts_query(r#"say "hi" - OR"#).as_deref(), Some(r#""say" """hi""" "OR"*"#));
+        assert_eq!(fts_query("  - "), None);
+    }
+
+    #[test]
+    fn test_incremental_index_and_search() {
+        let dir = TempDir::new("search");
+        let datev = dir.join("conv-datev.jsonl");
+        let other = dir.join("conv-other.jsonl");
+        std::fs::write(&datev, user("u1", "The DATEV export fails for March") + &assistant("a1", "Let me check the CSV encoding.")).unwrap();
+        std::fs::write(&other, user("u2", "Summarise my invoices")).unwrap();
+        let files = vec![
+            ("conv-datev".to_string(), datev.clone()),
+            ("conv-other".to_string(), other.clone()),
+        ];
+
+        let store = ClaudeStore::open_in_memory().unwrap();
+        store.sync_search_index(&files).unwrap();
+        let hits = store.search_conversations("datev exp", None).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].conversation_id.as_str(), hits[0].position), ("conv-datev", 0));
+        assert_eq!(hits[0].snippet, "The <mark>DATEV</mark> <mark>export</mark> fails for March");
+
+        // Appended lines are indexed at the following positions; a line still
+        // being written waits for the next sync
+        let mut file = std::fs::OpenOptions::new().append(true).open(&datev).unwrap();
+        file.write_all(assistant("a2", "Fixed: the export now writes cp1252.").as_bytes()).unwrap();
+        file.write_all(br#"{"type":"user","uuid":"u3","message":{"role":"user","content":"export"#).unwrap();
+        store.sync_search_index(&files).unwrap();
+        let hits = store.search_conversations("export", None).unwrap();
+        let mut positions: Vec<_> = hits.iter().map(|h| h.position).collect();
+        positions.sort();
+        assert_eq!(positions, vec![0, 2]);
+
+        file.write_all(b" again\"}}\n").unwrap();
+        store.sync_search_index(&files).unwrap();
+        let hits = store.search_conversations("again", None).unwrap();
+        assert_eq!((hits.len(), hits[0].position, hits[0].role.as_str()), (1, 3, "user"));
+
+        // A rewritten transcript is indexed from scratch
+        std::fs::write(&datev, user("u1", "Payroll question")).unwrap();
+        store.sync_search_index(&files).unwrap();
+        assert!(store.search_conversations("export", None).unwrap().is_empty());
+        assert_eq!(store.search_conversations("payroll", None).unwrap()[0].position, 0);
+
+        // Conversations that disappeared are dropped
+        store.sync_search_index(&files[..1]).unwrap();
+        assert!(store.search_conversations("invoices", None).unwrap().is_empty());
+    }
+}
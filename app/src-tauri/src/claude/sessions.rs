@@ -1,11 +1,22 @@
 //! Session and conversation history management
+//!
+//! Deleted conversations are moved to a `.trash` folder next to the
+//! transcripts, where the CLI doesn't look, and can be restored from there
+//! until they are purged. The time a transcript was trashed is kept as its
+//! modification time.
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use super::error::{ClaudeError, Result};
+use super::metadata::{ConversationTag, ForkPoint};
+use super::transcript::{
+    encode_project_dir, load_message_window, read_entries, Entry, HistoryMessage,
+};
 
 /// Represents a conversation session
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,37 +27,86 @@ pub struct ConversationInfo {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub message_count: usize,
+    /// App-side organisation, merged in by the manager
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub tags: Vec<ConversationTag>,
+    #[serde(default)]
+    pub forked_from: Option<ForkPoint>,
 }
 
+/// One page of a conversation's messages
+#[derive(Debug, Clone, Serialize)]
+pub struct MessagePage {
+    pub messages: Vec<HistoryMessage>,
+    /// Index of the first message of the page
+    pub offset: usize,
+    /// Number of messages in the whole conversation
+    pub total: usize,
+}
+
+/// A conversation in the trash
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashedConversation {
+    #[serde(flatten)]
+    pub conversation: ConversationInfo,
+    pub trashed_at: DateTime<Utc>,
+}
+
+/// Folder of trashed transcripts, inside the project's transcript folder
+const TRASH_DIR: &str = ".trash";
+
+/// Messages per page when none is requested, and the most allowed
+pub const DEFAULT_PAGE_SIZE: usize = 200;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Longest title and preview shown in the conversation list, in characters
+const TITLE_CHARS: usize = 50;
+const PREVIEW_CHARS: usize = 100;
+
 /// Manages conversation history from Claude CLI
+#[derive(Clone)]
 pub struct SessionManager {
     claude_dir: PathBuf,
-    project_hash: Option<String>,
+    /// Transcript directory name for the project (see `encode_project_dir`)
+    project_key: Option<String>,
+}
+
+/// The CLI's config directory: `CLAUDE_CONFIG_DIR` if set, else `~/.claude`
+fn claude_dir_from(config_dir: Option<OsString>, home: Option<PathBuf>) -> PathBuf {
+    config_dir
+        .map(PathBuf::from)
+        .or_else(|| home.map(|h| h.join(".claude")))
+        .unwrap_or_else(|| PathBuf::from(".claude"))
 }
 
 impl SessionManager {
     /// Create a new session manager
     pub fn new() -> Self {
-        let claude_dir = dirs::home_dir()
-            .map(|h| h.join(".claude"))
-            .unwrap_or_else(|| PathBuf::from(".claude"));
+        Self::with_claude_dir(claude_dir_from(
+            std::env::var_os("CLAUDE_CONFIG_DIR"),
+            dirs::home_dir(),
+        ))
+    }
 
+    /// Session manager reading from a specific CLI config directory
+    pub fn with_claude_dir(claude_dir: PathBuf) -> Self {
         Self {
             claude_dir,
-            project_hash: None,
+            project_key: None,
         }
     }
 
-    /// Set the project directory to determine the project hash
+    /// Set the project directory whose conversations are listed
     pub fn set_project_dir(&mut self, project_dir: &str) {
-        // Claude Code uses a hash of the project path for storage
-        // This is a simplified version - actual hash may differ
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-
-        let mut hasher = DefaultHasher::new();
-        project_dir.hash(&mut hasher);
-        self.project_hash = Some(format!("{:x}", hasher.finish()));
+        // The CLI keys transcripts by the absolute path it was started in
+        let project_dir = fs::canonicalize(project_dir)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| project_dir.to_string());
+        self.project_key = Some(encode_project_dir(&project_dir));
     }
 
     /// Get the path to the projects directory
@@ -55,14 +115,29 @@ impl SessionManager {
     }
 
     /// Get the path for a specific project's conversations
-    fn project_conversations_dir(&self) -> Option<PathBuf> {
-        self.project_hash.as_ref().map(|hash| {
-            self.projects_dir().join(hash)
-        })
+    pub fn project_conversations_dir(&self) -> Option<PathBuf> {
+        self.project_key.as_ref().map(|key| self.projects_dir().join(key))
     }
 
-    /// List all conversations for the current project
-    pub fn list_conversations(&self) -> Result<Vec<ConversationInfo>> {
+    /// Transcript file of a conversation, if the id is well-formed
+    pub fn transcript_path(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return None;
+        }
+        self.project_conversations_dir()
+            .map(|dir| dir.join(format!("{}.jsonl", id)))
+    }
+
+    /// Transcript file of a trashed conversation, if the id is well-formed
+    fn trashed_path(&self, id: &str) -> Option<PathBuf> {
+        let path = self.transcript_path(id)?;
+        let file_name = path.file_name()?.to_owned();
+        Some(path.with_file_name(TRASH_DIR).join(file_name))
+    }
+
+    /// Transcript files of the current project's conversations, by id
+    pub fn transcript_files(&self) -> Result<Vec<(String, PathBuf)>> {
         let conv_dir = match self.project_conversations_dir() {
             Some(dir) => dir,
             None => return Ok(Vec::new()),
@@ -72,140 +147,147 @@ impl SessionManager {
             return Ok(Vec::new());
         }
 
-        let mut conversations = Vec::new();
-
-        // Read conversation directories
+        // One transcript file per conversation
         let entries = fs::read_dir(&conv_dir)
             .map_err(|e| ClaudeError::HistoryParseError(e.to_string()))?;
 
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                if let Some(conv) = self.parse_conversation_dir(&path) {
-                    conversations.push(conv);
-                }
-            }
-        }
-
-        // Sort by updated_at descending
-        conversations.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
-
-        Ok(conversations)
+        Ok(entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .filter_map(|path| {
+                let id = path.file_stem()?.to_str()?.to_string();
+                // Subagent transcripts are part of their parent conversation
+                (!id.starts_with("agent-")).then_some((id, path))
+            })
+            .collect())
     }
 
-    /// Parse a conversation directory to extract metadata
-    fn parse_conversation_dir(&self, path: &PathBuf) -> Option<ConversationInfo> {
-        let id = path.file_name()?.to_str()?.to_string();
-
-        // Try to read conversation metadata
-        let _metadata_path = path.join("metadata.json");
-        let messages_path = path.join("messages.json");
-
-        let (title, preview, message_count) = if messages_path.exists() {
-            self.parse_messages_file(&messages_path).unwrap_or((None, None, 0))
-        } else {
-            (None, None, 0)
-        };
-
-        // Get file timestamps
-        let metadata = fs::metadata(path).ok()?;
-        let created_at = metadata.created().ok()
-            .map(|t| DateTime::<Utc>::from(t))
-            .unwrap_or_else(Utc::now);
-        let updated_at = metadata.modified().ok()
-            .map(|t| DateTime::<Utc>::from(t))
-            .unwrap_or_else(Utc::now);
-
-        Some(ConversationInfo {
-            id,
-            title,
-            preview,
-            created_at,
-            updated_at,
-            message_count,
-        })
-    }
-
-    /// Parse messages file to extract title and preview
-    fn parse_messages_file(&self, path: &PathBuf) -> Result<(Option<String>, Option<String>, usize)> {
-        let content = fs::read_to_string(path)
-            .map_err(|e| ClaudeError::HistoryParseError(e.to_string()))?;
-
-        // Try to parse as JSON array of messages
-        let messages: Vec<serde_json::Value> = serde_json::from_str(&content)
-            .map_err(|e| ClaudeError::HistoryParseError(e.to_string()))?;
-
-        let message_count = messages.len();
-
-        // Get first user message as title/preview
-        let first_user_msg = messages.iter()
-            .find(|m| m.get("role").and_then(|r| r.as_str()) == Some("user"))
-            .and_then(|m| m.get("content"))
-            .and_then(|c| c.as_str())
-            .map(|s| s.to_string());
-
-        let title = first_user_msg.clone().map(|s| {
-            if s.len() > 50 {
-                format!("{}...", &s[..47])
-            } else {
-                s
-            }
-        });
+    /// List all conversations for the current project
+    pub fn list_conversations(&self) -> Result<Vec<ConversationInfo>> {
+        let mut conversations: Vec<_> = self
+            .transcript_files()?
+            .iter()
+            .filter_map(|(_, path)| parse_transcript(path))
+            .collect();
 
-        let preview = first_user_msg.map(|s| {
-            if s.len() > 100 {
-                format!("{}...", &s[..97])
-            } else {
-                s
-            }
-        });
+        // Sort by updated_at descending
+        conversations.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
 
-        Ok((title, preview, message_count))
+        Ok(conversations)
     }
 
     /// Get a specific conversation by ID
-    #[allow(dead_code)]
     pub fn get_conversation(&self, id: &str) -> Result<Option<ConversationInfo>> {
-        let conv_dir = match self.project_conversations_dir() {
-            Some(dir) => dir.join(id),
+        let path = match self.transcript_path(id) {
+            Some(path) => path,
             None => return Ok(None),
         };
 
-        if !conv_dir.exists() {
+        if !path.exists() {
             return Ok(None);
         }
 
-        Ok(self.parse_conversation_dir(&conv_dir))
+        Ok(parse_transcript(&path))
     }
 
-    /// Check if Claude CLI is authenticated
-    /// Note: Claude Code uses OAuth tokens stored in the system keychain,
-    /// so we check for indicators that the CLI has been configured and used.
-    pub fn is_authenticated(&self) -> bool {
-        // Check for files that indicate Claude CLI has been configured
-        let settings_file = self.claude_dir.join("settings.json");
-        let history_file = self.claude_dir.join("history.jsonl");
-        let projects_dir = self.claude_dir.join("projects");
+    /// Messages of a conversation, `limit` at a time starting at `offset`.
+    /// Without an offset the last page is returned, so a reopened
+    /// conversation shows its latest messages and pages back from there.
+    pub fn get_conversation_messages(
+        &self,
+        id: &str,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<MessagePage> {
+        let path = self
+            .transcript_path(id)
+            .filter(|path| path.exists())
+            .ok_or_else(|| ClaudeError::ConversationNotFound(id.to_string()))?;
+
+        let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let (messages, offset, total) = load_message_window(&path, offset, limit)?;
+        Ok(MessagePage {
+            messages,
+            offset,
+            total,
+        })
+    }
+
+    /// Move a conversation to the trash
+    pub fn trash_conversation(&self, id: &str) -> Result<()> {
+        let (path, trashed) = self
+            .transcript_path(id)
+            .zip(self.trashed_path(id))
+            .filter(|(path, _)| path.exists())
+            .ok_or_else(|| ClaudeError::ConversationNotFound(id.to_string()))?;
 
-        // If settings exist and there's history, the user has likely authenticated
-        if settings_file.exists() && (history_file.exists() || projects_dir.exists()) {
-            return true;
+        if let Some(trash) = trashed.parent() {
+            fs::create_dir_all(trash)?;
         }
+        fs::rename(&path, &trashed)?;
+        fs::File::options()
+            .append(true)
+            .open(&trashed)?
+            .set_modified(std::time::SystemTime::now())?;
+        Ok(())
+    }
 
-        // Fallback: Run a quick CLI check to verify authentication status
-        // This is more reliable but has a small performance cost
-        match std::process::Command::new("claude")
-            .arg("--version")
-            .env("NO_COLOR", "1")
-            .output()
-        {
-            Ok(output) => {
-                // If claude --version succeeds, the CLI is installed and configured
-                // Note: Claude Code will fail with an auth error if not logged in
-                output.status.success()
-            }
-            Err(_) => false,
+    /// Move a conversation back out of the trash
+    pub fn restore_conversation(&self, id: &str) -> Result<()> {
+        let (path, trashed) = self
+            .transcript_path(id)
+            .zip(self.trashed_path(id))
+            .filter(|(_, trashed)| trashed.exists())
+            .ok_or_else(|| ClaudeError::ConversationNotFound(id.to_string()))?;
+
+        if path.exists() {
+            return Err(ClaudeError::InvalidOption(format!(
+                "Conversation {} already exists outside the trash",
+                id
+            )));
         }
+        fs::rename(&trashed, &path)?;
+        Ok(())
+    }
+
+    /// Delete a conversation for good, from the trash or not
+    pub fn purge_conversation(&self, id: &str) -> Result<()> {
+        let path = [self.trashed_path(id), self.transcript_path(id)]
+            .into_iter()
+            .flatten()
+            .find(|path| path.exists())
+            .ok_or_else(|| ClaudeError::ConversationNotFound(id.to_string()))?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Conversations in the trash, most recently trashed first
+    pub fn trashed_conversations(&self) -> Result<Vec<TrashedConversation>> {
+        let trash = match self.project_conversations_dir() {
+            Some(dir) => dir.join(TRASH_DIR),
+            None => return Ok(Vec::new()),
+        };
+        let entries = match fs::read_dir(&trash) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut trashed: Vec<_> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .filter_map(|path| {
+                let trashed_at = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some(TrashedConversation {
+                    conversation: parse_transcript(&path)?,
+                    trashed_at: trashed_at.into(),
+                })
+            })
+            .collect();
+        trashed.sort_by_key(|t| std::cmp::Reverse(t.trashed_at));
+        Ok(trashed)
     }
 
     /// Get the Claude CLI config directory
@@ -221,20 +303,220 @@ impl Default for SessionManager {
     }
 }
 
+/// Summarise a transcript; `None` if it holds no messages
+fn parse_transcript(path: &Path) -> Option<ConversationInfo> {
+    let id = path.file_stem()?.to_str()?.to_string();
+
+    let mut summaries: Vec<(Option<String>, String)> = Vec::new();
+    let mut uuids = HashSet::new();
+    let mut assistant_ids = HashSet::new();
+    let mut first_prompt: Option<String> = None;
+    let mut message_count = 0;
+    let mut created_at: Option<DateTime<Utc>> = None;
+    let mut updated_at: Option<DateTime<Utc>> = None;
+
+    for entry in read_entries(path).ok()? {
+        let entry = match entry {
+            Entry::Summary { summary, leaf_uuid } => {
+                summaries.push((leaf_uuid, summary));
+                continue;
+            }
+            Entry::User(entry) => {
+                if let Some(prompt) = entry.prompt() {
+                    message_count += 1;
+                    first_prompt.get_or_insert(prompt);
+                }
+                entry
+            }
+            Entry::Assistant(entry) => {
+                // Every content block is its own line; count the message once
+                let first_line = match entry.message.id {
+                    Some(ref id) => assistant_ids.insert(id.clone()),
+                    None => true,
+                };
+                if entry.is_main_thread() && first_line {
+                    message_count += 1;
+                }
+                entry
+            }
+            Entry::Other => continue,
+        };
+        if let Some(uuid) = entry.uuid {
+            uuids.insert(uuid);
+        }
+        if let Some(ts) = entry.timestamp {
+            created_at = Some(created_at.map_or(ts, |c| c.min(ts)));
+            updated_at = Some(updated_at.map_or(ts, |u| u.max(ts)));
+        }
+    }
+
+    if message_count == 0 {
+        return None;
+    }
+
+    // Prefer the summary of this conversation's own messages; summaries of
+    // the conversation it was resumed from come first in the file
+    let summary = summaries
+        .iter()
+        .rev()
+        .find(|(leaf, _)| leaf.as_ref().is_some_and(|l| uuids.contains(l)))
+        .or(summaries.last())
+        .map(|(_, summary)| summary.clone());
+
+    let modified = || {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now())
+    };
+    let updated_at = updated_at.unwrap_or_else(modified);
+
+    Some(ConversationInfo {
+        id,
+        title: summary
+            .or_else(|| first_prompt.clone())
+            .map(|t| truncate_chars(&t, TITLE_CHARS)),
+        preview: first_prompt.map(|p| truncate_chars(&p, PREVIEW_CHARS)),
+        created_at: created_at.unwrap_or(updated_at),
+        updated_at,
+        message_count,
+        pinned: false,
+        archived: false,
+        tags: Vec::new(),
+        forked_from: None,
+    })
+}
+
+/// First `max` characters of `text` on one line, with an ellipsis if cut
+fn truncate_chars(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max {
+        return text;
+    }
+    let cut: String = text.chars().take(max.saturating_sub(3)).collect();
+    format!("{}...", cut.trim_end())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::testutil::TempDir;
 
     #[test]
-    fn test_session_manager_creation() {
-        let manager = SessionManager::new();
-        assert!(manager.claude_dir.ends_with(".claude"));
+    fn test_claude_dir() {
+        let home = Some(PathBuf::from("/home/ada"));
+        assert_eq!(claude_dir_from(None, home.clone()), Path::new("/home/ada/.claude"));
+        assert_eq!(
+            claude_dir_from(Some("/etc/claude".into()), home),
+            Path::new("/etc/claude")
+        );
+        assert_eq!(claude_dir_from(None, None), Path::new(".claude"));
     }
 
     #[test]
     fn test_project_hash() {
         let mut manager = SessionManager::new();
         manager.set_project_dir("/Users/test/project");
-        assert!(manager.project_hash.is_some());
+        assert_eq!(manager.project_key.as_deref(), Some("-Users-test-project"));
+    }
+
+    #[test]
+    fn test_list_conversations_from_transcripts() {
+        let claude_dir = TempDir::new("sessions");
+        let project = claude_dir.join("projects/-work-app");
+        fs::create_dir_all(&project).unwrap();
+
+        let resumed = [
+            // Summary of the conversation this one was resumed from
+            r#"{"type":"summary","summary":"Older topic","leafUuid":"elsewhere"}"#,
+            r#"{"type":"summary","summary":"Weekly planning","leafUuid":"a2"}"#,
+            r#"{"type":"user","uuid":"u0","isMeta":true,"timestamp":"2025-06-01T09:59:00Z","message":{"role":"user","content":"Caveat: local command output"}}"#,
+            r#"{"type":"user","uuid":"u1","timestamp":"2025-06-01T10:00:00Z","message":{"role":"user","content":"Plan my week"}}"#,
+            r#"{"type":"assistant","uuid":"a1","timestamp":"2025-06-01T10:00:05Z","message":{"id":"msg_1","content":[{"type":"text","text":"Let me look."}]}}"#,
+            r#"{"type":"assistant","uuid":"a1b","timestamp":"2025-06-01T10:00:06Z","message":{"id":"msg_1","content":[{"type":"tool_use","id":"t1","name":"list_tasks","input":{}}]}}"#,
+            r#"{"type":"user","uuid":"r1","timestamp":"2025-06-01T10:00:07Z","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"3 tasks"}]}}"#,
+            r#"{"type":"assistant","uuid":"a2","timestamp":"2025-06-01T10:00:09Z","message":{"id":"msg_2","content":[{"type":"text","text":"You have 3 tasks."}]}}"#,
+            r#"{"type":"user","uuid":"s1","isSidechain":true,"timestamp":"2025-06-01T10:00:08Z","message":{"role":"user","content":"subagent prompt"}}"#,
+            r#"{"type":"assistant","uuid":"a3","timestamp":"2025-06-01T10:00:10Z","message":{"id":"msg_3","content":[{"type":"text","text":"part"#,
+        ];
+        fs::write(project.join("11111111-aaaa.jsonl"), resumed.join("\n")).unwrap();
+
+        let long_prompt = "A rather long first message that goes on\nand on beyond the title limit";
+        let untitled = serde_json::json!({"type": "user", "timestamp": "2025-06-02T08:00:00Z", "message": {"role": "user", "content": long_prompt}});
+        fs::write(project.join("22222222-bbbb.jsonl"), format!("{}\n", untitled)).unwrap();
+        fs::write(project.join("33333333-cccc.jsonl"), r#"{"type":"summary","summary":"Empty"}"#).unwrap();
+
+        let mut manager = SessionManager::with_claude_dir(claude_dir.to_path_buf());
+        manager.project_key = Some("-work-app".to_string());
+        let conversations = manager.list_conversations().unwrap();
+        assert_eq!(conversations.len(), 2);
+
+        let latest = &conversations[0];
+        assert_eq!(latest.id, "22222222-bbbb");
+        assert_eq!(latest.title.as_deref(), Some("A rather long first message that goes on and on..."));
+        assert_eq!(latest.message_count, 1);
+
+        let planning = &conversations[1];
+        assert_eq!(planning.title.as_deref(), Some("Weekly planning"));
+        assert_eq!(planning.preview.as_deref(), Some("Plan my week"));
+        assert_eq!(planning.message_count, 3);
+        assert_eq!(planning.created_at.to_rfc3339(), "2025-06-01T09:59:00+00:00");
+        assert_eq!(planning.updated_at.to_rfc3339(), "2025-06-01T10:00:09+00:00");
+
+        assert!(manager.get_conversation("11111111-aaaa").unwrap().is_some());
+        assert!(manager.get_conversation("../secrets").unwrap().is_none());
+
+        // Pages, latest first by default
+        let page = manager.get_conversation_messages("11111111-aaaa", None, Some(2)).unwrap();
+        assert_eq!((page.offset, page.total, page.messages.len()), (3, 5, 2));
+        assert_eq!(page.messages[0].uuid.as_deref(), Some("r1"));
+        let page = manager.get_conversation_messages("11111111-aaaa", Some(0), Some(2)).unwrap();
+        assert_eq!(page.messages[0].uuid.as_deref(), Some("u1"));
+        let page = manager.get_conversation_messages("11111111-aaaa", Some(2), Some(2)).unwrap();
+        assert_eq!((page.offset, page.total, page.messages.len()), (2, 5, 2));
+        assert_eq!(page.messages[1].uuid.as_deref(), Some("r1"));
+        let page = manager.get_conversation_messages("11111111-aaaa", Some(9), None).unwrap();
+        assert_eq!((page.offset, page.messages.len()), (5, 0));
+        assert!(matches!(
+            manager.get_conversation_messages("99999999-dddd", None, None),
+            Err(ClaudeError::ConversationNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_trash_restore_and_purge() {
+        let claude_dir = TempDir::new("trash");
+        let project = claude_dir.join("projects/-work-app");
+        fs::create_dir_all(&project).unwrap();
+        let line = r#"{"type":"user","uuid":"u1","timestamp":"2025-06-01T10:00:00Z","message":{"role":"user","content":"Old question"}}"#;
+        fs::write(project.join("aaaa-1.jsonl"), format!("{}\n", line)).unwrap();
+        fs::write(project.join("bbbb-2.jsonl"), format!("{}\n", line)).unwrap();
+
+        let mut manager = SessionManager::with_claude_dir(claude_dir.to_path_buf());
+        manager.project_key = Some("-work-app".to_string());
+        assert!(manager.trashed_conversations().unwrap().is_empty());
+
+        manager.trash_conversation("aaaa-1").unwrap();
+        let ids: Vec<_> = manager.list_conversations().unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec!["bbbb-2"]);
+        let trashed = manager.trashed_conversations().unwrap();
+        assert_eq!(trashed[0].conversation.id, "aaaa-1");
+        assert!(Utc::now() - trashed[0].trashed_at < chrono::Duration::minutes(1));
+        assert!(matches!(
+            manager.trash_conversation("aaaa-1"),
+            Err(ClaudeError::ConversationNotFound(_))
+        ));
+
+        manager.restore_conversation("aaaa-1").unwrap();
+        assert!(manager.get_conversation("aaaa-1").unwrap().is_some());
+        assert!(manager.trashed_conversations().unwrap().is_empty());
+
+        // Purging works in and out of the trash
+        manager.trash_conversation("aaaa-1").unwrap();
+        manager.purge_conversation("aaaa-1").unwrap();
+        manager.purge_conversation("bbbb-2").unwrap();
+        assert!(manager.list_conversations().unwrap().is_empty());
+        assert!(manager.trashed_conversations().unwrap().is_empty());
+        assert!(manager.restore_conversation("aaaa-1").is_err());
     }
 }
@@ -0,0 +1,187 @@
+//! SQLite persistence for the Claude integration
+//!
+//! Shares the app database (`personal-assistant.db`) with the frontend's
+//! `tauri-plugin-sql` connection and keeps its own `claude_*` tables.
+
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+
+use super::error::{ClaudeError, Result};
+
+/// Schema for the Claude tables (idempotent, run on every open)
+const SCHEMA: &str = "
+    -- Token usage and cost per assistant turn
+    CREATE TABLE IF NOT EXISTS claude_usage (
+        id TEXT PRIMARY KEY,
+        session_id TEXT NOT NULL,
+        message_id TEXT NOT NULL,
+        input_tokens INTEGER NOT NULL DEFAULT 0,
+        output_tokens INTEGER NOT NULL DEFAULT 0,
+        cache_creation_input_tokens INTEGER NOT NULL DEFAULT 0,
+        cache_read_input_tokens INTEGER NOT NULL DEFAULT 0,
+        total_cost_usd REAL NOT NULL DEFAULT 0,
+        duration_ms INTEGER NOT NULL DEFAULT 0,
+        duration_api_ms INTEGER NOT NULL DEFAULT 0,
+        num_turns INTEGER NOT NULL DEFAULT 0,
+        is_error INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT DEFAULT (datetime('now'))
+    );
+    CREATE INDEX IF NOT EXISTS idx_claude_usage_session ON claude_usage(session_id);
+    CREATE INDEX IF NOT EXISTS idx_claude_usage_created ON claude_usage(created_at);
+
+    -- Named session option presets (options stored as JSON)
+    CREATE TABLE IF NOT EXISTS claude_presets (
+        name TEXT PRIMARY KEY,
+        options TEXT NOT NULL,
+        created_at TEXT DEFAULT (datetime('now')),
+        updated_at TEXT DEFAULT (datetime('now'))
+    );
+
+    -- Integration settings (values stored as JSON)
+    CREATE TABLE IF NOT EXISTS claude_settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL,
+        updated_at TEXT DEFAULT (datetime('now'))
+    );
+
+    -- Full-text index of conversation transcripts
+    CREATE VIRTUAL TABLE IF NOT EXISTS claude_search USING fts5(
+        content,
+        conversation_id UNINDEXED,
+        position UNINDEXED,
+        role UNINDEXED,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+
+    -- How far each transcript has been indexed
+    CREATE TABLE IF NOT EXISTS claude_search_files (
+        conversation_id TEXT PRIMARY KEY,
+        byte_offset INTEGER NOT NULL,
+        message_count INTEGER NOT NULL,
+        indexed_at TEXT DEFAULT (datetime('now'))
+    );
+
+    -- Conversations exported as notes into the vault
+    CREATE TABLE IF NOT EXISTS claude_exports (
+        conversation_id TEXT PRIMARY KEY,
+        path TEXT NOT NULL,
+        exported_at TEXT DEFAULT (datetime('now'))
+    );
+
+    -- App-side title, pin and archive state of CLI conversations
+    CREATE TABLE IF NOT EXISTS claude_conversation_meta (
+        conversation_id TEXT PRIMARY KEY,
+        title TEXT,
+        pinned INTEGER NOT NULL DEFAULT 0,
+        archived INTEGER NOT NULL DEFAULT 0,
+        updated_at TEXT DEFAULT (datetime('now'))
+    );
+
+    -- The frontend's tag table (src/lib/db.ts), in case it isn't there yet
+    CREATE TABLE IF NOT EXISTS tags (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        color TEXT
+    );
+
+    -- Conversation-Tag relationship
+    CREATE TABLE IF NOT EXISTS claude_conversation_tags (
+        conversation_id TEXT NOT NULL,
+        tag_id TEXT REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (conversation_id, tag_id)
+    );
+
+    -- Forked conversations and where they branched off
+    CREATE TABLE IF NOT EXISTS claude_conversation_forks (
+        conversation_id TEXT PRIMARY KEY,
+        parent_id TEXT NOT NULL,
+        message_index INTEGER NOT NULL,
+        created_at TEXT DEFAULT (datetime('now'))
+    );
+";
+
+/// Handle to the Claude tables in the app database
+pub struct ClaudeStore {
+    conn: Mutex<Connection>,
+}
+
+impl ClaudeStore {
+    /// Open (or create) the database file and ensure the schema exists
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        // The frontend holds its own connection to the same file
+        conn.busy_timeout(Duration::from_secs(5))?;
+        Self::init(conn)
+    }
+
+    /// Open a private in-memory database (tests and fallback)
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Run a closure with exclusive access to the connection
+    pub fn with_conn<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Result<T> {
+        Ok(f(&self.conn.lock())?)
+    }
+
+    /// Read a setting, `None` if it was never saved
+    pub fn get_setting<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value: Option<String> = self.with_conn(|conn| {
+            conn.query_row(
+                "SELECT value FROM claude_settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+        value
+            .map(|v| {
+                serde_json::from_str(&v)
+                    .map_err(|e| ClaudeError::InvalidOption(format!("Setting {} is corrupt: {}", key, e)))
+            })
+            .transpose()
+    }
+
+    /// Create or replace a setting
+    pub fn set_setting<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value).map_err(|e| ClaudeError::InvalidOption(e.to_string()))?;
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO claude_settings (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value,
+                     updated_at = datetime('now')",
+                params![key, json],
+            )
+            .map(|_| ())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let store = ClaudeStore::open_in_memory().unwrap();
+        assert_eq!(store.get_setting::<String>("missing").unwrap(), None);
+
+        store.set_setting("vault", &"Notes/Claude").unwrap();
+        store.set_setting("vault", &"Claude").unwrap();
+        assert_eq!(store.get_setting::<String>("vault").unwrap().as_deref(), Some("Claude"));
+    }
+}
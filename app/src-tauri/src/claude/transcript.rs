@@ -0,0 +1,583 @@
+//! Claude Code conversation transcripts
+//!
+//! The CLI appends one JSON object per line to
+//! `~/.claude/projects/<encoded project dir>/<session id>.jsonl`: user and
+//! assistant messages (an assistant message with several content blocks is
+//! split over several lines sharing `message.id`), tool results as user
+//! entries, `summary` entries written on compaction or resume, and
+//! bookkeeping entries the app ignores. Files are read line by line, and
+//! lines that don't parse (e.g. one still being written) are skipped.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::error::{ClaudeError, Result};
+use super::pty::{ContentBlock, Usage, UserContent};
+
+/// A message of a past conversation, for display
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryMessage {
+    /// Transcript entry the message came from
+    pub uuid: Option<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub content: HistoryContent,
+}
+
+/// What a history message holds; mirrors the live `claude:*` events
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryContent {
+    User { content: String },
+    Assistant { content: String },
+    Thinking { content: String },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        /// Name of the tool, so a result can be shown without its call
+        name: Option<String>,
+        content: String,
+        is_error: bool,
+    },
+}
+
+/// One line of a transcript
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    Summary {
+        summary: String,
+        #[serde(rename = "leafUuid", default)]
+        leaf_uuid: Option<String>,
+    },
+    User(MessageEntry),
+    Assistant(MessageEntry),
+    /// `system`, `file-history-snapshot` and other bookkeeping
+    #[serde(other)]
+    Other,
+}
+
+/// A user or assistant entry
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageEntry {
+    #[serde(default)]
+    pub uuid: Option<String>,
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Part of a subagent's conversation rather than the main thread
+    #[serde(default)]
+    pub is_sidechain: bool,
+    /// Injected by the CLI (e.g. the caveat before local command output)
+    #[serde(default)]
+    pub is_meta: bool,
+    pub message: TranscriptMessage,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranscriptMessage {
+    /// Messages API id, shared by the lines of one assistant message
+    #[serde(default)]
+    pub id: Option<String>,
+    pub content: UserContent,
+    /// Token usage of an assistant message, repeated on each of its lines
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+impl MessageEntry {
+    /// Whether the entry is part of the visible main conversation
+    pub fn is_main_thread(&self) -> bool {
+        !self.is_sidechain && !self.is_meta
+    }
+
+    /// Text of the entry's content blocks, joined
+    pub fn text(&self) -> String {
+        match &self.message.content {
+            UserContent::Text(text) => text.clone(),
+            UserContent::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// The prompt of a user entry the person actually typed, as opposed to
+    /// tool results and slash-command bookkeeping
+    pub fn prompt(&self) -> Option<String> {
+        if !self.is_main_thread() {
+            return None;
+        }
+        let text = self.text();
+        let text = text.trim();
+        let internal = text.starts_with("<command-") || text.starts_with("<local-command-");
+        (!text.is_empty() && !internal).then(|| text.to_string())
+    }
+}
+
+/// Name of the directory the CLI keeps a project's transcripts in: the
+/// absolute project path with every non-alphanumeric character replaced
+/// by `-` (`/Users/me/my.app` becomes `-Users-me-my-app`)
+pub fn encode_project_dir(project_dir: &str) -> String {
+    project_dir
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Stream the entries of a transcript file
+pub fn read_entries(path: &Path) -> Result<EntryReader> {
+    EntryReader::open(path, 0, false)
+}
+
+/// Iterator over the entries of a transcript, from a byte offset
+pub struct EntryReader {
+    reader: BufReader<File>,
+    line: Vec<u8>,
+    offset: u64,
+    /// Stop at a last line without a newline instead of parsing it
+    complete_lines_only: bool,
+}
+
+impl EntryReader {
+    /// Read the entries after `offset`, which must be the start of a line.
+    /// With `complete_lines_only`, a line the CLI is still writing is left
+    /// for the next read.
+    pub fn open(path: &Path, offset: u64, complete_lines_only: bool) -> Result<Self> {
+        let mut file = File::open(path)
+            .map_err(|e| ClaudeError::HistoryParseError(format!("{}: {}", path.display(), e)))?;
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(Self {
+            reader: BufReader::new(file),
+            line: Vec::new(),
+            offset,
+            complete_lines_only,
+        })
+    }
+
+    /// Byte offset just past the last complete line read
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl Iterator for EntryReader {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        loop {
+            self.line.clear();
+            let read = self.reader.read_until(b'\n', &mut self.line).ok()?;
+            if read == 0 {
+                return None;
+            }
+            let complete = self.line.ends_with(b"\n");
+            if complete {
+                self.offset += read as u64;
+            } else if self.complete_lines_only {
+                return None;
+            }
+            if let Ok(entry) = serde_json::from_slice(&self.line) {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+/// Copy the start of a transcript, through the entry `through_uuid`, as
+/// the conversation `session_id`. The cut moves past the rest of that
+/// assistant message and the results of its tool calls, since every tool
+/// call needs its result. Returns `false`, writing nothing, when the copy
+/// would be the whole transcript.
+pub fn write_prefix(source: &Path, through_uuid: &str, dest: &Path, session_id: &str) -> Result<bool> {
+    let content = std::fs::read_to_string(source)?;
+    let entries: Vec<serde_json::Value> = content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+
+    let field = |entry: &serde_json::Value, pointer: &str| {
+        entry.pointer(pointer).and_then(|v| v.as_str()).map(str::to_string)
+    };
+    let message_id = |entry: &serde_json::Value| field(entry, "/message/id");
+    let cut = entries
+        .iter()
+        .position(|entry| field(entry, "/uuid").as_deref() == Some(through_uuid))
+        .ok_or_else(|| ClaudeError::InvalidOption(format!("Message {} not found", through_uuid)))?;
+
+    // Tool calls in the copy that have no result yet
+    let mut open_calls = HashSet::new();
+    let mut track_calls = |entry: &serde_json::Value| {
+        let blocks = entry.pointer("/message/content").and_then(|c| c.as_array());
+        for block in blocks.into_iter().flatten() {
+            match block.get("type").and_then(|t| t.as_str()) {
+                Some("tool_use") => {
+                    open_calls.insert(block["id"].as_str().unwrap_or_default().to_string());
+                }
+                Some("tool_result") => {
+                    open_calls.remove(block["tool_use_id"].as_str().unwrap_or_default());
+                }
+                _ => {}
+            }
+        }
+        !open_calls.is_empty()
+    };
+    let mut waiting = false;
+    for entry in &entries[..=cut] {
+        waiting = track_calls(entry);
+    }
+    let cut_message = message_id(&entries[cut]);
+    let mut end = cut + 1;
+    while let Some(entry) = entries.get(end) {
+        let same_message = cut_message.is_some() && message_id(entry) == cut_message;
+        let answers = waiting && entry.get("type").and_then(|t| t.as_str()) == Some("user");
+        if !same_message && !answers {
+            break;
+        }
+        waiting = track_calls(entry);
+        end += 1;
+    }
+    if end == entries.len() {
+        return Ok(false);
+    }
+
+    write_copy(entries.into_iter().take(end), dest, session_id)?;
+    Ok(true)
+}
+
+/// Copy a whole transcript as the conversation `session_id`
+pub fn copy_transcript(source: &Path, dest: &Path, session_id: &str) -> Result<()> {
+    let content = std::fs::read_to_string(source)?;
+    let entries = content.lines().filter_map(|line| serde_json::from_str(line).ok());
+    write_copy(entries, dest, session_id)
+}
+
+/// Write `entries` to `dest`, relabelled as the conversation `session_id`
+fn write_copy(
+    entries: impl Iterator<Item = serde_json::Value>,
+    dest: &Path,
+    session_id: &str,
+) -> Result<()> {
+    let mut copy = String::new();
+    for mut entry in entries {
+        if let Some(id) = entry.get_mut("sessionId") {
+            *id = serde_json::Value::String(session_id.to_string());
+        }
+        copy.push_str(&entry.to_string());
+        copy.push('\n');
+    }
+    std::fs::write(dest, copy)?;
+    Ok(())
+}
+
+/// Uuid of the last message entry, which the next entry's `parentUuid`
+/// points at
+pub fn last_uuid(path: &Path) -> Result<Option<String>> {
+    Ok(read_entries(path)?
+        .filter_map(|entry| match entry {
+            Entry::User(entry) | Entry::Assistant(entry) => entry.uuid,
+            _ => None,
+        })
+        .last())
+}
+
+/// Append entries to a transcript, creating it (and its folder) if needed
+pub fn append_entries(path: &Path, entries: &[serde_json::Value]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&entry.to_string());
+        lines.push('\n');
+    }
+    File::options()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+/// Tokens used by a conversation, subagents included
+pub fn token_usage(path: &Path) -> Result<Usage> {
+    // Keep the last line of each message, which has its final usage
+    let mut by_message: HashMap<String, Usage> = HashMap::new();
+    let mut total = Usage::default();
+    for entry in read_entries(path)? {
+        let message = match entry {
+            Entry::Assistant(entry) => entry.message,
+            _ => continue,
+        };
+        match (message.id, message.usage) {
+            (Some(id), Some(usage)) => {
+                by_message.insert(id, usage);
+            }
+            (None, Some(usage)) => add_usage(&mut total, &usage),
+            _ => {}
+        }
+    }
+    for usage in by_message.values() {
+        add_usage(&mut total, usage);
+    }
+    Ok(total)
+}
+
+fn add_usage(total: &mut Usage, usage: &Usage) {
+    total.input_tokens += usage.input_tokens;
+    total.output_tokens += usage.output_tokens;
+    total.cache_creation_input_tokens += usage.cache_creation_input_tokens;
+    total.cache_read_input_tokens += usage.cache_read_input_tokens;
+}
+
+/// The main-thread messages of a transcript, in order
+pub fn load_messages(path: &Path) -> Result<Vec<HistoryMessage>> {
+    let mut builder = MessageBuilder::default();
+    let mut messages = Vec::new();
+    for entry in read_entries(path)? {
+        builder.push(entry, &mut messages);
+    }
+    Ok(messages)
+}
+
+/// A window of the main-thread messages of a transcript, with the index of
+/// its first message and the number of messages in the whole transcript
+///
+/// Only messages inside the window are kept; without `offset` the window is
+/// the last `limit` messages.
+pub fn load_message_window(
+    path: &Path,
+    offset: Option<usize>,
+    limit: usize,
+) -> Result<(Vec<HistoryMessage>, usize, usize)> {
+    let mut builder = MessageBuilder::default();
+    let mut built = Vec::new();
+    let mut window = VecDeque::new();
+    let mut total = 0;
+    for entry in read_entries(path)? {
+        builder.push(entry, &mut built);
+        for message in built.drain(..) {
+            match offset {
+                Some(offset) if (offset..offset.saturating_add(limit)).contains(&total) => {
+                    window.push_back(message)
+                }
+                Some(_) => {}
+                None => {
+                    if window.len() == limit {
+                        window.pop_front();
+                    }
+                    window.push_back(message);
+                }
+            }
+            total += 1;
+        }
+    }
+    let offset = offset.unwrap_or_else(|| total.saturating_sub(limit)).min(total);
+    Ok((window.into(), offset, total))
+}
+
+/// Turns transcript entries into history messages
+#[derive(Default)]
+pub struct MessageBuilder {
+    /// Tool names by tool use id, to label results
+    tool_names: HashMap<String, String>,
+}
+
+impl MessageBuilder {
+    /// Append the messages of `entry` (none for bookkeeping and sidechains)
+    pub fn push(&mut self, entry: Entry, messages: &mut Vec<HistoryMessage>) {
+        let (entry, is_user) = match entry {
+            Entry::User(entry) => (entry, true),
+            Entry::Assistant(entry) => (entry, false),
+            _ => return,
+        };
+        if !entry.is_main_thread() {
+            return;
+        }
+        let prompt = if is_user { entry.prompt() } else { None };
+        let MessageEntry { uuid, timestamp, message, .. } = entry;
+        let mut push = |content| {
+            messages.push(HistoryMessage {
+                uuid: uuid.clone(),
+                timestamp,
+                content,
+            })
+        };
+
+        if let Some(prompt) = prompt {
+            push(HistoryContent::User { content: prompt });
+        }
+        let blocks = match message.content {
+            UserContent::Blocks(blocks) => blocks,
+            UserContent::Text(_) => return,
+        };
+        for block in blocks {
+            match block {
+                ContentBlock::Text { text } if !is_user && !text.trim().is_empty() => {
+                    push(HistoryContent::Assistant { content: text })
+                }
+                ContentBlock::Thinking { thinking } if !is_user => {
+                    push(HistoryContent::Thinking { content: thinking })
+                }
+                ContentBlock::ToolUse { id, name, input } => {
+                    self.tool_names.insert(id.clone(), name.clone());
+                    push(HistoryContent::ToolUse { id, name, input })
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } => push(HistoryContent::ToolResult {
+                    name: self.tool_names.get(&tool_use_id).cloned(),
+                    tool_use_id,
+                    content: content.map(|c| c.into_text()).unwrap_or_default(),
+                    is_error,
+                }),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testutil::TempDir;
+
+    #[test]
+    fn test_encode_project_dir() {
+        assert_eq!(encode_project_dir("/Users/test/project"), "-Users-test-project");
+        assert_eq!(encode_project_dir("/home/a.b/my_app v2"), "-home-a-b-my-app-v2");
+    }
+
+    #[test]
+    fn test_parse_entries() {
+        let prompt: Entry = serde_json::from_str(
+            r#"{"type":"user","uuid":"u1","timestamp":"2025-06-01T10:00:00.000Z","isSidechain":false,"message":{"role":"user","content":"Plan my week"}}"#,
+        )
+        .unwrap();
+        match prompt {
+            Entry::User(entry) => assert_eq!(entry.prompt().as_deref(), Some("Plan my week")),
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let tool_result: Entry = serde_json::from_str(
+            r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}"#,
+        )
+        .unwrap();
+        assert!(matches!(tool_result, Entry::User(ref e) if e.prompt().is_none()));
+
+        let command: Entry = serde_json::from_str(
+            r#"{"type":"user","message":{"role":"user","content":"<command-name>/clear</command-name>"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(command, Entry::User(ref e) if e.prompt().is_none()));
+
+        let snapshot: Entry =
+            serde_json::from_str(r#"{"type":"file-history-snapshot","messageId":"m"}"#).unwrap();
+        assert!(matches!(snapshot, Entry::Other));
+    }
+
+    #[test]
+    fn test_load_messages() {
+        let lines = [
+            r#"{"type":"summary","summary":"Weekly planning","leafUuid":"a2"}"#,
+            r#"{"type":"user","uuid":"u0","isMeta":true,"message":{"role":"user","content":"Caveat: local command output"}}"#,
+            r#"{"type":"user","uuid":"u1","timestamp":"2025-06-01T10:00:00Z","message":{"role":"user","content":"Plan my week"}}"#,
+            r#"{"type":"assistant","uuid":"a0","message":{"id":"msg_1","content":[{"type":"thinking","thinking":"Tasks first","signature":"s"}]}}"#,
+            r#"{"type":"assistant","uuid":"a1","message":{"id":"msg_1","content":[{"type":"tool_use","id":"t1","name":"list_tasks","input":{"week":24}}]}}"#,
+            r#"{"type":"user","uuid":"r1","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":[{"type":"text","text":"3 tasks"}]}]}}"#,
+            r#"{"type":"user","uuid":"s1","isSidechain":true,"message":{"role":"user","content":"subagent prompt"}}"#,
+            r#"{"type":"assistant","uuid":"a2","message":{"id":"msg_2","content":[{"type":"text","text":"You have 3 tasks."}]}}"#,
+        ];
+        let dir = TempDir::new("transcript");
+        let path = dir.join("conv.jsonl");
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let messages = load_messages(&path).unwrap();
+        let contents: Vec<_> = messages.iter().map(|m| m.content.clone()).collect();
+        assert_eq!(
+            contents,
+            vec![
+                HistoryContent::User { content: "Plan my week".to_string() },
+                HistoryContent::Thinking { content: "Tasks first".to_string() },
+                HistoryContent::ToolUse {
+                    id: "t1".to_string(),
+                    name: "list_tasks".to_string(),
+                    input: serde_json::json!({"week": 24}),
+                },
+                HistoryContent::ToolResult {
+                    tool_use_id: "t1".to_string(),
+                    name: Some("list_tasks".to_string()),
+                    content: "3 tasks".to_string(),
+                    is_error: false,
+                },
+                HistoryContent::Assistant { content: "You have 3 tasks.".to_string() },
+            ]
+        );
+        assert_eq!(messages[0].uuid.as_deref(), Some("u1"));
+        assert!(messages[0].timestamp.is_some());
+
+        let json = serde_json::to_value(&messages[3]).unwrap();
+        assert_eq!(json["type"], "tool_result");
+        assert_eq!(json["uuid"], "r1");
+
+        // Resuming stops before a line that is still being written
+        let head = lines[..3].join("\n") + "\n";
+        std::fs::write(&path, format!("{}{}", head, &lines[3][..20])).unwrap();
+        let mut reader = EntryReader::open(&path, 0, true).unwrap();
+        assert_eq!(reader.by_ref().count(), 3);
+        assert_eq!(reader.offset(), head.len() as u64);
+        let mut reader = EntryReader::open(&path, reader.offset(), true).unwrap();
+        assert!(reader.next().is_none());
+        assert_eq!(reader.offset(), head.len() as u64);
+    }
+
+    #[test]
+    fn test_write_prefix() {
+        let lines = [
+            r#"{"type":"user","uuid":"u1","sessionId":"parent","message":{"role":"user","content":"List my tasks"}}"#,
+            r#"{"type":"assistant","uuid":"a1","sessionId":"parent","message":{"id":"msg_1","content":[{"type":"text","text":"Checking."}]}}"#,
+            r#"{"type":"assistant","uuid":"a2","sessionId":"parent","message":{"id":"msg_1","content":[{"type":"tool_use","id":"t1","name":"list_tasks","input":{}}]}}"#,
+            r#"{"type":"user","uuid":"r1","sessionId":"parent","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"none"}]}}"#,
+            r#"{"type":"assistant","uuid":"a3","sessionId":"parent","message":{"id":"msg_2","content":[{"type":"text","text":"No tasks."}]}}"#,
+            r#"{"type":"user","uuid":"u2","sessionId":"parent","message":{"role":"user","content":"Thanks"}}"#,
+        ];
+        let dir = TempDir::new("fork");
+        let source = dir.join("parent.jsonl");
+        std::fs::write(&source, lines.join("\n") + "\n").unwrap();
+        let dest = dir.join("fork.jsonl");
+
+        // Cutting at the text keeps the tool call of the same message and its result
+        assert!(write_prefix(&source, "a1", &dest, "fork").unwrap());
+        let copied: Vec<serde_json::Value> = std::fs::read_to_string(&dest)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let uuids: Vec<_> = copied.iter().map(|e| e["uuid"].as_str().unwrap()).collect();
+        assert_eq!(uuids, ["u1", "a1", "a2", "r1"]);
+        assert!(copied.iter().all(|e| e["sessionId"] == "fork"));
+
+        assert!(write_prefix(&source, "u1", &dest, "fork").unwrap());
+        assert_eq!(std::fs::read_to_string(&dest).unwrap().lines().count(), 1);
+
+        // Through the last message there is nothing to cut
+        std::fs::remove_file(&dest).unwrap();
+        assert!(!write_prefix(&source, "u2", &dest, "fork").unwrap());
+        assert!(!dest.exists());
+        assert!(write_prefix(&source, "missing", &dest, "fork").is_err());
+    }
+}
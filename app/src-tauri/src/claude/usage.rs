@@ -0,0 +1,232 @@
+//! Token usage and cost accounting
+//!
+//! Each completed turn reports usage in the CLI's `result` event. Those
+//! numbers are stored per session/message in `claude_usage` and rolled up
+//! into daily and monthly aggregates for the usage view.
+
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::error::Result;
+use super::pty::TurnUsage;
+use super::store::ClaudeStore;
+
+/// Usage recorded for a single message (one user turn)
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRecord {
+    pub session_id: String,
+    pub message_id: String,
+    #[serde(flatten)]
+    pub turn: TurnUsage,
+}
+
+/// Summed usage over a set of messages
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct UsageTotals {
+    pub messages: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub total_cost_usd: f64,
+}
+
+/// Aggregated usage for one day (`YYYY-MM-DD`) or month (`YYYY-MM`)
+#[derive(Debug, Clone, Serialize)]
+pub struct UsagePeriod {
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// Usage report returned by `claude_get_usage`
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageReport {
+    pub session: Option<UsageTotals>,
+    pub total: UsageTotals,
+    pub daily: Vec<UsagePeriod>,
+    pub monthly: Vec<UsagePeriod>,
+}
+
+const TOTALS_COLUMNS: &str = "COUNT(*), \
+    COALESCE(SUM(input_tokens), 0), \
+    COALESCE(SUM(output_tokens), 0), \
+    COALESCE(SUM(cache_creation_input_tokens), 0), \
+    COALESCE(SUM(cache_read_input_tokens), 0), \
+    COALESCE(SUM(total_cost_usd), 0)";
+
+/// Read totals from the row, starting at column `offset`
+fn totals_from_row(row: &Row, offset: usize) -> rusqlite::Result<UsageTotals> {
+    Ok(UsageTotals {
+        messages: row.get::<_, i64>(offset)? as u64,
+        input_tokens: row.get::<_, i64>(offset + 1)? as u64,
+        output_tokens: row.get::<_, i64>(offset + 2)? as u64,
+        cache_creation_input_tokens: row.get::<_, i64>(offset + 3)? as u64,
+        cache_read_input_tokens: row.get::<_, i64>(offset + 4)? as u64,
+        total_cost_usd: row.get(offset + 5)?,
+    })
+}
+
+impl ClaudeStore {
+    /// Persist the usage of one message
+    pub fn record_usage(&self, record: &UsageRecord) -> Result<()> {
+        let turn = &record.turn;
+        self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO claude_usage (
+                    id, session_id, message_id, input_tokens, output_tokens,
+                    cache_creation_input_tokens, cache_read_input_tokens,
+                    total_cost_usd, duration_ms, duration_api_ms, num_turns, is_error
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    record.session_id,
+                    record.message_id,
+                    turn.usage.input_tokens as i64,
+                    turn.usage.output_tokens as i64,
+                    turn.usage.cache_creation_input_tokens as i64,
+                    turn.usage.cache_read_input_tokens as i64,
+                    turn.total_cost_usd,
+                    turn.duration_ms as i64,
+                    turn.duration_api_ms as i64,
+                    turn.num_turns as i64,
+                    turn.is_error,
+                ],
+            )
+            .map(|_| ())
+        })
+    }
+
+    /// Totals for a single session, if it has any recorded messages
+    pub fn session_usage(&self, session_id: &str) -> Result<Option<UsageTotals>> {
+        let sql = format!(
+            "SELECT {} FROM claude_usage WHERE session_id = ?1",
+            TOTALS_COLUMNS
+        );
+        let totals = self.with_conn(|conn| {
+            conn.query_row(&sql, params![session_id], |row| totals_from_row(row, 0))
+                .optional()
+        })?;
+        Ok(totals.filter(|t| t.messages > 0))
+    }
+
+    /// Totals across all sessions
+    pub fn total_usage(&self) -> Result<UsageTotals> {
+        let sql = format!("SELECT {} FROM claude_usage", TOTALS_COLUMNS);
+        self.with_conn(|conn| conn.query_row(&sql, [], |row| totals_from_row(row, 0)))
+    }
+
+    /// Per-day totals for the last `days` days (UTC), oldest first
+    pub fn daily_usage(&self, days: u32) -> Result<Vec<UsagePeriod>> {
+        self.period_usage(
+            "date(created_at)",
+            "date('now', ?1)",
+            format!("-{} days", days.saturating_sub(1)),
+        )
+    }
+
+    /// Per-month totals for the last `months` months (UTC), oldest first
+    pub fn monthly_usage(&self, months: u32) -> Result<Vec<UsagePeriod>> {
+        self.period_usage(
+            "strftime('%Y-%m', created_at)",
+            "date('now', 'start of month', ?1)",
+            format!("-{} months", months.saturating_sub(1)),
+        )
+    }
+
+    fn period_usage(&self, bucket: &str, since: &str, modifier: String) -> Result<Vec<UsagePeriod>> {
+        let sql = format!(
+            "SELECT {bucket} AS period, {TOTALS_COLUMNS} FROM claude_usage
+             WHERE created_at >= {since}
+             GROUP BY period ORDER BY period"
+        );
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params![modifier], |row| {
+                Ok(UsagePeriod {
+                    period: row.get(0)?,
+                    totals: totals_from_row(row, 1)?,
+                })
+            })?;
+            rows.collect()
+        })
+    }
+
+    /// Build the full report for the usage view
+    pub fn usage_report(&self, session_id: Option<&str>, days: u32, months: u32) -> Result<UsageReport> {
+        Ok(UsageReport {
+            session: match session_id {
+                Some(id) => self.session_usage(id)?,
+                None => None,
+            },
+            total: self.total_usage()?,
+            daily: self.daily_usage(days)?,
+            monthly: self.monthly_usage(months)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claude::pty::Usage;
+
+    fn record(session_id: &str, input: u64, output: u64, cost: f64) -> UsageRecord {
+        UsageRecord {
+            session_id: session_id.to_string(),
+            message_id: uuid::Uuid::new_v4().to_string(),
+            turn: TurnUsage {
+                usage: Usage {
+                    input_tokens: input,
+                    output_tokens: output,
+                    ..Default::default()
+                },
+                total_cost_usd: cost,
+                duration_ms: 1200,
+                duration_api_ms: 1000,
+                num_turns: 1,
+                is_error: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_session_and_total_usage() {
+        let store = ClaudeStore::open_in_memory().unwrap();
+        store.record_usage(&record("a", 100, 50, 0.01)).unwrap();
+        store.record_usage(&record("a", 200, 25, 0.02)).unwrap();
+        store.record_usage(&record("b", 10, 5, 0.001)).unwrap();
+
+        let session = store.session_usage("a").unwrap().unwrap();
+        assert_eq!(session.messages, 2);
+        assert_eq!(session.input_tokens, 300);
+        assert_eq!(session.output_tokens, 75);
+        assert!((session.total_cost_usd - 0.03).abs() < 1e-9);
+
+        assert_eq!(store.total_usage().unwrap().messages, 3);
+        assert!(store.session_usage("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_period_aggregates() {
+        let store = ClaudeStore::open_in_memory().unwrap();
+        store.record_usage(&record("a", 100, 50, 0.01)).unwrap();
+        store
+            .with_conn(|conn| {
+                conn.execute(
+                    "UPDATE claude_usage SET created_at = datetime('now', '-40 days')",
+                    [],
+                )
+            })
+            .unwrap();
+        store.record_usage(&record("a", 200, 25, 0.02)).unwrap();
+
+        let daily = store.daily_usage(7).unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].totals.input_tokens, 200);
+
+        let monthly = store.monthly_usage(3).unwrap();
+        assert_eq!(monthly.len(), 2);
+        assert_eq!(monthly.iter().map(|m| m.totals.messages).sum::<u64>(), 2);
+    }
+}
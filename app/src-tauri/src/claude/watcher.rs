@@ -0,0 +1,180 @@
+//! Live updates of the conversation list
+//!
+//! Watches the project's transcript directory and emits
+//! `claude:conversations-changed` with the conversations that were created,
+//! appended to or removed, whether the session runs in the app or in a
+//! terminal. Changes are gathered for `DEBOUNCE` after the first one, so a
+//! streaming response refreshes the sidebar at most about once a second.
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::error::{ClaudeError, Result};
+use super::events::EventEmitter;
+use super::metadata;
+use super::sessions::{ConversationInfo, SessionManager};
+use super::store::ClaudeStore;
+
+/// How long changes are gathered before they are emitted
+const DEBOUNCE: Duration = Duration::from_millis(1000);
+
+/// Payload of `claude:conversations-changed`
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationsChanged {
+    /// New or updated conversations, with the app's overlay merged in
+    pub upserted: Vec<ConversationInfo>,
+    /// Ids of conversations whose transcript is gone
+    pub removed: Vec<String>,
+}
+
+/// Watches transcripts until dropped
+pub struct ConversationWatcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl ConversationWatcher {
+    /// Start watching the project of `sessions`
+    pub fn start(sessions: SessionManager, store: Arc<ClaudeStore>, events: EventEmitter) -> Result<Self> {
+        let dir = sessions
+            .project_conversations_dir()
+            .ok_or_else(|| ClaudeError::HistoryParseError("No project directory set".to_string()))?;
+        // The CLI creates it with the first conversation; creating it now
+        // lets a conversation started in a terminal show up right away
+        std::fs::create_dir_all(&dir)?;
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                    for path in event.paths {
+                        let _ = tx.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("[Claude] Conversation watcher error: {}", e),
+            }
+        })
+        .map_err(|e| ClaudeError::HistoryParseError(e.to_string()))?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| ClaudeError::HistoryParseError(format!("{}: {}", dir.display(), e)))?;
+
+        // Ends when the watcher, and with it the sender, is dropped
+        std::thread::spawn(move || {
+            while let Some(ids) = next_batch(&rx, &dir) {
+                emit_changes(&sessions, &store, &events, ids);
+            }
+        });
+
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Wait for a change, then gather the conversations changed within
+/// `DEBOUNCE`; `None` once the watcher is gone
+fn next_batch(rx: &Receiver<PathBuf>, dir: &Path) -> Option<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+    let first = rx.recv().ok()?;
+    ids.extend(conversation_id(&first, dir));
+
+    let deadline = Instant::now() + DEBOUNCE;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(timeout) {
+            Ok(path) => ids.extend(conversation_id(&path, dir)),
+            Err(RecvTimeoutError::Timeout) => return Some(ids),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Id of the conversation a changed path belongs to, if any
+fn conversation_id(path: &Path, dir: &Path) -> Option<String> {
+    if path.parent() != Some(dir) || path.extension().is_none_or(|ext| ext != "jsonl") {
+        return None;
+    }
+    let id = path.file_stem()?.to_str()?;
+    // Subagent transcripts are part of their parent conversation
+    (!id.starts_with("agent-")).then(|| id.to_string())
+}
+
+fn emit_changes(
+    sessions: &SessionManager,
+    store: &ClaudeStore,
+    events: &EventEmitter,
+    ids: BTreeSet<String>,
+) {
+    if ids.is_empty() {
+        return;
+    }
+    let meta = store.conversation_meta().unwrap_or_else(|e| {
+        eprintln!("[Claude] Failed to load conversation metadata: {}", e);
+        Default::default()
+    });
+
+    let mut changes = ConversationsChanged {
+        upserted: Vec::new(),
+        removed: Vec::new(),
+    };
+    for id in ids {
+        // A transcript without messages yet isn't listed either
+        match sessions.get_conversation(&id) {
+            Ok(Some(conversation)) => changes
+                .upserted
+                .push(metadata::merge(conversation, meta.get(&id))),
+            Ok(None) => changes.removed.push(id),
+            Err(e) => eprintln!("[Claude] Failed to read conversation {}: {}", id, e),
+        }
+    }
+    events.emit("claude:conversations-changed", changes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::events::RecordingSink;
+    use super::super::testutil::TempDir;
+
+    #[test]
+    fn test_conversation_id() {
+        let dir = Path::new("/c/projects/-work-app");
+        assert_eq!(conversation_id(&dir.join("abc-1.jsonl"), dir).as_deref(), Some("abc-1"));
+        assert_eq!(conversation_id(&dir.join("agent-1.jsonl"), dir), None);
+        assert_eq!(conversation_id(&dir.join("abc-1.json"), dir), None);
+        assert_eq!(conversation_id(&dir.join("sub/abc-1.jsonl"), dir), None);
+    }
+
+    #[tokio::test]
+    async fn test_watcher_emits_changes() {
+        let claude_dir = TempDir::new("watch");
+        let mut sessions = SessionManager::with_claude_dir(claude_dir.to_path_buf());
+        sessions.set_project_dir("/work/app");
+        let dir = sessions.project_conversations_dir().unwrap();
+        let store = Arc::new(ClaudeStore::open_in_memory().unwrap());
+        store.set_conversation_pinned("abc-1", true).unwrap();
+        let sink = RecordingSink::default();
+
+        let _watcher = ConversationWatcher::start(sessions, store, sink.emitter()).unwrap();
+        let transcript = dir.join("abc-1.jsonl");
+        std::fs::write(
+            &transcript,
+            r#"{"type":"user","uuid":"u1","message":{"role":"user","content":"Started in a terminal"}}"#.to_string() + "\n",
+        )
+        .unwrap();
+
+        let changed = sink
+            .wait_for("claude:conversations-changed", |p| p["upserted"][0]["id"] == "abc-1")
+            .await;
+        assert_eq!(changed["upserted"][0]["title"], "Started in a terminal");
+        assert_eq!(changed["upserted"][0]["pinned"], true);
+
+        std::fs::remove_file(&transcript).unwrap();
+        sink.wait_for("claude:conversations-changed", |p| p["removed"][0] == "abc-1")
+            .await;
+    }
+}
@@ -1,9 +1,18 @@
 mod claude;
 
 use claude::{
-    claude_check_status, claude_get_session_state, claude_list_conversations,
-    claude_send_message, claude_start_session, claude_stop_session,
-    ClaudeManagerState,
+    claude_archive_conversation, claude_cancel_message, claude_check_status, claude_delete_preset,
+    claude_diagnostics, claude_edit_queued_message, claude_export_conversation, claude_fork_session,
+    claude_get_api_settings, claude_get_cli_status, claude_get_conversation_messages,
+    claude_get_export_settings, claude_get_retention_report, claude_get_retention_settings,
+    claude_get_session_state, claude_get_usage, claude_interrupt, claude_list_conversations,
+    claude_list_presets, claude_list_queued_messages, claude_list_sessions, claude_list_trash,
+    claude_pin_conversation, claude_purge_conversation, claude_remove_queued_message,
+    claude_rename_conversation, claude_restore_conversation, claude_run_retention,
+    claude_save_api_settings, claude_save_export_settings, claude_save_preset,
+    claude_save_retention_settings, claude_search_conversations, claude_send_message,
+    claude_set_api_key, claude_set_cli_path, claude_set_conversation_tags, claude_start_session,
+    claude_stop_session, claude_sync_exports, claude_trash_conversation, ClaudeManagerState,
 };
 use tauri::Manager;
 
@@ -21,8 +30,19 @@ pub fn run() {
                 .map(|p| p.to_string_lossy().to_string())
                 .unwrap_or_else(|_| String::from("."));
 
+            // Claude usage shares the app database opened by tauri-plugin-sql
+            let db_path = app.path().app_config_dir()?.join("personal-assistant.db");
+
             // Initialize Claude manager
-            let claude_state = ClaudeManagerState::new(working_dir);
+            let claude_state = ClaudeManagerState::new(working_dir, &db_path);
+            // Keep the conversation list current with sessions run in a terminal
+            if let Err(e) = claude_state.0.watch_conversations(app.handle().clone().into()) {
+                eprintln!("[Claude] Failed to watch conversations: {}", e);
+            }
+            // Archive and delete old conversations without holding up the window
+            let manager = claude_state.0.clone();
+            let events = app.handle().clone().into();
+            tauri::async_runtime::spawn_blocking(move || manager.apply_retention(&events));
             app.manage(claude_state);
 
             Ok(())
@@ -30,10 +50,45 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             claude_check_status,
             claude_start_session,
+            claude_fork_session,
             claude_send_message,
+            claude_list_queued_messages,
+            claude_edit_queued_message,
+            claude_remove_queued_message,
+            claude_interrupt,
+            claude_cancel_message,
             claude_stop_session,
             claude_list_conversations,
+            claude_rename_conversation,
+            claude_pin_conversation,
+            claude_archive_conversation,
+            claude_set_conversation_tags,
+            claude_trash_conversation,
+            claude_restore_conversation,
+            claude_purge_conversation,
+            claude_list_trash,
+            claude_get_conversation_messages,
+            claude_search_conversations,
+            claude_export_conversation,
+            claude_sync_exports,
+            claude_get_export_settings,
+            claude_save_export_settings,
+            claude_get_retention_settings,
+            claude_save_retention_settings,
+            claude_get_retention_report,
+            claude_run_retention,
             claude_get_session_state,
+            claude_list_sessions,
+            claude_get_usage,
+            claude_list_presets,
+            claude_save_preset,
+            claude_delete_preset,
+            claude_diagnostics,
+            claude_get_cli_status,
+            claude_set_cli_path,
+            claude_get_api_settings,
+            claude_save_api_settings,
+            claude_set_api_key,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");